@@ -0,0 +1,208 @@
+use core::fmt;
+use core::marker::PhantomData;
+
+use bits::raw_ids;
+use {AllocPolicy, Id, IdSet, Ids, Key, LowestFree};
+
+/// Allocates ids without storing any values, so that several maps can share one id namespace.
+///
+/// An `IdAllocator` follows the same allocation policy as an `IdMap`, and can be turned into one
+/// with `IdMap::from_allocator`.
+///
+/// # Examples
+///
+/// ```
+/// # use id_map::{IdAllocator, SecondaryIdMap};
+/// #
+/// let mut alloc = IdAllocator::new();
+/// let mut names = SecondaryIdMap::new();
+/// let mut ages = SecondaryIdMap::new();
+///
+/// let alice = alloc.allocate();
+/// names.insert(alice, "alice");
+/// ages.insert(alice, 31);
+///
+/// let block = alloc.reserve_block(3);
+/// assert_eq!(block, 1);
+/// assert_eq!(alloc.next_id(), 4);
+///
+/// alloc.free(alice);
+/// assert!(!alloc.is_allocated(alice));
+/// assert_eq!(alloc.allocate(), alice);
+/// ```
+#[derive(Clone)]
+pub struct IdAllocator<K = Id, A = LowestFree> {
+    // The set of allocated ids.
+    pub(crate) ids: IdSet,
+    // The policy choosing the next id to allocate.
+    pub(crate) policy: A,
+    marker: PhantomData<K>,
+}
+
+impl IdAllocator {
+    #[inline]
+    /// Creates an `IdAllocator` with no ids allocated.
+    pub fn new() -> Self {
+        IdAllocator::with_key()
+    }
+}
+
+impl<A: AllocPolicy> IdAllocator<Id, A> {
+    #[inline]
+    /// Creates an `IdAllocator<Id, A>` with the given allocation policy.
+    pub fn with_policy(policy: A) -> Self {
+        IdAllocator::from_parts(IdSet::new(), policy)
+    }
+}
+
+impl<K: Key, A: AllocPolicy> IdAllocator<K, A> {
+    #[inline]
+    /// Creates an `IdAllocator<K, A>` with a custom key type.
+    pub fn with_key() -> Self {
+        IdAllocator::from_parts(IdSet::new(), A::default())
+    }
+
+    #[inline]
+    pub(crate) fn from_parts(ids: IdSet, policy: A) -> Self {
+        IdAllocator {
+            ids,
+            policy,
+            marker: PhantomData,
+        }
+    }
+
+    #[inline]
+    /// Frees all ids.
+    pub fn clear(&mut self) {
+        self.policy.cleared(&self.ids);
+        self.ids.clear();
+    }
+
+    #[inline]
+    /// Returns the id that a subsequent call to allocate() will produce.
+    ///
+    /// # Panics
+    ///
+    /// Panics if every id up to `K::MAX_ID` is allocated. Use `try_next_id` to check for this
+    /// instead.
+    pub fn next_id(&self) -> K {
+        K::from_id(self.policy.next_id())
+    }
+
+    #[inline]
+    /// Returns the id that a subsequent call to allocate() will produce, or `None` if it does not
+    /// fit in a `K`.
+    pub fn try_next_id(&self) -> Option<K> {
+        K::try_from_id(self.policy.next_id())
+    }
+
+    #[inline]
+    /// Returns the number of allocated ids.
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    #[inline]
+    /// Returns true if no ids are allocated.
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    #[inline]
+    /// Returns a reference to the set of allocated ids.
+    pub fn as_set(&self) -> &IdSet {
+        &self.ids
+    }
+
+    #[inline]
+    /// Allocates an id.
+    pub fn allocate(&mut self) -> K {
+        let id = self.policy.next_id();
+        let key = K::from_id(id);
+        self.ids.insert(id);
+        self.policy.inserted(&self.ids, id);
+        key
+    }
+
+    #[inline]
+    /// Allocates a specific id, returning false if it was already allocated.
+    pub fn allocate_at(&mut self, id: K) -> bool {
+        let id = id.to_id();
+        if self.ids.contains(id) {
+            false
+        } else {
+            self.ids.insert(id);
+            self.policy.inserted(&self.ids, id);
+            true
+        }
+    }
+
+    /// Allocates `n` consecutive ids and returns the first of them.
+    ///
+    /// The block is placed where the allocation policy chooses, which for the default policy is
+    /// the lowest run of `n` free ids.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the block would contain an id greater than `K::MAX_ID`. No ids are allocated in
+    /// that case.
+    pub fn reserve_block(&mut self, n: usize) -> K {
+        let start = self.policy.block_start(&self.ids, n);
+        // Check that the whole block fits in the key type before allocating any of it.
+        let end = match start.checked_add(n) {
+            Some(end) if n == 0 || K::try_from_id(end - 1).is_some() => end,
+            _ => panic!(
+                "a block of {} ids from {} does not fit in the key type",
+                n, start
+            ),
+        };
+        let key = K::from_id(start);
+        for id in start..end {
+            self.ids.insert(id);
+            self.policy.inserted(&self.ids, id);
+        }
+        key
+    }
+
+    #[inline]
+    /// Frees an id, returning true if it was allocated.
+    pub fn free(&mut self, id: K) -> bool {
+        let id = id.to_id();
+        if self.ids.remove(id) {
+            self.policy.removed(id);
+            true
+        } else {
+            false
+        }
+    }
+
+    #[inline]
+    /// Returns true if the id is allocated.
+    pub fn is_allocated(&self, id: K) -> bool {
+        self.ids.contains(id.to_id())
+    }
+
+    #[inline]
+    /// An iterator over allocated ids, in increasing order.
+    pub fn ids(&self) -> Ids<'_, K> {
+        Ids::new(raw_ids(&self.ids))
+    }
+
+    #[cfg(all(test, feature = "std"))]
+    pub(crate) fn assert_invariant(&self) {
+        self.policy.assert_invariant(&self.ids);
+    }
+}
+
+impl<K: Key, A: AllocPolicy> fmt::Debug for IdAllocator<K, A> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.ids()).finish()
+    }
+}
+
+impl<K: Key, A: AllocPolicy> Default for IdAllocator<K, A> {
+    #[inline]
+    fn default() -> Self {
+        IdAllocator::with_key()
+    }
+}
@@ -0,0 +1,309 @@
+//! An append-only map which can be inserted into through a shared reference.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::array;
+use core::cell::{Cell, OnceCell};
+use core::fmt;
+use core::iter::{FromIterator, FusedIterator};
+use core::marker::PhantomData;
+use core::ops::{Index, IndexMut};
+
+use raw::IdState;
+use {Id, IdMap, IdSet, Key, LowestFree};
+
+// The log2 of the size of the first chunk. Each chunk is twice the size of the previous one.
+const FIRST_CHUNK_BITS: u32 = 5;
+// The number of chunks, which is enough to hold any id.
+const CHUNKS: usize = (Id::BITS - FIRST_CHUNK_BITS) as usize;
+
+type Chunk<T> = Box<[OnceCell<T>]>;
+
+/// An append-only map from ids to values, whose values never move once inserted.
+///
+/// Values are stored in chunks which are allocated as needed and never reallocated, so `insert`
+/// only needs a shared reference, and references returned by earlier calls to `insert` and `get`
+/// stay valid. Ids are allocated in increasing order starting from zero. Values cannot be removed,
+/// but the arena can be converted into an `IdMap` once no more references are held.
+///
+/// # Examples
+///
+/// ```
+/// # use id_map::IdArena;
+/// #
+/// let arena = IdArena::new();
+/// let (a, hello) = arena.insert(String::from("hello"));
+/// let (b, world) = arena.insert(String::from("world"));
+///
+/// assert_eq!(format!("{} {}", hello, world), "hello world");
+/// assert_eq!(arena[b], "world");
+///
+/// let map = arena.into_id_map();
+/// assert_eq!(map[a], "hello");
+/// ```
+pub struct IdArena<T, K = Id> {
+    chunks: [OnceCell<Chunk<T>>; CHUNKS],
+    len: Cell<usize>,
+    marker: PhantomData<K>,
+}
+
+impl<T> IdArena<T> {
+    #[inline]
+    /// Creates an empty `IdArena<T>`.
+    pub fn new() -> Self {
+        IdArena::with_key()
+    }
+}
+
+impl<T, K: Key> IdArena<T, K> {
+    #[inline]
+    /// Creates an empty `IdArena<T, K>` with a custom key type.
+    pub fn with_key() -> Self {
+        IdArena {
+            chunks: array::from_fn(|_| OnceCell::new()),
+            len: Cell::new(0),
+            marker: PhantomData,
+        }
+    }
+
+    #[inline]
+    /// Returns the id that a subsequent call to insert() will produce.
+    ///
+    /// # Panics
+    ///
+    /// Panics if every id up to `K::MAX_ID` is in the arena. Use `try_next_id` to check for this
+    /// instead.
+    pub fn next_id(&self) -> K {
+        K::from_id(self.len.get())
+    }
+
+    #[inline]
+    /// Returns the id that a subsequent call to insert() will produce, or `None` if it does not
+    /// fit in a `K`.
+    pub fn try_next_id(&self) -> Option<K> {
+        K::try_from_id(self.len.get())
+    }
+
+    #[inline]
+    /// Returns the number of id-value pairs in the arena.
+    pub fn len(&self) -> usize {
+        self.len.get()
+    }
+
+    #[inline]
+    /// Returns true if the arena contains no values.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Inserts a value, returning its id and a reference to it which lives as long as the arena.
+    pub fn insert(&self, val: T) -> (K, &T) {
+        let id = self.len.get();
+        let key = K::from_id(id);
+        let (chunk, offset) = locate(id);
+        let slot = &self.chunks[chunk].get_or_init(|| new_chunk(chunk))[offset];
+        if slot.set(val).is_err() {
+            unreachable!("slot {} is already filled", id);
+        }
+        self.len.set(id + 1);
+        (key, slot.get().unwrap())
+    }
+
+    #[inline]
+    /// Returns true if the arena contains a value for the specified id.
+    pub fn contains(&self, id: K) -> bool {
+        id.to_id() < self.len()
+    }
+
+    #[inline]
+    /// Returns a reference to the value at the specified id if it is in the arena.
+    pub fn get(&self, id: K) -> Option<&T> {
+        if !self.contains(id) {
+            return None;
+        }
+        let (chunk, offset) = locate(id.to_id());
+        self.chunks[chunk].get()?[offset].get()
+    }
+
+    #[inline]
+    /// Returns a mutable reference to the value at the specified id if it is in the arena.
+    pub fn get_mut(&mut self, id: K) -> Option<&mut T> {
+        if !self.contains(id) {
+            return None;
+        }
+        let (chunk, offset) = locate(id.to_id());
+        self.chunks[chunk].get_mut()?[offset].get_mut()
+    }
+
+    #[inline]
+    /// An iterator over id-value pairs, in order of increasing id.
+    pub fn iter(&self) -> Iter<'_, T, K> {
+        Iter {
+            arena: self,
+            front: 0,
+            back: self.len(),
+        }
+    }
+
+    /// Converts the arena into an `IdMap` with the same ids and values.
+    pub fn into_id_map(self) -> IdMap<T, K> {
+        let len = self.len();
+        let values: Vec<_> = IntoIterator::into_iter(self.chunks)
+            .filter_map(OnceCell::into_inner)
+            .flat_map(|chunk| chunk.into_vec())
+            .map(OnceCell::into_inner)
+            .take(len)
+            .collect();
+        IdMap {
+            state: IdState::new(IdSet::new_filled(len), LowestFree { space: len }, K::MAX_ID),
+            values,
+            marker: PhantomData,
+        }
+    }
+}
+
+/// Returns the chunk and offset within it of an id.
+#[inline]
+fn locate(id: Id) -> (usize, usize) {
+    let n = id + (1 << FIRST_CHUNK_BITS);
+    let bits = Id::BITS - 1 - n.leading_zeros();
+    ((bits - FIRST_CHUNK_BITS) as usize, n - (1 << bits))
+}
+
+/// Allocates an empty chunk.
+fn new_chunk<T>(chunk: usize) -> Chunk<T> {
+    (0..1 << (chunk as u32 + FIRST_CHUNK_BITS))
+        .map(|_| OnceCell::new())
+        .collect()
+}
+
+impl<T: fmt::Debug, K: Key> fmt::Debug for IdArena<T, K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<T, K: Key> Default for IdArena<T, K> {
+    #[inline]
+    fn default() -> Self {
+        IdArena::with_key()
+    }
+}
+
+impl<T, K: Key> Extend<T> for IdArena<T, K> {
+    #[inline]
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for val in iter {
+            self.insert(val);
+        }
+    }
+}
+
+impl<T> FromIterator<T> for IdArena<T> {
+    #[inline]
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut arena = IdArena::new();
+        arena.extend(iter);
+        arena
+    }
+}
+
+impl<'a, T, K: Key> IntoIterator for &'a IdArena<T, K> {
+    type Item = (K, &'a T);
+    type IntoIter = Iter<'a, T, K>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T, K: Key> Index<K> for IdArena<T, K> {
+    type Output = T;
+
+    #[inline]
+    fn index(&self, id: K) -> &Self::Output {
+        match self.get(id) {
+            Some(val) => val,
+            None => panic!("id {} out of bounds", id.to_id()),
+        }
+    }
+}
+
+impl<T, K: Key> IndexMut<K> for IdArena<T, K> {
+    #[inline]
+    fn index_mut(&mut self, id: K) -> &mut Self::Output {
+        match self.get_mut(id) {
+            Some(val) => val,
+            None => panic!("id {} out of bounds", id.to_id()),
+        }
+    }
+}
+
+/// An iterator over id-value pairs, in order of increasing id.
+///
+/// Values inserted while iterating are not visited.
+pub struct Iter<'a, T: 'a, K: 'a = Id> {
+    arena: &'a IdArena<T, K>,
+    // The range of ids which have not been yielded yet.
+    front: Id,
+    back: Id,
+}
+
+impl<'a, T: fmt::Debug + 'a, K: Key> fmt::Debug for Iter<'a, T, K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+impl<'a, T: 'a, K: Key> Iterator for Iter<'a, T, K> {
+    type Item = (K, &'a T);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.front == self.back {
+            return None;
+        }
+        let id = K::from_id(self.front);
+        self.front += 1;
+        Some((id, &self.arena[id]))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.back - self.front;
+        (len, Some(len))
+    }
+}
+
+impl<'a, T: 'a, K: Key> DoubleEndedIterator for Iter<'a, T, K> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.front == self.back {
+            return None;
+        }
+        self.back -= 1;
+        let id = K::from_id(self.back);
+        Some((id, &self.arena[id]))
+    }
+}
+
+impl<'a, T: 'a, K: Key> ExactSizeIterator for Iter<'a, T, K> {
+    #[inline]
+    fn len(&self) -> usize {
+        self.back - self.front
+    }
+}
+
+impl<'a, T: 'a, K: Key> FusedIterator for Iter<'a, T, K> {}
+
+impl<'a, T: 'a, K: 'a> Clone for Iter<'a, T, K> {
+    #[inline]
+    fn clone(&self) -> Self {
+        Iter {
+            arena: self.arena,
+            front: self.front,
+            back: self.back,
+        }
+    }
+}
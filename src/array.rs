@@ -0,0 +1,638 @@
+//! A fixed-capacity map which stores its values inline and never allocates.
+
+use core::iter::{Enumerate, FusedIterator};
+use core::marker::PhantomData;
+use core::ops::{Index, IndexMut};
+use core::{array, cmp, fmt, slice};
+
+#[cfg(feature = "alloc")]
+use IdMap;
+use {CapacityError, Id, Key};
+
+/// A map from ids to values with room for ids `0..N`, stored inline without allocating.
+///
+/// Ids are handed out lowest first, like an `IdMap` with the default policy. Inserting into a full
+/// map, or at an id of `N` or more, returns the value back in a `CapacityError`.
+///
+/// The occupied ids are kept in an inline bitset of `W` words, so finding a vacant id and
+/// iterating skip over empty slots a word at a time. Stable Rust cannot size an array by an
+/// expression of `N`, so `W` is a separate parameter which must be at least `words(N)`. It
+/// defaults to 1, which covers maps of up to 64 values; larger maps spell it out, as in
+/// `ArrayIdMap<T, 100, Id, { words(100) }>`. A `W` which is too small fails to compile.
+///
+/// `ArrayIdMap` is available without the `std` and `alloc` features, so it can be used in `no_std`
+/// crates without an allocator.
+///
+/// # Examples
+///
+/// ```
+/// # use id_map::ArrayIdMap;
+/// #
+/// let mut map: ArrayIdMap<_, 2> = ArrayIdMap::new();
+/// let a = map.insert("a").unwrap();
+/// let b = map.insert("b").unwrap();
+/// assert!(map.is_full());
+///
+/// let err = map.insert("c").unwrap_err();
+/// assert_eq!(err.into_inner(), "c");
+///
+/// map.remove(a);
+/// assert_eq!(map.insert("c"), Ok(a));
+/// assert_eq!(map[b], "b");
+/// ```
+#[derive(Clone)]
+pub struct ArrayIdMap<T, const N: usize, K = Id, const W: usize = 1> {
+    values: [Option<T>; N],
+    // The occupied ids, one bit per slot. Bits at N and above are never set.
+    bits: [u64; W],
+    // The number of occupied slots.
+    len: usize,
+    marker: PhantomData<K>,
+}
+
+/// Returns the number of bitset words an `ArrayIdMap` needs to hold `n` values, for use as its
+/// `W` parameter.
+///
+/// # Examples
+///
+/// ```
+/// # use id_map::{array, ArrayIdMap, Id};
+/// #
+/// let mut map: ArrayIdMap<u8, 100, Id, { array::words(100) }> = ArrayIdMap::new();
+/// for i in 0..100 {
+///     map.insert(i).unwrap();
+/// }
+/// assert!(map.is_full());
+/// ```
+///
+/// A `W` which is too small is rejected when the map is created:
+///
+/// ```compile_fail
+/// # use id_map::ArrayIdMap;
+/// #
+/// let map: ArrayIdMap<u8, 100> = ArrayIdMap::new();
+/// ```
+pub const fn words(n: usize) -> usize {
+    n.div_ceil(BITS)
+}
+
+const BITS: usize = u64::BITS as usize;
+
+impl<T, const N: usize, const W: usize> ArrayIdMap<T, N, Id, W> {
+    #[inline]
+    /// Creates an empty `ArrayIdMap<T, N>`.
+    pub const fn new() -> Self {
+        const { assert!(W >= words(N), "W is too small to hold N ids") };
+        ArrayIdMap {
+            values: [const { None }; N],
+            bits: [0; W],
+            len: 0,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T, const N: usize, K: Key, const W: usize> ArrayIdMap<T, N, K, W> {
+    #[inline]
+    /// Creates an empty `ArrayIdMap<T, N, K>` with a custom key type.
+    pub fn with_key() -> Self {
+        const { assert!(W >= words(N), "W is too small to hold N ids") };
+        ArrayIdMap {
+            values: [const { None }; N],
+            bits: [0; W],
+            len: 0,
+            marker: PhantomData,
+        }
+    }
+
+    #[inline]
+    /// Removes all values from the map.
+    pub fn clear(&mut self) {
+        for id in BitIds::new(&self.bits, self.len) {
+            self.values[id] = None;
+        }
+        self.bits = [0; W];
+        self.len = 0;
+    }
+
+    #[inline]
+    /// Returns the id that a subsequent call to insert() will produce, or `None` if the map is
+    /// full.
+    pub fn next_id(&self) -> Option<K> {
+        let id = self.vacant();
+        if id < N {
+            Some(K::from_id(id))
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    /// Returns the number of id-value pairs in the map.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    /// Returns true if the map contains no values.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    #[inline]
+    /// Returns the number of values the map can hold, which is `N`.
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    #[inline]
+    /// Returns true if every id below `N` is in use.
+    pub fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    /// Inserts a value into an empty slot in the map and returns its id, or returns the value back
+    /// if the map is full.
+    pub fn insert(&mut self, val: T) -> Result<K, CapacityError<T>> {
+        let id = self.vacant();
+        if id == N {
+            return Err(CapacityError::new(val));
+        }
+        let key = K::from_id(id);
+        self.values[id] = Some(val);
+        self.bits[id / BITS] |= 1 << (id % BITS);
+        self.len += 1;
+        Ok(key)
+    }
+
+    /// Inserts a value at a specific id, returning the old value if it existed.
+    ///
+    /// Returns the value back if the id is `N` or more.
+    pub fn insert_at(&mut self, id: K, val: T) -> Result<Option<T>, CapacityError<T>> {
+        let id = id.to_id();
+        if id >= N {
+            return Err(CapacityError::new(val));
+        }
+        let old = self.values[id].replace(val);
+        if old.is_none() {
+            self.bits[id / BITS] |= 1 << (id % BITS);
+            self.len += 1;
+        }
+        Ok(old)
+    }
+
+    #[inline]
+    /// Removes an id from the map, returning its value if it was previously in the map.
+    pub fn remove(&mut self, id: K) -> Option<T> {
+        let id = id.to_id();
+        if !self.contains_id(id) {
+            return None;
+        }
+        self.bits[id / BITS] &= !(1 << (id % BITS));
+        self.len -= 1;
+        self.values[id].take()
+    }
+
+    /// Remove all values not satisfying a predicate.
+    pub fn retain<F: FnMut(K, &T) -> bool>(&mut self, mut pred: F) {
+        for idx in 0..W {
+            let mut word = self.bits[idx];
+            while word != 0 {
+                let bit = word.trailing_zeros() as usize;
+                word &= word - 1;
+
+                let id = idx * BITS + bit;
+                if !pred(K::from_id(id), self.values[id].as_ref().unwrap()) {
+                    self.values[id] = None;
+                    self.bits[idx] &= !(1 << bit);
+                    self.len -= 1;
+                }
+            }
+        }
+    }
+
+    #[inline]
+    /// Returns true if the map contains a value for the specified id.
+    pub fn contains(&self, id: K) -> bool {
+        self.contains_id(id.to_id())
+    }
+
+    #[inline]
+    /// Returns a reference to the value at the specified id if it is in the map.
+    pub fn get(&self, id: K) -> Option<&T> {
+        if self.contains(id) {
+            self.values[id.to_id()].as_ref()
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    /// Returns a mutable reference to the value at the specified id if it is in the map.
+    pub fn get_mut(&mut self, id: K) -> Option<&mut T> {
+        if self.contains(id) {
+            self.values[id.to_id()].as_mut()
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    /// An iterator over ids, in increasing order.
+    pub fn ids(&self) -> Ids<'_, T, K> {
+        Ids { iter: self.iter() }
+    }
+
+    #[inline]
+    /// An iterator over id-value pairs, in order of increasing id.
+    pub fn iter(&self) -> Iter<'_, T, K> {
+        Iter {
+            ids: BitIds::new(&self.bits, self.len),
+            values: &self.values,
+            marker: PhantomData,
+        }
+    }
+
+    #[inline]
+    /// A mutable iterator over id-value pairs, in order of increasing id.
+    pub fn iter_mut(&mut self) -> IterMut<'_, T, K> {
+        IterMut {
+            ids: BitIds::new(&self.bits, self.len),
+            front: 0,
+            back: N,
+            values: self.values.iter_mut(),
+            marker: PhantomData,
+        }
+    }
+
+    #[inline]
+    /// Returns true if the bit for an id is set.
+    fn contains_id(&self, id: Id) -> bool {
+        id < N && self.bits[id / BITS] & (1 << (id % BITS)) != 0
+    }
+
+    /// Returns the smallest vacant id, or `N` if there is none.
+    fn vacant(&self) -> Id {
+        self.bits
+            .iter()
+            .position(|&word| word != !0)
+            .map_or(N, |idx| {
+                let id = idx * BITS + (!self.bits[idx]).trailing_zeros() as usize;
+                cmp::min(id, N)
+            })
+    }
+
+    #[cfg(all(test, feature = "std"))]
+    pub(crate) fn assert_invariant(&self) {
+        assert_eq!(self.len, self.values.iter().filter(|v| v.is_some()).count());
+        for id in 0..W * BITS {
+            let occupied = self.values.get(id).is_some_and(Option::is_some);
+            assert_eq!(self.contains_id(id), occupied);
+        }
+    }
+}
+
+impl<T: fmt::Debug, const N: usize, K: Key, const W: usize> fmt::Debug for ArrayIdMap<T, N, K, W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<T, const N: usize, K: Key, const W: usize> Default for ArrayIdMap<T, N, K, W> {
+    #[inline]
+    fn default() -> Self {
+        ArrayIdMap::with_key()
+    }
+}
+
+impl<T: Eq, const N: usize, K, const W: usize> Eq for ArrayIdMap<T, N, K, W> {}
+
+impl<T: PartialEq, const N: usize, K, const W: usize> PartialEq for ArrayIdMap<T, N, K, W> {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.values == other.values
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T, const N: usize, K: Key, const W: usize> From<ArrayIdMap<T, N, K, W>> for IdMap<T, K> {
+    fn from(map: ArrayIdMap<T, N, K, W>) -> Self {
+        map.into_iter().collect()
+    }
+}
+
+impl<'a, T, const N: usize, K: Key, const W: usize> IntoIterator for &'a ArrayIdMap<T, N, K, W> {
+    type Item = (K, &'a T);
+    type IntoIter = Iter<'a, T, K>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, T, const N: usize, K: Key, const W: usize> IntoIterator
+    for &'a mut ArrayIdMap<T, N, K, W>
+{
+    type Item = (K, &'a mut T);
+    type IntoIter = IterMut<'a, T, K>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+impl<T, const N: usize, K: Key, const W: usize> IntoIterator for ArrayIdMap<T, N, K, W> {
+    type Item = (K, T);
+    type IntoIter = IntoIter<T, N, K>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            iter: IntoIterator::into_iter(self.values).enumerate(),
+            len: self.len,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T, const N: usize, K: Key, const W: usize> Index<K> for ArrayIdMap<T, N, K, W> {
+    type Output = T;
+
+    #[inline]
+    fn index(&self, id: K) -> &Self::Output {
+        match self.get(id) {
+            Some(val) => val,
+            None => panic!("id {} out of bounds", id.to_id()),
+        }
+    }
+}
+
+impl<T, const N: usize, K: Key, const W: usize> IndexMut<K> for ArrayIdMap<T, N, K, W> {
+    #[inline]
+    fn index_mut(&mut self, id: K) -> &mut Self::Output {
+        match self.get_mut(id) {
+            Some(val) => val,
+            None => panic!("id {} out of bounds", id.to_id()),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+/// An iterator over ids, in increasing order.
+pub struct Ids<'a, T: 'a, K = Id> {
+    iter: Iter<'a, T, K>,
+}
+
+impl<'a, T: 'a, K: Key> Iterator for Ids<'a, T, K> {
+    type Item = K;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|(id, _)| id)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<'a, T: 'a, K: Key> DoubleEndedIterator for Ids<'a, T, K> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter.next_back().map(|(id, _)| id)
+    }
+}
+
+impl<'a, T: 'a, K: Key> ExactSizeIterator for Ids<'a, T, K> {
+    #[inline]
+    fn len(&self) -> usize {
+        self.iter.len()
+    }
+}
+
+impl<'a, T: 'a, K: Key> FusedIterator for Ids<'a, T, K> {}
+
+/// A double-ended iterator over the set bits of a slice of words.
+#[derive(Clone, Debug)]
+struct BitIds<'a> {
+    words: &'a [u64],
+    // The range of ids which have not been yielded yet.
+    front: Id,
+    back: Id,
+    // The number of set bits in the range.
+    len: usize,
+}
+
+impl<'a> BitIds<'a> {
+    #[inline]
+    fn new(words: &'a [u64], len: usize) -> Self {
+        BitIds {
+            words,
+            front: 0,
+            back: words.len() * BITS,
+            len,
+        }
+    }
+}
+
+impl<'a> Iterator for BitIds<'a> {
+    type Item = Id;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        // There is a set bit in front..back, so this stops before reaching back.
+        loop {
+            let word = self.words[self.front / BITS] >> (self.front % BITS);
+            if word != 0 {
+                let id = self.front + word.trailing_zeros() as usize;
+                self.front = id + 1;
+                self.len -= 1;
+                return Some(id);
+            }
+            self.front = (self.front / BITS + 1) * BITS;
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a> DoubleEndedIterator for BitIds<'a> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        loop {
+            let last = self.back - 1;
+            let word = self.words[last / BITS] << (BITS - 1 - last % BITS);
+            if word != 0 {
+                let id = last - word.leading_zeros() as usize;
+                self.back = id;
+                self.len -= 1;
+                return Some(id);
+            }
+            self.back = last / BITS * BITS;
+        }
+    }
+}
+
+#[derive(Debug)]
+/// An iterator over id-value pairs, in order of increasing id.
+pub struct Iter<'a, T: 'a, K = Id> {
+    ids: BitIds<'a>,
+    values: &'a [Option<T>],
+    marker: PhantomData<K>,
+}
+
+impl<'a, T: 'a, K: Key> Iterator for Iter<'a, T, K> {
+    type Item = (K, &'a T);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.ids
+            .next()
+            .map(|id| (K::from_id(id), self.values[id].as_ref().unwrap()))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.ids.size_hint()
+    }
+}
+
+impl<'a, T: 'a, K: Key> DoubleEndedIterator for Iter<'a, T, K> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.ids
+            .next_back()
+            .map(|id| (K::from_id(id), self.values[id].as_ref().unwrap()))
+    }
+}
+
+impl<'a, T: 'a, K: Key> ExactSizeIterator for Iter<'a, T, K> {
+    #[inline]
+    fn len(&self) -> usize {
+        self.ids.len
+    }
+}
+
+impl<'a, T: 'a, K: Key> FusedIterator for Iter<'a, T, K> {}
+
+impl<'a, T: 'a, K> Clone for Iter<'a, T, K> {
+    #[inline]
+    fn clone(&self) -> Self {
+        Iter {
+            ids: self.ids.clone(),
+            values: self.values,
+            marker: PhantomData,
+        }
+    }
+}
+
+#[derive(Debug)]
+/// A mutable iterator over id-value pairs, in order of increasing id.
+pub struct IterMut<'a, T: 'a, K = Id> {
+    ids: BitIds<'a>,
+    // The ids of the next slot at either end of values.
+    front: Id,
+    back: Id,
+    values: slice::IterMut<'a, Option<T>>,
+    marker: PhantomData<K>,
+}
+
+impl<'a, T: 'a, K: Key> Iterator for IterMut<'a, T, K> {
+    type Item = (K, &'a mut T);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        let id = self.ids.next()?;
+        let val = self.values.nth(id - self.front).unwrap().as_mut().unwrap();
+        self.front = id + 1;
+        Some((K::from_id(id), val))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.ids.size_hint()
+    }
+}
+
+impl<'a, T: 'a, K: Key> DoubleEndedIterator for IterMut<'a, T, K> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let id = self.ids.next_back()?;
+        let val = self
+            .values
+            .nth_back(self.back - 1 - id)
+            .unwrap()
+            .as_mut()
+            .unwrap();
+        self.back = id;
+        Some((K::from_id(id), val))
+    }
+}
+
+impl<'a, T: 'a, K: Key> ExactSizeIterator for IterMut<'a, T, K> {
+    #[inline]
+    fn len(&self) -> usize {
+        self.ids.len
+    }
+}
+
+impl<'a, T: 'a, K: Key> FusedIterator for IterMut<'a, T, K> {}
+
+#[derive(Clone, Debug)]
+/// A consuming iterator over id-value pairs, in order of increasing id.
+pub struct IntoIter<T, const N: usize, K = Id> {
+    iter: Enumerate<array::IntoIter<Option<T>, N>>,
+    // The number of values not yet yielded.
+    len: usize,
+    marker: PhantomData<K>,
+}
+
+impl<T, const N: usize, K: Key> Iterator for IntoIter<T, N, K> {
+    type Item = (K, T);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        let (id, val) = self.iter.find_map(|(id, slot)| slot.map(|val| (id, val)))?;
+        self.len -= 1;
+        Some((K::from_id(id), val))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<T, const N: usize, K: Key> DoubleEndedIterator for IntoIter<T, N, K> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let (id, val) = self
+            .iter
+            .by_ref()
+            .rev()
+            .find_map(|(id, slot)| slot.map(|val| (id, val)))?;
+        self.len -= 1;
+        Some((K::from_id(id), val))
+    }
+}
+
+impl<T, const N: usize, K: Key> ExactSizeIterator for IntoIter<T, N, K> {
+    #[inline]
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+impl<T, const N: usize, K: Key> FusedIterator for IntoIter<T, N, K> {}
@@ -0,0 +1,194 @@
+//! Iteration over the set bits of a sequence of `IdSet` blocks.
+
+use core::iter::{Cloned, FusedIterator};
+use core::{cmp, slice};
+
+use {Block, Id, IdSet, BITS};
+
+/// A double-ended iterator over the set bits of a sequence of blocks.
+#[derive(Clone, Debug)]
+pub struct BlockIds<B> {
+    // The blocks which have not yet been loaded from either end.
+    blocks: B,
+    // The block being consumed from the front, and the id of the block after it.
+    front_word: Block,
+    front_next: Id,
+    // The block being consumed from the back, and the id of its first bit.
+    back_word: Block,
+    back_base: Id,
+    // The number of set bits remaining.
+    len: usize,
+}
+
+/// Iterator over the ids of a borrowed `IdSet`.
+pub type RawIds<'a> = BlockIds<Cloned<slice::Iter<'a, Block>>>;
+
+#[inline]
+/// Returns an iterator over the ids of a set.
+pub fn raw_ids(set: &IdSet) -> RawIds<'_> {
+    BlockIds::new(set.as_blocks().iter().cloned(), 0, set.len())
+}
+
+/// Returns the smallest id in the set which is at least `from`.
+pub fn next_id(set: &IdSet, from: Id) -> Option<Id> {
+    let blocks = set.as_blocks();
+    let mut idx = from / BITS;
+    if idx >= blocks.len() {
+        return None;
+    }
+
+    let mut word = blocks[idx] & (!0 << (from % BITS));
+    while word == 0 {
+        idx += 1;
+        if idx == blocks.len() {
+            return None;
+        }
+        word = blocks[idx];
+    }
+    Some(idx * BITS + word.trailing_zeros() as usize)
+}
+
+/// Returns an iterator over the ids of a set in the range `start..end`. Only the blocks
+/// overlapping the range are visited.
+pub fn range_ids(set: &IdSet, start: Id, end: Id) -> RawIds<'_> {
+    let blocks = set.as_blocks();
+    let end = cmp::min(end, blocks.len() * BITS);
+    if start >= end {
+        return BlockIds::new([].iter().cloned(), 0, 0);
+    }
+
+    let (first, last) = (start / BITS, (end - 1) / BITS);
+    let front_mask = !0 << (start % BITS);
+    let back_mask = !0 >> (BITS - 1 - (end - 1) % BITS);
+    let (front_word, middle, back_word) = if first == last {
+        (blocks[first] & front_mask & back_mask, &[][..], 0)
+    } else {
+        (
+            blocks[first] & front_mask,
+            &blocks[first + 1..last],
+            blocks[last] & back_mask,
+        )
+    };
+    let len = middle
+        .iter()
+        .chain(&[front_word, back_word])
+        .map(|word| word.count_ones() as usize)
+        .sum();
+
+    BlockIds {
+        blocks: middle.iter().cloned(),
+        front_word,
+        front_next: (first + 1) * BITS,
+        back_word,
+        back_base: last * BITS,
+        len,
+    }
+}
+
+impl<B> BlockIds<B>
+where
+    B: DoubleEndedIterator<Item = Block> + ExactSizeIterator,
+{
+    #[inline]
+    /// Creates an iterator over blocks starting at id `base`, with `len` bits set in total.
+    pub fn new(blocks: B, base: Id, len: usize) -> Self {
+        let back_base = base + blocks.len() * BITS;
+        BlockIds {
+            blocks,
+            front_word: 0,
+            front_next: base,
+            back_word: 0,
+            back_base,
+            len,
+        }
+    }
+}
+
+impl<B> Iterator for BlockIds<B>
+where
+    B: DoubleEndedIterator<Item = Block> + ExactSizeIterator,
+{
+    type Item = Id;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.front_word == 0 {
+            match self.blocks.next() {
+                Some(word) => {
+                    self.front_word = word;
+                    self.front_next += BITS;
+                }
+                // Everything left is in the back block.
+                None => {
+                    if self.back_word == 0 {
+                        return None;
+                    }
+                    self.len -= 1;
+                    return Some(self.back_base + pop_lsb(&mut self.back_word));
+                }
+            }
+        }
+        self.len -= 1;
+        Some(self.front_next - BITS + pop_lsb(&mut self.front_word))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<B> DoubleEndedIterator for BlockIds<B>
+where
+    B: DoubleEndedIterator<Item = Block> + ExactSizeIterator,
+{
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        while self.back_word == 0 {
+            match self.blocks.next_back() {
+                Some(word) => {
+                    self.back_word = word;
+                    self.back_base -= BITS;
+                }
+                // Everything left is in the front block.
+                None => {
+                    if self.front_word == 0 {
+                        return None;
+                    }
+                    self.len -= 1;
+                    return Some(self.front_next - BITS + pop_msb(&mut self.front_word));
+                }
+            }
+        }
+        self.len -= 1;
+        Some(self.back_base + pop_msb(&mut self.back_word))
+    }
+}
+
+impl<B> ExactSizeIterator for BlockIds<B>
+where
+    B: DoubleEndedIterator<Item = Block> + ExactSizeIterator,
+{
+    #[inline]
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+impl<B> FusedIterator for BlockIds<B> where B: DoubleEndedIterator<Item = Block> + ExactSizeIterator {}
+
+/// Remove the least significant bit and return its index.
+#[inline]
+fn pop_lsb(word: &mut Block) -> usize {
+    let idx = word.trailing_zeros() as usize;
+    *word &= *word - 1;
+    idx
+}
+
+/// Remove the most significant bit and return its index.
+#[inline]
+fn pop_msb(word: &mut Block) -> usize {
+    let idx = BITS - 1 - word.leading_zeros() as usize;
+    *word &= !(1 << idx);
+    idx
+}
@@ -0,0 +1,393 @@
+//! A map which can be shared between threads, with its ids spread over independently locked
+//! shards which are read without locking.
+
+// Snapshots are published through `crossbeam_epoch`, which needs `unsafe` to dereference them.
+#![allow(unsafe_code)]
+
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, PoisonError, TryLockError};
+
+use crossbeam_epoch::{self as epoch, Atomic, Guard, Owned};
+#[cfg(loom)]
+use loom::sync::{Mutex, MutexGuard};
+#[cfg(not(loom))]
+use std::sync::{Mutex, MutexGuard};
+
+use {Id, IdMap, Key, PersistentIdMap};
+
+// The number of shards used by `new` and `with_key`.
+const DEFAULT_SHARDS: usize = 16;
+
+/// A map from ids to values which can be read and modified through a shared reference.
+///
+/// The ids are split between a fixed number of shards, with id `i` stored in shard `i % shards`.
+/// Each insert goes to the shard whose lowest free id is lowest, so without contention the map
+/// reuses its lowest free id after removal, just like an `IdMap`. If that shard is locked by
+/// another writer, the insert uses the unlocked shard with the next lowest free id rather than
+/// wait, so ids stay small but concurrent inserts may skip over a free id.
+///
+/// Reads are lock-free. Each shard publishes an immutable `PersistentIdMap` snapshot, which
+/// `get`, `contains` and `len` read without taking any lock, so they never wait for writers or
+/// for each other. Writes to a shard are serialized by a lock, and each one publishes a new
+/// snapshot sharing most of its structure with the previous one. Old snapshots are freed once no
+/// reader can still be looking at them.
+///
+/// Values are stored behind an `Arc`, so the guard returned by `get` keeps its value alive even if
+/// it is removed from the map, and `remove` returns the `Arc` rather than the value.
+///
+/// # Examples
+///
+/// ```
+/// # use id_map::ConcurrentIdMap;
+/// # use std::thread;
+/// #
+/// let map = &ConcurrentIdMap::new();
+/// let ids: Vec<_> = thread::scope(|s| {
+///     let handles: Vec<_> = (0..4).map(|i| s.spawn(move || map.insert(i))).collect();
+///     handles.into_iter().map(|h| h.join().unwrap()).collect()
+/// });
+///
+/// for (i, &id) in ids.iter().enumerate() {
+///     assert_eq!(*map.get(id).unwrap(), i);
+/// }
+/// *map.get_mut(ids[0]).unwrap() += 10;
+/// assert_eq!(map.remove(ids[0]).as_deref(), Some(&10));
+/// assert_eq!(map.len(), 3);
+/// ```
+pub struct ConcurrentIdMap<T, K = Id> {
+    shards: Box<[Shard<T>]>,
+    marker: PhantomData<K>,
+}
+
+impl<T: Send + Sync + 'static> ConcurrentIdMap<T> {
+    #[inline]
+    /// Creates an empty `ConcurrentIdMap<T>`.
+    pub fn new() -> Self {
+        ConcurrentIdMap::with_key()
+    }
+
+    #[inline]
+    /// Creates an empty `ConcurrentIdMap<T>` with the specified number of shards.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is zero.
+    pub fn with_shards(shards: usize) -> Self {
+        ConcurrentIdMap::with_shards_and_key(shards)
+    }
+}
+
+// Old snapshots may be freed later on any thread, so the values must be sendable and outlive the
+// map.
+impl<T: Send + Sync + 'static, K: Key> ConcurrentIdMap<T, K> {
+    #[inline]
+    /// Creates an empty `ConcurrentIdMap<T, K>` with a custom key type.
+    pub fn with_key() -> Self {
+        ConcurrentIdMap::with_shards_and_key(DEFAULT_SHARDS)
+    }
+
+    /// Creates an empty `ConcurrentIdMap<T, K>` with a custom key type and the specified number of
+    /// shards.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is zero.
+    pub fn with_shards_and_key(shards: usize) -> Self {
+        assert!(shards != 0, "a ConcurrentIdMap needs at least one shard");
+        ConcurrentIdMap {
+            shards: (0..shards).map(|_| Shard::new()).collect(),
+            marker: PhantomData,
+        }
+    }
+
+    #[inline]
+    /// Returns the number of shards.
+    pub fn shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Returns the number of id-value pairs in the map.
+    ///
+    /// The shards are counted one at a time, so the result may be out of date if other threads are
+    /// modifying the map.
+    pub fn len(&self) -> usize {
+        let guard = &epoch::pin();
+        self.shards
+            .iter()
+            .map(|shard| shard.load(guard).len())
+            .sum()
+    }
+
+    /// Returns true if the map contains no values.
+    pub fn is_empty(&self) -> bool {
+        let guard = &epoch::pin();
+        self.shards.iter().all(|shard| shard.load(guard).is_empty())
+    }
+
+    /// Inserts a value into an empty slot in the map and returns its id.
+    ///
+    /// This is the lowest free id in the map unless another thread is inserting at the same time.
+    /// If every shard is locked, this waits for the one with the lowest free id. Reads never lock
+    /// a shard, but a `RefMut` does, so a thread holding a `RefMut` for every shard deadlocks here.
+    pub fn insert(&self, val: T) -> K {
+        let count = self.shards.len();
+        let val = Arc::new(val);
+        // The id each shard would allocate, lowest first.
+        let mut next_ids: Vec<(Id, usize)> = {
+            let guard = &epoch::pin();
+            self.shards
+                .iter()
+                .enumerate()
+                .map(|(idx, shard)| {
+                    let id = shard.load(guard).next_id();
+                    (id.saturating_mul(count).saturating_add(idx), idx)
+                })
+                .collect()
+        };
+        next_ids.sort_unstable();
+        // Use the first shard which is not locked, and wait for the lowest one if they all are.
+        for &(_, shard) in &next_ids {
+            let lock = match self.shards[shard].lock.try_lock() {
+                Ok(lock) => lock,
+                Err(TryLockError::Poisoned(err)) => err.into_inner(),
+                Err(TryLockError::WouldBlock) => continue,
+            };
+            return self.insert_into(&lock, shard, val);
+        }
+        let shard = next_ids[0].1;
+        let lock = self.shards[shard].lock();
+        self.insert_into(&lock, shard, val)
+    }
+
+    /// Removes an id from the map, returning its value if it was previously in the map.
+    ///
+    /// Guards returned by `get` may still share the value, so it is returned as an `Arc`.
+    pub fn remove(&self, id: K) -> Option<Arc<T>> {
+        let (shard, id) = self.locate(id);
+        let shard = &self.shards[shard];
+        let lock = shard.lock();
+        let guard = &epoch::pin();
+        let mut map = shard.load(guard).clone();
+        let val = map.remove(id)?;
+        shard.publish(&lock, map, guard);
+        Some(val)
+    }
+
+    /// Returns true if the map contains a value for the specified id.
+    pub fn contains(&self, id: K) -> bool {
+        let (shard, id) = self.locate(id);
+        self.shards[shard].load(&epoch::pin()).contains(id)
+    }
+
+    /// Returns a guard giving shared access to the value at the specified id if it is in the map.
+    ///
+    /// The guard holds no lock, and keeps the value alive if it is removed from the map.
+    pub fn get(&self, id: K) -> Option<Ref<'_, T>> {
+        let (shard, id) = self.locate(id);
+        let val = self.shards[shard].load(&epoch::pin()).get(id)?.clone();
+        Some(Ref {
+            val,
+            marker: PhantomData,
+        })
+    }
+
+    /// Converts the map into an `IdMap` with the same ids and values.
+    ///
+    /// The values may still be shared with guards returned by `get`, or with old snapshots which
+    /// have not been freed yet, so they stay behind their `Arc`.
+    pub fn into_id_map(self) -> IdMap<Arc<T>, K> {
+        let count = self.shards.len();
+        let guard = &epoch::pin();
+        self.shards
+            .iter()
+            .enumerate()
+            .flat_map(|(idx, shard)| {
+                shard
+                    .load(guard)
+                    .iter()
+                    .map(move |(id, val)| (K::from_id(id * count + idx), val.clone()))
+            })
+            .collect()
+    }
+
+    /// Returns the shard holding an id, and the id within that shard.
+    #[inline]
+    fn locate(&self, id: K) -> (usize, Id) {
+        let id = id.to_id();
+        (id % self.shards.len(), id / self.shards.len())
+    }
+
+    fn insert_into(&self, lock: &MutexGuard<'_, ()>, shard: usize, val: Arc<T>) -> K {
+        let guard = &epoch::pin();
+        let mut map = self.shards[shard].load(guard).clone();
+        let id = K::from_id(map.insert(val) * self.shards.len() + shard);
+        self.shards[shard].publish(lock, map, guard);
+        id
+    }
+}
+
+impl<T: Clone + Send + Sync + 'static, K: Key> ConcurrentIdMap<T, K> {
+    /// Returns a guard giving exclusive access to a copy of the value at the specified id if it is
+    /// in the map. The copy replaces the value when the guard is dropped.
+    ///
+    /// The guard locks the shard, so readers see the old value until it is dropped, and any other
+    /// write to the same shard, including from this thread, waits until then.
+    pub fn get_mut(&self, id: K) -> Option<RefMut<'_, T>> {
+        let (shard, id) = self.locate(id);
+        let shard = &self.shards[shard];
+        let lock = shard.lock();
+        let val = T::clone(shard.load(&epoch::pin()).get(id)?);
+        Some(RefMut {
+            shard,
+            lock,
+            id,
+            val: Some(val),
+        })
+    }
+}
+
+impl<T: fmt::Debug + Send + Sync + 'static, K: Key> fmt::Debug for ConcurrentIdMap<T, K> {
+    // Formats the values shard by shard, from the latest snapshot of each.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let count = self.shards.len();
+        let guard = &epoch::pin();
+        let mut entries = f.debug_map();
+        for (idx, shard) in self.shards.iter().enumerate() {
+            for (id, val) in shard.load(guard).iter() {
+                entries.entry(&K::from_id(id * count + idx), val);
+            }
+        }
+        entries.finish()
+    }
+}
+
+impl<T: Send + Sync + 'static, K: Key> Default for ConcurrentIdMap<T, K> {
+    #[inline]
+    fn default() -> Self {
+        ConcurrentIdMap::with_key()
+    }
+}
+
+/// One shard of a `ConcurrentIdMap`: the latest snapshot of its values, and a lock held while
+/// replacing it.
+struct Shard<T> {
+    lock: Mutex<()>,
+    // Never null.
+    map: Atomic<PersistentIdMap<Arc<T>>>,
+}
+
+impl<T> Shard<T> {
+    fn new() -> Self {
+        Shard {
+            lock: Mutex::new(()),
+            map: Atomic::new(PersistentIdMap::new()),
+        }
+    }
+
+    // A panic while the lock is held cannot leave a published snapshot inconsistent, so poisoning
+    // is ignored.
+    fn lock(&self) -> MutexGuard<'_, ()> {
+        self.lock.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Returns the latest snapshot, which stays valid while `guard` is pinned.
+    #[inline]
+    fn load<'g>(&self, guard: &'g Guard) -> &'g PersistentIdMap<Arc<T>> {
+        // SAFETY: the pointer is never null, and a snapshot replaced by `publish` is only freed
+        // once every guard pinned before it was replaced has been dropped.
+        unsafe { self.map.load(Ordering::Acquire, guard).deref() }
+    }
+
+    /// Replaces the latest snapshot. Taking the lock guard ensures the snapshot being replaced
+    /// is the one `map` was cloned from.
+    fn publish(&self, _lock: &MutexGuard<'_, ()>, map: PersistentIdMap<Arc<T>>, guard: &Guard)
+    where
+        T: Send + Sync + 'static,
+    {
+        let old = self.map.swap(Owned::new(map), Ordering::AcqRel, guard);
+        // SAFETY: `old` is no longer reachable from the shard, so only readers pinned before the
+        // swap can still see it.
+        unsafe { guard.defer_destroy(old) };
+    }
+}
+
+impl<T> Drop for Shard<T> {
+    fn drop(&mut self) {
+        // SAFETY: the shard is borrowed mutably, so no reader can still see the latest snapshot.
+        unsafe {
+            drop(
+                self.map
+                    .load(Ordering::Relaxed, epoch::unprotected())
+                    .into_owned(),
+            )
+        };
+    }
+}
+
+/// A guard giving shared access to a value in a `ConcurrentIdMap`.
+///
+/// It holds no lock, and keeps the value alive even if it is removed from the map.
+pub struct Ref<'a, T: 'a> {
+    val: Arc<T>,
+    marker: PhantomData<&'a T>,
+}
+
+impl<'a, T: 'a> Deref for Ref<'a, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        &self.val
+    }
+}
+
+impl<'a, T: fmt::Debug + 'a> fmt::Debug for Ref<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+/// A guard giving exclusive access to a copy of a value in a `ConcurrentIdMap`, which replaces the
+/// value when it is dropped.
+pub struct RefMut<'a, T: Send + Sync + 'static> {
+    shard: &'a Shard<T>,
+    lock: MutexGuard<'a, ()>,
+    id: Id,
+    // Only `None` while being dropped.
+    val: Option<T>,
+}
+
+impl<'a, T: Send + Sync + 'static> Deref for RefMut<'a, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        self.val.as_ref().unwrap()
+    }
+}
+
+impl<'a, T: Send + Sync + 'static> DerefMut for RefMut<'a, T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        self.val.as_mut().unwrap()
+    }
+}
+
+impl<'a, T: Send + Sync + 'static> Drop for RefMut<'a, T> {
+    fn drop(&mut self) {
+        let guard = &epoch::pin();
+        let mut map = self.shard.load(guard).clone();
+        let val = Arc::new(self.val.take().unwrap());
+        map.insert_at(self.id, val);
+        self.shard.publish(&self.lock, map, guard);
+    }
+}
+
+impl<'a, T: fmt::Debug + Send + Sync + 'static> fmt::Debug for RefMut<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
@@ -0,0 +1,1136 @@
+//! A variant of [`IdMap`] which stores its values densely, for fast iteration over sparse maps.
+//!
+//! [`IdMap`]: ../struct.IdMap.html
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::iter::{FromIterator, FusedIterator, Zip};
+use core::marker::PhantomData;
+use core::ops::{Index, IndexMut, RangeBounds};
+use core::slice;
+
+use bits::{self, RawIds};
+use map::id_range;
+use raw::{IdState, RawMap};
+use {
+    AllocPolicy, GetManyError, Id, IdAllocator, IdMap, IdMapDiff, IdRemap, IdSet, Key, LowestFree,
+    TryReserveError,
+};
+
+/// A container that gives each item a unique id, storing the values contiguously with no holes.
+///
+/// Each id maps to a position in a dense vector of values, so iteration is proportional to `len()`
+/// rather than to the largest id, and the values can be borrowed as a slice. Removal swaps the
+/// last value into the removed one's position, so iteration is in no particular order of id.
+///
+/// Methods which visit ids in a range, such as `range` and `drain_range`, use the set of ids and
+/// so yield pairs in order of increasing id, as `IdMap` does.
+///
+/// # Examples
+///
+/// ```
+/// # use id_map::DenseIdMap;
+/// #
+/// let mut map = DenseIdMap::new();
+/// let a = map.insert("a");
+/// let b = map.insert("b");
+/// let c = map.insert("c");
+///
+/// map.remove(a);
+/// assert_eq!(map.as_slice(), &["c", "b"]);
+/// assert_eq!(map[c], "c");
+/// assert_eq!(map.insert("d"), a);
+/// assert_eq!(map.ids().collect::<Vec<_>>(), vec![c, b, a]);
+/// ```
+#[derive(Clone)]
+pub struct DenseIdMap<T, K = Id, A = LowestFree> {
+    // The set of ids with values, the allocation policy and the maximum id.
+    state: IdState<A>,
+    // The position in values of each id in the set. Other entries are meaningless.
+    index: Vec<usize>,
+    // The values, and the id of each.
+    values: Vec<T>,
+    dense_ids: Vec<Id>,
+    marker: PhantomData<K>,
+}
+
+impl<T> DenseIdMap<T> {
+    #[inline]
+    /// Creates an empty `DenseIdMap<T>`.
+    pub fn new() -> Self {
+        DenseIdMap::with_key()
+    }
+
+    #[inline]
+    /// Creates a `DenseIdMap<T>` with the specified capacity.
+    pub fn with_capacity(cap: usize) -> Self {
+        DenseIdMap::with_capacity_and_key(cap)
+    }
+}
+
+impl<T, A: AllocPolicy> DenseIdMap<T, Id, A> {
+    #[inline]
+    /// Creates an empty `DenseIdMap<T, Id, A>` with the given allocation policy.
+    pub fn with_policy(policy: A) -> Self {
+        DenseIdMap {
+            state: IdState::new(IdSet::new(), policy, Id::MAX),
+            index: Vec::new(),
+            values: Vec::new(),
+            dense_ids: Vec::new(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T, K: Key, A: AllocPolicy> DenseIdMap<T, K, A> {
+    /// Creates a map which takes over the ids allocated by `alloc`, calling the closure for the
+    /// value of each id. Subsequent inserts continue with the allocator's policy.
+    pub fn from_allocator<F: FnMut(K) -> T>(alloc: IdAllocator<K, A>, f: F) -> Self {
+        let mut map = DenseIdMap::with_key();
+        map.reset_allocator(alloc, f);
+        map
+    }
+
+    /// Replaces the ids of the map with those allocated by `alloc`, and its allocation policy
+    /// with the allocator's. Values at ids which are not allocated are dropped, and the closure is
+    /// called for the value of each allocated id which has none. The new values are appended in
+    /// order of increasing id.
+    pub fn reset_allocator<F: FnMut(K) -> T>(&mut self, alloc: IdAllocator<K, A>, mut f: F) {
+        let IdAllocator { ids, policy, .. } = alloc;
+        let removed: Vec<_> = self.state.ids.difference(&ids).into_iter().collect();
+        for id in removed {
+            self.swap_remove(self.index[id]);
+        }
+        let added: Vec<_> = ids.difference(&self.state.ids).into_iter().collect();
+        for id in added {
+            self.push(id, f(K::from_id(id)));
+        }
+        self.state.ids = ids;
+        self.state.policy = policy;
+    }
+
+    #[inline]
+    /// Converts the map into an allocator of its ids, dropping the values.
+    pub fn into_allocator(self) -> IdAllocator<K, A> {
+        IdAllocator::from_parts(self.state.ids, self.state.policy)
+    }
+
+    #[inline]
+    /// Creates an empty `DenseIdMap<T, K>` with a custom key type.
+    pub fn with_key() -> Self {
+        DenseIdMap::with_capacity_and_key(0)
+    }
+
+    #[inline]
+    /// Creates a `DenseIdMap<T, K>` with a custom key type and the specified capacity.
+    pub fn with_capacity_and_key(cap: usize) -> Self {
+        DenseIdMap {
+            state: IdState::new(IdSet::new(), A::default(), K::MAX_ID),
+            index: Vec::with_capacity(cap),
+            values: Vec::with_capacity(cap),
+            dense_ids: Vec::with_capacity(cap),
+            marker: PhantomData,
+        }
+    }
+
+    #[inline]
+    /// Removes all values from the map.
+    pub fn clear(&mut self) {
+        self.state.clear();
+        self.values.clear();
+        self.dense_ids.clear();
+    }
+
+    #[inline]
+    /// Returns the id that a subsequent call to insert() will produce.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the map is full, as `IdMap::next_id` does.
+    pub fn next_id(&self) -> K {
+        K::from_id(self.state.next_id())
+    }
+
+    #[inline]
+    /// Returns the id that a subsequent call to insert() will produce, or `None` if it is greater
+    /// than the maximum id of the map.
+    pub fn try_next_id(&self) -> Option<K> {
+        self.state.try_next_id().map(K::from_id)
+    }
+
+    #[inline]
+    /// Returns the largest id which may be inserted into the map.
+    pub fn max_id(&self) -> K {
+        K::from_id(self.state.max_id)
+    }
+
+    #[inline]
+    /// Sets the largest id which may be inserted into the map, which is `K::MAX_ID` by default.
+    ///
+    /// Inserting a larger id makes the `try_` methods return an error, and the other methods
+    /// panic. Ids already in the map are not affected.
+    pub fn set_max_id(&mut self, max_id: K) {
+        self.state.max_id = max_id.to_id();
+    }
+
+    #[inline]
+    /// Returns the number of id-value pairs in the map.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    #[inline]
+    /// Returns true if the map contains no values.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    #[inline]
+    /// Returns the number of id-value pairs the map can hold before reallocating.
+    pub fn capacity(&self) -> usize {
+        self.values.capacity()
+    }
+
+    #[inline]
+    /// Resizes the map such that that `capacity() >= cap`.
+    pub fn reserve(&mut self, cap: usize) {
+        let additional = cap.saturating_sub(self.values.len());
+        self.values.reserve(additional);
+        self.dense_ids.reserve(additional);
+        self.index.reserve(cap.saturating_sub(self.index.len()));
+    }
+
+    #[inline]
+    /// Tries to resize the map such that `capacity() >= cap`, returning an error if the
+    /// allocation fails.
+    pub fn try_reserve(&mut self, cap: usize) -> Result<(), TryReserveError> {
+        let additional = cap.saturating_sub(self.values.len());
+        self.values.try_reserve(additional)?;
+        self.dense_ids.try_reserve(additional)?;
+        self.index
+            .try_reserve(cap.saturating_sub(self.index.len()))?;
+        self.state.ids.try_reserve(cap)
+    }
+
+    #[inline]
+    /// Resizes the map to minimize allocated memory.
+    pub fn shrink_to_fit(&mut self) {
+        let len = self.dense_ids.iter().max().map_or(0, |&id| id + 1);
+        self.index.truncate(len);
+        self.index.shrink_to_fit();
+        self.values.shrink_to_fit();
+        self.dense_ids.shrink_to_fit();
+    }
+
+    /// Moves all values to the ids `0..len()`, keeping the relative order of their ids, and
+    /// returns the mapping from old ids to new ids. The order of `as_slice()` is unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use id_map::DenseIdMap;
+    /// #
+    /// let mut map: DenseIdMap<_> = (0..5).collect();
+    /// map.remove(1);
+    /// map.remove(2);
+    ///
+    /// let remap = map.compact();
+    /// assert_eq!(remap.get(3), Some(1));
+    /// assert_eq!(map[1], 3);
+    /// assert_eq!(map.as_slice(), &[0, 4, 3]);
+    /// assert_eq!(map.next_id(), 3);
+    /// ```
+    pub fn compact(&mut self) -> IdRemap<K> {
+        let index = &mut self.index;
+        let dense_ids = &mut self.dense_ids;
+        let remap = self.state.compact(|old, new| {
+            // Every index entry overwritten so far was for an id before old.
+            let pos = index[old];
+            index[new] = pos;
+            dense_ids[pos] = new;
+        });
+        self.index.truncate(self.values.len());
+        remap
+    }
+
+    #[inline]
+    /// Returns a reference to the set of valid ids.
+    pub fn as_set(&self) -> &IdSet {
+        &self.state.ids
+    }
+
+    #[inline]
+    /// Returns the values as a slice, in the same order as `iter()`.
+    pub fn as_slice(&self) -> &[T] {
+        &self.values
+    }
+
+    #[inline]
+    /// Returns the values as a mutable slice, in the same order as `iter()`.
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        &mut self.values
+    }
+
+    #[inline]
+    /// Inserts a value into an empty slot in the map and returns its id.
+    pub fn insert(&mut self, val: T) -> K {
+        let id = self.state.next_id();
+        self.insert_vacant(id, val);
+        K::from_id(id)
+    }
+
+    #[inline]
+    /// Inserts a value into an empty slot in the map and returns its id, or returns an error if
+    /// the id is too large or the map cannot grow to hold it.
+    pub fn try_insert(&mut self, val: T) -> Result<K, TryReserveError> {
+        let id = self.state.next_id();
+        self.try_grow(id)?;
+        self.insert_vacant(id, val);
+        Ok(K::from_id(id))
+    }
+
+    #[inline]
+    /// Inserts a value at a specific id, returning the old value if it existed.
+    pub fn insert_at(&mut self, id: K, val: T) -> Option<T> {
+        match self.entry(id) {
+            Entry::Occupied(mut entry) => Some(entry.insert(val)),
+            Entry::Vacant(entry) => {
+                entry.insert(val);
+                None
+            }
+        }
+    }
+
+    /// Inserts a value at a specific id, returning the old value if it existed, or returns an
+    /// error if the id is too large or the map cannot grow to hold it.
+    pub fn try_insert_at(&mut self, id: K, val: T) -> Result<Option<T>, TryReserveError> {
+        if !self.contains(id) {
+            self.try_grow(id.to_id())?;
+        }
+        Ok(self.insert_at(id, val))
+    }
+
+    #[inline]
+    /// Removes an id from the map, returning its value if it was previously in the map.
+    ///
+    /// The last value in the map takes the removed value's position.
+    pub fn remove(&mut self, id: K) -> Option<T> {
+        self.remove_id(id.to_id())
+    }
+
+    #[inline]
+    /// If the id has a value, returns it, otherwise inserts a new value.
+    pub fn get_or_insert(&mut self, id: K, val: T) -> &mut T {
+        self.get_or_insert_with(id, || val)
+    }
+
+    #[inline]
+    /// If the id has a value, returns it, otherwise inserts a new value with the provided closure.
+    pub fn get_or_insert_with<F: FnOnce() -> T>(&mut self, id: K, f: F) -> &mut T {
+        self.entry(id).or_insert_with(f)
+    }
+
+    /// If the id has a value, returns it, otherwise inserts a new value with the provided closure,
+    /// or returns an error if the id is too large or the map cannot grow to hold it.
+    pub fn try_get_or_insert_with<F: FnOnce() -> T>(
+        &mut self,
+        id: K,
+        f: F,
+    ) -> Result<&mut T, TryReserveError> {
+        if !self.contains(id) {
+            self.try_grow(id.to_id())?;
+        }
+        Ok(self.get_or_insert_with(id, f))
+    }
+
+    #[inline]
+    /// Returns a vacant entry for the id that a subsequent call to insert() would produce. This
+    /// allows the id to be known before the value is constructed.
+    pub fn vacant_entry(&mut self) -> VacantEntry<'_, T, K, A> {
+        let id = self.state.next_id();
+        VacantEntry::new(self, id)
+    }
+
+    #[inline]
+    /// Gets the entry for the specified id for in-place manipulation.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use id_map::DenseIdMap;
+    /// #
+    /// let mut counts = DenseIdMap::new();
+    /// for &id in &[3, 1, 3] {
+    ///     *counts.entry(id).or_insert(0) += 1;
+    /// }
+    /// assert_eq!(counts.as_slice(), &[2, 1]);
+    /// ```
+    pub fn entry(&mut self, id: K) -> Entry<'_, T, K, A> {
+        let id = id.to_id();
+        if self.state.ids.contains(id) {
+            Entry::Occupied(OccupiedEntry::new(self, id))
+        } else {
+            Entry::Vacant(VacantEntry::new(self, id))
+        }
+    }
+
+    /// Removes all ids in the set from the map.
+    pub fn remove_set(&mut self, set: &IdSet) {
+        let ids: Vec<_> = self.as_set().intersection(set).into_iter().collect();
+        for id in ids {
+            self.remove(K::from_id(id));
+        }
+    }
+
+    /// Applies the differences from another map, as returned by `diff`. Inserted values are
+    /// appended to the map's slice.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the diff inserts an id greater than the maximum id of the map. Use `try_apply`
+    /// for a diff which cannot be trusted.
+    pub fn apply(&mut self, diff: IdMapDiff<T, K>) {
+        diff.apply_to(self);
+    }
+
+    /// Applies the differences from another map, as returned by `diff`, or returns an error
+    /// without changing the map if the diff inserts an id which is too large or the map cannot
+    /// grow to hold it.
+    pub fn try_apply(&mut self, diff: IdMapDiff<T, K>) -> Result<(), TryReserveError> {
+        if let Some(id) = diff.max_new_id(&self.state.ids) {
+            self.try_grow(id)?;
+            self.values.try_reserve(diff.inserted.len())?;
+            self.dense_ids.try_reserve(diff.inserted.len())?;
+        }
+        self.apply(diff);
+        Ok(())
+    }
+
+    /// Remove all values not satisfying the predicate.
+    pub fn retain<F: FnMut(K, &T) -> bool>(&mut self, mut pred: F) {
+        let mut pos = 0;
+        while pos < self.values.len() {
+            let id = self.dense_ids[pos];
+            if pred(K::from_id(id), &self.values[pos]) {
+                pos += 1;
+            } else {
+                self.state.remove(id);
+                self.swap_remove(pos);
+            }
+        }
+    }
+
+    #[inline]
+    /// Removes all id-value pairs from the map, returning them as an iterator in order of
+    /// increasing id. The capacity of the map is unchanged.
+    pub fn drain(&mut self) -> Drain<'_, T, K, A> {
+        Drain::new(self, 0, Id::MAX)
+    }
+
+    #[inline]
+    /// Removes all id-value pairs with ids in the given range from the map, returning them as an
+    /// iterator in order of increasing id.
+    pub fn drain_range<R: RangeBounds<K>>(&mut self, range: R) -> Drain<'_, T, K, A> {
+        let (start, end) = id_range(range);
+        Drain::new(self, start, end)
+    }
+
+    #[inline]
+    /// Returns an iterator which removes and yields the id-value pairs for which the predicate
+    /// returns true, in order of increasing id. Pairs are only visited as the iterator is
+    /// advanced.
+    pub fn extract_if<F: FnMut(K, &mut T) -> bool>(
+        &mut self,
+        pred: F,
+    ) -> ExtractIf<'_, T, F, K, A> {
+        ExtractIf::new(self, pred)
+    }
+
+    #[inline]
+    /// Returns true if the map contains a value for the specified id.
+    pub fn contains(&self, id: K) -> bool {
+        self.state.ids.contains(id.to_id())
+    }
+
+    #[inline]
+    /// Returns a reference to the value at the specified id if it is in the map.
+    pub fn get(&self, id: K) -> Option<&T> {
+        if self.contains(id) {
+            Some(&self.values[self.index[id.to_id()]])
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    /// Returns a mutable reference to the value at the specified id if it is in the map.
+    pub fn get_mut(&mut self, id: K) -> Option<&mut T> {
+        if self.contains(id) {
+            Some(&mut self.values[self.index[id.to_id()]])
+        } else {
+            None
+        }
+    }
+
+    /// Returns mutable references to the values at several distinct ids at once.
+    ///
+    /// Returns an error if any id is not in the map, or if the same id is requested more than
+    /// once.
+    pub fn get_many_mut<const N: usize>(
+        &mut self,
+        ids: [K; N],
+    ) -> Result<[&mut T; N], GetManyError<K>> {
+        let mut order: [usize; N] = core::array::from_fn(|i| i);
+        order.sort_unstable_by_key(|&i| ids[i].to_id());
+        for (n, &i) in order.iter().enumerate() {
+            if !self.contains(ids[i]) {
+                return Err(GetManyError::Missing(ids[i]));
+            }
+            if n > 0 && ids[order[n - 1]].to_id() == ids[i].to_id() {
+                return Err(GetManyError::Duplicate(ids[i]));
+            }
+        }
+
+        // Split the values off in order of position, which is unrelated to the order of ids.
+        let index = &self.index;
+        order.sort_unstable_by_key(|&i| index[ids[i].to_id()]);
+        let mut vals: [Option<&mut T>; N] = core::array::from_fn(|_| None);
+        let mut slots = self.values.iter_mut();
+        let mut front = 0;
+        for &i in &order {
+            let pos = index[ids[i].to_id()];
+            vals[i] = slots.nth(pos - front);
+            front = pos + 1;
+        }
+        Ok(vals.map(Option::unwrap))
+    }
+
+    #[inline]
+    /// Returns mutable references to the values at two distinct ids at once.
+    ///
+    /// Returns an error if either id is not in the map, or if the ids are equal.
+    pub fn get2_mut(&mut self, a: K, b: K) -> Result<(&mut T, &mut T), GetManyError<K>> {
+        let [a, b] = self.get_many_mut([a, b])?;
+        Ok((a, b))
+    }
+
+    #[inline]
+    /// An iterator over ids, in the same order as `as_slice()`.
+    pub fn ids(&self) -> Ids<'_, K> {
+        Ids {
+            iter: self.dense_ids.iter(),
+            marker: PhantomData,
+        }
+    }
+
+    #[inline]
+    /// An iterator over values, in the same order as `as_slice()`.
+    pub fn values(&self) -> slice::Iter<'_, T> {
+        self.values.iter()
+    }
+
+    #[inline]
+    /// A mutable iterator over values, in the same order as `as_slice()`.
+    pub fn values_mut(&mut self) -> slice::IterMut<'_, T> {
+        self.values.iter_mut()
+    }
+
+    #[inline]
+    /// An iterator over id-value pairs, in the same order as `as_slice()`.
+    pub fn iter(&self) -> Iter<'_, T, K> {
+        Iter {
+            iter: self.dense_ids.iter().zip(self.values.iter()),
+            marker: PhantomData,
+        }
+    }
+
+    #[inline]
+    /// A mutable iterator over id-value pairs, in the same order as `as_slice()`.
+    pub fn iter_mut(&mut self) -> IterMut<'_, T, K> {
+        IterMut {
+            iter: self.dense_ids.iter().zip(self.values.iter_mut()),
+            marker: PhantomData,
+        }
+    }
+
+    #[inline]
+    /// An iterator over the ids in the given range, in increasing order.
+    pub fn ids_in<R: RangeBounds<K>>(&self, range: R) -> ::Ids<'_, K> {
+        let (start, end) = id_range(range);
+        ::Ids::new(bits::range_ids(&self.state.ids, start, end))
+    }
+
+    #[inline]
+    /// An iterator over id-value pairs with ids in the given range, in order of increasing id.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use id_map::DenseIdMap;
+    /// #
+    /// let mut map: DenseIdMap<_> = (0..5).collect();
+    /// map.remove(1);
+    /// assert_eq!(map.as_slice(), &[0, 4, 2, 3]);
+    /// let range: Vec<_> = map.range(1..).map(|(id, &val)| (id, val)).collect();
+    /// assert_eq!(range, vec![(2, 2), (3, 3), (4, 4)]);
+    /// ```
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Range<'_, T, K> {
+        let (start, end) = id_range(range);
+        Range {
+            ids: bits::range_ids(&self.state.ids, start, end),
+            index: &self.index,
+            values: &self.values,
+            marker: PhantomData,
+        }
+    }
+
+    /// A mutable iterator over id-value pairs with ids in the given range, in order of increasing
+    /// id.
+    ///
+    /// The references are collected and sorted when the iterator is created, which takes time
+    /// proportional to the number of pairs in the range.
+    pub fn range_mut<R: RangeBounds<K>>(&mut self, range: R) -> RangeMut<'_, T, K> {
+        let (start, end) = id_range(range);
+        let index = &self.index;
+        let mut positions: Vec<_> = bits::range_ids(&self.state.ids, start, end)
+            .map(|id| (id, index[id]))
+            .collect();
+        positions.sort_unstable_by_key(|&(_, pos)| pos);
+
+        let mut slots = self.values.iter_mut();
+        let mut front = 0;
+        let mut pairs: Vec<_> = positions
+            .into_iter()
+            .map(|(id, pos)| {
+                let val = slots.nth(pos - front).unwrap();
+                front = pos + 1;
+                (K::from_id(id), val)
+            })
+            .collect();
+        pairs.sort_unstable_by_key(|&(id, _)| id.to_id());
+        RangeMut {
+            iter: pairs.into_iter(),
+        }
+    }
+
+    #[inline]
+    /// An iterator over id-value pairs with ids greater than or equal to `id`, in order of
+    /// increasing id.
+    pub fn iter_from(&self, id: K) -> Range<'_, T, K> {
+        self.range(id..)
+    }
+
+    #[cfg(all(test, feature = "std"))]
+    pub(crate) fn assert_invariant(&self) {
+        self.state.assert_invariant();
+        assert_eq!(self.state.ids.len(), self.values.len());
+        assert_eq!(self.dense_ids.len(), self.values.len());
+        for (pos, &id) in self.dense_ids.iter().enumerate() {
+            assert!(self.state.ids.contains(id));
+            assert_eq!(self.index[id], pos);
+        }
+    }
+
+    /// Checks that `id` may be inserted and allocates space for it and its value.
+    fn try_grow(&mut self, id: Id) -> Result<(), TryReserveError> {
+        self.state.try_reserve_id(id)?;
+        if self.index.len() <= id {
+            self.index
+                .try_reserve((id - self.index.len()).saturating_add(1))?;
+        }
+        self.values.try_reserve(1)?;
+        self.dense_ids.try_reserve(1)?;
+        Ok(())
+    }
+
+    /// Appends the value of an id which is not in the map, without updating the set of ids.
+    fn push(&mut self, id: Id, val: T) -> &mut T {
+        if self.index.len() <= id {
+            self.index.resize(id + 1, 0);
+        }
+        self.index[id] = self.values.len();
+        self.dense_ids.push(id);
+        self.values.push(val);
+        self.values.last_mut().unwrap()
+    }
+
+    /// Removes the value at a position whose id has already been removed, moving the last value
+    /// into its place.
+    fn swap_remove(&mut self, pos: usize) -> T {
+        self.dense_ids.swap_remove(pos);
+        if let Some(&moved) = self.dense_ids.get(pos) {
+            self.index[moved] = pos;
+        }
+        self.values.swap_remove(pos)
+    }
+}
+
+impl<T, K: Key, A: AllocPolicy> RawMap<T> for DenseIdMap<T, K, A> {
+    #[inline]
+    fn id_set(&self) -> &IdSet {
+        &self.state.ids
+    }
+
+    #[inline]
+    fn value(&self, id: Id) -> &T {
+        &self.values[self.index[id]]
+    }
+
+    #[inline]
+    fn value_mut(&mut self, id: Id) -> &mut T {
+        &mut self.values[self.index[id]]
+    }
+
+    /// Appends the value, after the values already in the map.
+    fn insert_vacant(&mut self, id: Id, val: T) -> &mut T {
+        self.state.insert(id);
+        self.push(id, val)
+    }
+
+    #[inline]
+    fn remove_id(&mut self, id: Id) -> Option<T> {
+        if self.state.remove(id) {
+            Some(self.swap_remove(self.index[id]))
+        } else {
+            None
+        }
+    }
+}
+
+impl<T: Clone, K: Key, A: AllocPolicy> DenseIdMap<T, K, A> {
+    #[inline]
+    /// Returns the differences between this map and `other`, comparing values with `PartialEq`.
+    /// Applying the result to this map makes it equal to `other`.
+    pub fn diff<B: AllocPolicy>(&self, other: &DenseIdMap<T, K, B>) -> IdMapDiff<T, K>
+    where
+        T: PartialEq,
+    {
+        self.diff_by(other, |a, b| a == b)
+    }
+
+    #[inline]
+    /// Returns the differences between this map and `other`, using a closure to decide whether
+    /// two values are equal. Applying the result to this map makes it equal to `other`.
+    pub fn diff_by<B, F>(&self, other: &DenseIdMap<T, K, B>, eq: F) -> IdMapDiff<T, K>
+    where
+        B: AllocPolicy,
+        F: FnMut(&T, &T) -> bool,
+    {
+        IdMapDiff::between(self, other, eq)
+    }
+}
+
+impl<T: fmt::Debug, K: Key, A: AllocPolicy> fmt::Debug for DenseIdMap<T, K, A> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<T, K: Key, A: AllocPolicy> Default for DenseIdMap<T, K, A> {
+    #[inline]
+    fn default() -> Self {
+        DenseIdMap::with_key()
+    }
+}
+
+impl<T: Eq, K: Key, A: AllocPolicy> Eq for DenseIdMap<T, K, A> {}
+
+impl<T: PartialEq, K: Key, A: AllocPolicy> PartialEq for DenseIdMap<T, K, A> {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_set() == other.as_set() && self.iter().all(|(id, val)| other[id] == *val)
+    }
+}
+
+impl<T, K: Key, A: AllocPolicy> Extend<T> for DenseIdMap<T, K, A> {
+    #[inline]
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for val in iter {
+            self.insert(val);
+        }
+    }
+}
+
+impl<T> FromIterator<T> for DenseIdMap<T> {
+    #[inline]
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut map = DenseIdMap::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<T, K: Key, A: AllocPolicy> FromIterator<(K, T)> for DenseIdMap<T, K, A> {
+    #[inline]
+    fn from_iter<I: IntoIterator<Item = (K, T)>>(iter: I) -> Self {
+        let iter = iter.into_iter();
+        let mut map = DenseIdMap::with_capacity_and_key(iter.size_hint().0);
+        for (id, val) in iter {
+            map.insert_at(id, val);
+        }
+        map
+    }
+}
+
+impl<T, K: Key, A: AllocPolicy> From<IdMap<T, K, A>> for DenseIdMap<T, K, A> {
+    /// Converts an `IdMap`, keeping its ids, allocation state and maximum id. The values are stored
+    /// in order of increasing id.
+    fn from(map: IdMap<T, K, A>) -> Self {
+        let IdMap { state, values, .. } = map;
+        let mut index = vec![0; values.len()];
+        let mut dense_ids = Vec::with_capacity(state.ids.len());
+        let mut dense_values = Vec::with_capacity(state.ids.len());
+        for (id, val) in values.into_iter().enumerate() {
+            if let Some(val) = val {
+                index[id] = dense_values.len();
+                dense_ids.push(id);
+                dense_values.push(val);
+            }
+        }
+        DenseIdMap {
+            state,
+            index,
+            values: dense_values,
+            dense_ids,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T, K: Key, A: AllocPolicy> From<DenseIdMap<T, K, A>> for IdMap<T, K, A> {
+    /// Converts a `DenseIdMap`, keeping its ids, allocation state and maximum id.
+    fn from(map: DenseIdMap<T, K, A>) -> Self {
+        let DenseIdMap {
+            state,
+            values,
+            dense_ids,
+            ..
+        } = map;
+        let mut sparse = Vec::new();
+        sparse.resize_with(
+            dense_ids.iter().max().map_or(0, |&id| id + 1),
+            Default::default,
+        );
+        for (id, val) in dense_ids.into_iter().zip(values) {
+            sparse[id] = Some(val);
+        }
+        IdMap {
+            state,
+            values: sparse,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, T, K: Key, A: AllocPolicy> IntoIterator for &'a DenseIdMap<T, K, A> {
+    type Item = (K, &'a T);
+    type IntoIter = Iter<'a, T, K>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, T, K: Key, A: AllocPolicy> IntoIterator for &'a mut DenseIdMap<T, K, A> {
+    type Item = (K, &'a mut T);
+    type IntoIter = IterMut<'a, T, K>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+impl<T, K: Key, A> IntoIterator for DenseIdMap<T, K, A> {
+    type Item = (K, T);
+    type IntoIter = IntoIter<T, K>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            iter: self.dense_ids.into_iter().zip(self.values),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T, K: Key, A: AllocPolicy> Index<K> for DenseIdMap<T, K, A> {
+    type Output = T;
+
+    #[inline]
+    fn index(&self, id: K) -> &Self::Output {
+        assert!(self.contains(id), "id {} out of bounds", id.to_id());
+        &self.values[self.index[id.to_id()]]
+    }
+}
+
+impl<T, K: Key, A: AllocPolicy> IndexMut<K> for DenseIdMap<T, K, A> {
+    #[inline]
+    fn index_mut(&mut self, id: K) -> &mut Self::Output {
+        assert!(self.contains(id), "id {} out of bounds", id.to_id());
+        &mut self.values[self.index[id.to_id()]]
+    }
+}
+
+#[derive(Clone, Debug)]
+/// An iterator over ids, in the order of the map's values.
+pub struct Ids<'a, K = Id> {
+    iter: slice::Iter<'a, Id>,
+    marker: PhantomData<K>,
+}
+
+impl<'a, K: Key> Iterator for Ids<'a, K> {
+    type Item = K;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|&id| K::from_id(id))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<'a, K: Key> DoubleEndedIterator for Ids<'a, K> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter.next_back().map(|&id| K::from_id(id))
+    }
+}
+
+impl<'a, K: Key> ExactSizeIterator for Ids<'a, K> {
+    #[inline]
+    fn len(&self) -> usize {
+        self.iter.len()
+    }
+}
+
+impl<'a, K: Key> FusedIterator for Ids<'a, K> {}
+
+#[derive(Clone, Debug)]
+/// An iterator over id-value pairs, in the order of the map's values.
+pub struct Iter<'a, T: 'a, K = Id> {
+    iter: Zip<slice::Iter<'a, Id>, slice::Iter<'a, T>>,
+    marker: PhantomData<K>,
+}
+
+impl<'a, T: 'a, K: Key> Iterator for Iter<'a, T, K> {
+    type Item = (K, &'a T);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|(&id, val)| (K::from_id(id), val))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<'a, T: 'a, K: Key> DoubleEndedIterator for Iter<'a, T, K> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter
+            .next_back()
+            .map(|(&id, val)| (K::from_id(id), val))
+    }
+}
+
+impl<'a, T: 'a, K: Key> ExactSizeIterator for Iter<'a, T, K> {
+    #[inline]
+    fn len(&self) -> usize {
+        self.iter.len()
+    }
+}
+
+impl<'a, T: 'a, K: Key> FusedIterator for Iter<'a, T, K> {}
+
+#[derive(Debug)]
+/// A mutable iterator over id-value pairs, in the order of the map's values.
+pub struct IterMut<'a, T: 'a, K = Id> {
+    iter: Zip<slice::Iter<'a, Id>, slice::IterMut<'a, T>>,
+    marker: PhantomData<K>,
+}
+
+impl<'a, T: 'a, K: Key> Iterator for IterMut<'a, T, K> {
+    type Item = (K, &'a mut T);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|(&id, val)| (K::from_id(id), val))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<'a, T: 'a, K: Key> DoubleEndedIterator for IterMut<'a, T, K> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter
+            .next_back()
+            .map(|(&id, val)| (K::from_id(id), val))
+    }
+}
+
+impl<'a, T: 'a, K: Key> ExactSizeIterator for IterMut<'a, T, K> {
+    #[inline]
+    fn len(&self) -> usize {
+        self.iter.len()
+    }
+}
+
+impl<'a, T: 'a, K: Key> FusedIterator for IterMut<'a, T, K> {}
+
+#[derive(Clone, Debug)]
+/// A consuming iterator over id-value pairs, in the order of the map's values.
+pub struct IntoIter<T, K = Id> {
+    iter: Zip<vec::IntoIter<Id>, vec::IntoIter<T>>,
+    marker: PhantomData<K>,
+}
+
+impl<T, K: Key> Iterator for IntoIter<T, K> {
+    type Item = (K, T);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|(id, val)| (K::from_id(id), val))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<T, K: Key> DoubleEndedIterator for IntoIter<T, K> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter.next_back().map(|(id, val)| (K::from_id(id), val))
+    }
+}
+
+impl<T, K: Key> ExactSizeIterator for IntoIter<T, K> {
+    #[inline]
+    fn len(&self) -> usize {
+        self.iter.len()
+    }
+}
+
+impl<T, K: Key> FusedIterator for IntoIter<T, K> {}
+
+#[derive(Debug)]
+/// An iterator over id-value pairs in a range of ids, in order of increasing id.
+pub struct Range<'a, T: 'a, K = Id> {
+    ids: RawIds<'a>,
+    index: &'a [usize],
+    values: &'a [T],
+    marker: PhantomData<K>,
+}
+
+impl<'a, T: 'a, K: Key> Iterator for Range<'a, T, K> {
+    type Item = (K, &'a T);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.ids
+            .next()
+            .map(|id| (K::from_id(id), &self.values[self.index[id]]))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.ids.size_hint()
+    }
+}
+
+impl<'a, T: 'a, K: Key> DoubleEndedIterator for Range<'a, T, K> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.ids
+            .next_back()
+            .map(|id| (K::from_id(id), &self.values[self.index[id]]))
+    }
+}
+
+impl<'a, T: 'a, K: Key> ExactSizeIterator for Range<'a, T, K> {
+    #[inline]
+    fn len(&self) -> usize {
+        self.ids.len()
+    }
+}
+
+impl<'a, T: 'a, K: Key> FusedIterator for Range<'a, T, K> {}
+
+impl<'a, T: 'a, K> Clone for Range<'a, T, K> {
+    #[inline]
+    fn clone(&self) -> Self {
+        Range {
+            ids: self.ids.clone(),
+            index: self.index,
+            values: self.values,
+            marker: PhantomData,
+        }
+    }
+}
+
+#[derive(Debug)]
+/// A mutable iterator over id-value pairs in a range of ids, in order of increasing id.
+pub struct RangeMut<'a, T: 'a, K = Id> {
+    iter: vec::IntoIter<(K, &'a mut T)>,
+}
+
+impl<'a, T: 'a, K: Key> Iterator for RangeMut<'a, T, K> {
+    type Item = (K, &'a mut T);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next()
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<'a, T: 'a, K: Key> DoubleEndedIterator for RangeMut<'a, T, K> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter.next_back()
+    }
+}
+
+impl<'a, T: 'a, K: Key> ExactSizeIterator for RangeMut<'a, T, K> {
+    #[inline]
+    fn len(&self) -> usize {
+        self.iter.len()
+    }
+}
+
+impl<'a, T: 'a, K: Key> FusedIterator for RangeMut<'a, T, K> {}
+
+/// A draining iterator over the id-value pairs of a `DenseIdMap`, in order of increasing id.
+pub type Drain<'a, T, K = Id, A = LowestFree> = ::Drain<'a, T, K, A, DenseIdMap<T, K, A>>;
+
+/// An iterator which removes and yields the id-value pairs of a `DenseIdMap` satisfying a
+/// predicate, in order of increasing id.
+pub type ExtractIf<'a, T, F, K = Id, A = LowestFree> =
+    ::ExtractIf<'a, T, F, K, A, DenseIdMap<T, K, A>>;
+
+/// A view into a single id in a `DenseIdMap`, which may either be vacant or occupied.
+pub type Entry<'a, T, K = Id, A = LowestFree> = ::Entry<'a, T, K, A, DenseIdMap<T, K, A>>;
+
+/// A view into an id with a value in a `DenseIdMap`.
+pub type OccupiedEntry<'a, T, K = Id, A = LowestFree> =
+    ::OccupiedEntry<'a, T, K, A, DenseIdMap<T, K, A>>;
+
+/// A view into an id without a value in a `DenseIdMap`.
+pub type VacantEntry<'a, T, K = Id, A = LowestFree> =
+    ::VacantEntry<'a, T, K, A, DenseIdMap<T, K, A>>;
@@ -0,0 +1,113 @@
+use alloc::vec::Vec;
+
+use raw::RawMap;
+use {Id, IdSet, Key};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// The differences between two maps, which turn the first into the second when applied to it.
+///
+/// Returned by `IdMap::diff`, and applied with `IdMap::apply`, or `IdMap::try_apply` if it comes
+/// from an untrusted source. `DenseIdMap` has the same methods. Each list is in order of increasing id. With the `serde` feature, it
+/// can be serialized to send to a replica of the map.
+///
+/// # Examples
+///
+/// ```
+/// # use id_map::IdMap;
+/// #
+/// let old: IdMap<_> = vec!["a", "b", "c"].into_iter().collect();
+/// let mut new = old.clone();
+/// new.remove(0);
+/// new[1] = "B";
+/// new.insert_at(5, "f");
+///
+/// let diff = old.diff(&new);
+/// assert_eq!(diff.inserted, [(5, "f")]);
+/// assert_eq!(diff.removed, [0]);
+/// assert_eq!(diff.changed, [(1, "B")]);
+///
+/// let mut replica = old.clone();
+/// replica.apply(diff);
+/// assert_eq!(replica, new);
+/// ```
+pub struct IdMapDiff<T, K = Id> {
+    /// Ids which are only in the second map, with their values.
+    pub inserted: Vec<(K, T)>,
+    /// Ids which are only in the first map.
+    pub removed: Vec<K>,
+    /// Ids which are in both maps with different values, with their values in the second map.
+    pub changed: Vec<(K, T)>,
+}
+
+impl<T, K> IdMapDiff<T, K> {
+    #[inline]
+    /// Returns true if the two maps had the same ids and values.
+    pub fn is_empty(&self) -> bool {
+        self.inserted.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl<T, K: Key> IdMapDiff<T, K> {
+    /// Returns the differences between two maps, using a closure to decide whether two values are
+    /// equal.
+    pub(crate) fn between<M, N, F>(old: &M, new: &N, mut eq: F) -> Self
+    where
+        T: Clone,
+        M: RawMap<T>,
+        N: RawMap<T>,
+        F: FnMut(&T, &T) -> bool,
+    {
+        let entry = |id: Id| (K::from_id(id), new.value(id).clone());
+        let (old_ids, new_ids) = (old.id_set(), new.id_set());
+        IdMapDiff {
+            inserted: new_ids.difference(old_ids).into_iter().map(entry).collect(),
+            removed: old_ids
+                .difference(new_ids)
+                .into_iter()
+                .map(K::from_id)
+                .collect(),
+            changed: old_ids
+                .intersection(new_ids)
+                .into_iter()
+                .filter(|&id| !eq(old.value(id), new.value(id)))
+                .map(entry)
+                .collect(),
+        }
+    }
+
+    /// Returns the largest id inserted by the diff which is not in `ids`.
+    pub(crate) fn max_new_id(&self, ids: &IdSet) -> Option<Id> {
+        self.inserted
+            .iter()
+            .chain(&self.changed)
+            .map(|&(id, _)| id.to_id())
+            .filter(|&id| !ids.contains(id))
+            .max()
+    }
+
+    /// Applies the diff to a map.
+    pub(crate) fn apply_to<M: RawMap<T>>(self, map: &mut M) {
+        for id in self.removed {
+            map.remove_id(id.to_id());
+        }
+        for (id, val) in self.inserted.into_iter().chain(self.changed) {
+            let id = id.to_id();
+            if map.id_set().contains(id) {
+                *map.value_mut(id) = val;
+            } else {
+                map.insert_vacant(id, val);
+            }
+        }
+    }
+}
+
+impl<T, K> Default for IdMapDiff<T, K> {
+    #[inline]
+    fn default() -> Self {
+        IdMapDiff {
+            inserted: Vec::new(),
+            removed: Vec::new(),
+            changed: Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use core::fmt;
+use core::iter::FusedIterator;
+use core::marker::PhantomData;
+
+use bits;
+use raw::{MapMarker, RawMap};
+use {AllocPolicy, Id, IdMap, Key, LowestFree};
+
+/// A draining iterator over id-value pairs, in order of increasing id.
+///
+/// Each pair is removed from the map as it is yielded, and any pairs left when the iterator is
+/// dropped are removed then. If the iterator is leaked, only the yielded pairs are removed.
+///
+/// The last parameter is the type of the map, which is an `IdMap` unless this is a
+/// `dense::Drain`.
+pub struct Drain<
+    'a,
+    T: 'a,
+    K: Key + 'a = Id,
+    A: AllocPolicy + 'a = LowestFree,
+    M: RawMap<T> + 'a = IdMap<T, K, A>,
+> {
+    map: &'a mut M,
+    // The range of ids which have not been yielded yet.
+    next: Id,
+    end: Id,
+    len: usize,
+    marker: MapMarker<T, K, A>,
+}
+
+impl<'a, T: 'a, K: Key, A: AllocPolicy, M: RawMap<T>> Drain<'a, T, K, A, M> {
+    #[inline]
+    pub(crate) fn new(map: &'a mut M, start: Id, end: Id) -> Self {
+        let len = bits::range_ids(map.id_set(), start, end).len();
+        Drain {
+            map,
+            next: start,
+            end,
+            len,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, T: 'a, K: Key, A: AllocPolicy, M: RawMap<T>> Iterator for Drain<'a, T, K, A, M> {
+    type Item = (K, T);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        let id = bits::next_id(self.map.id_set(), self.next).filter(|&id| id < self.end)?;
+        self.next = id + 1;
+        self.len -= 1;
+
+        Some((K::from_id(id), self.map.remove_id(id).unwrap()))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, T: 'a, K: Key, A: AllocPolicy, M: RawMap<T>> ExactSizeIterator for Drain<'a, T, K, A, M> {
+    #[inline]
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+impl<'a, T: 'a, K: Key, A: AllocPolicy, M: RawMap<T>> FusedIterator for Drain<'a, T, K, A, M> {}
+
+impl<'a, T: 'a, K: Key, A: AllocPolicy, M: RawMap<T>> Drop for Drain<'a, T, K, A, M> {
+    fn drop(&mut self) {
+        self.for_each(drop);
+    }
+}
+
+impl<'a, T: fmt::Debug + 'a, K: Key, A: AllocPolicy, M: RawMap<T>> fmt::Debug
+    for Drain<'a, T, K, A, M>
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list()
+            .entries(entries::<_, K, _>(self.map, self.next, self.end))
+            .finish()
+    }
+}
+
+/// An iterator which removes and yields the id-value pairs satisfying a predicate, in order of
+/// increasing id.
+///
+/// Pairs which have not been visited when the iterator is dropped are kept in the map.
+///
+/// The last parameter is the type of the map, which is an `IdMap` unless this is a
+/// `dense::ExtractIf`.
+pub struct ExtractIf<'a, T: 'a, F, K: 'a = Id, A: 'a = LowestFree, M: 'a = IdMap<T, K, A>> {
+    map: &'a mut M,
+    // The smallest id which has not been visited yet.
+    next: Id,
+    pred: F,
+    marker: MapMarker<T, K, A>,
+}
+
+impl<'a, T: 'a, F, K: Key, A: AllocPolicy, M: RawMap<T>> ExtractIf<'a, T, F, K, A, M> {
+    #[inline]
+    pub(crate) fn new(map: &'a mut M, pred: F) -> Self {
+        ExtractIf {
+            map,
+            next: 0,
+            pred,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, T: 'a, F, K: Key, A: AllocPolicy, M: RawMap<T>> Iterator for ExtractIf<'a, T, F, K, A, M>
+where
+    F: FnMut(K, &mut T) -> bool,
+{
+    type Item = (K, T);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(id) = bits::next_id(self.map.id_set(), self.next) {
+            self.next = id + 1;
+
+            if (self.pred)(K::from_id(id), self.map.value_mut(id)) {
+                return Some((K::from_id(id), self.map.remove_id(id).unwrap()));
+            }
+        }
+        None
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.map.id_set().len()))
+    }
+}
+
+impl<'a, T: 'a, F, K: Key, A: AllocPolicy, M: RawMap<T>> FusedIterator
+    for ExtractIf<'a, T, F, K, A, M>
+where
+    F: FnMut(K, &mut T) -> bool,
+{
+}
+
+impl<'a, T: fmt::Debug + 'a, F, K: Key, A: AllocPolicy, M: RawMap<T>> fmt::Debug
+    for ExtractIf<'a, T, F, K, A, M>
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list()
+            .entries(entries::<_, K, _>(self.map, self.next, Id::MAX))
+            .finish()
+    }
+}
+
+/// The id-value pairs of a map in the range `start..end`.
+fn entries<'a, T: 'a, K: Key, M: RawMap<T>>(
+    map: &'a M,
+    start: Id,
+    end: Id,
+) -> impl Iterator<Item = (K, &'a T)> {
+    bits::range_ids(map.id_set(), start, end).map(move |id| (K::from_id(id), map.value(id)))
+}
@@ -0,0 +1,210 @@
+use core::marker::PhantomData;
+use core::{fmt, mem};
+
+use raw::{MapMarker, RawMap};
+use {AllocPolicy, Id, IdMap, Key, LowestFree};
+
+/// A view into a single id in a map, which may either be vacant or occupied.
+///
+/// The last parameter is the type of the map, which is an `IdMap` unless this is a
+/// `dense::Entry`.
+pub enum Entry<'a, T: 'a, K: 'a = Id, A: 'a = LowestFree, M: 'a = IdMap<T, K, A>> {
+    /// An id with a value.
+    Occupied(OccupiedEntry<'a, T, K, A, M>),
+    /// An id without a value.
+    Vacant(VacantEntry<'a, T, K, A, M>),
+}
+
+impl<'a, T: 'a, K: Key, A: AllocPolicy, M: RawMap<T>> Entry<'a, T, K, A, M> {
+    #[inline]
+    /// Returns the id of this entry.
+    pub fn id(&self) -> K {
+        match *self {
+            Entry::Occupied(ref entry) => entry.id(),
+            Entry::Vacant(ref entry) => entry.id(),
+        }
+    }
+
+    #[inline]
+    /// Inserts a value if the entry is vacant, and returns a mutable reference to the value.
+    pub fn or_insert(self, val: T) -> &'a mut T {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(val),
+        }
+    }
+
+    #[inline]
+    /// Inserts the result of the closure if the entry is vacant, and returns a mutable reference
+    /// to the value.
+    pub fn or_insert_with<F: FnOnce() -> T>(self, f: F) -> &'a mut T {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(f()),
+        }
+    }
+
+    #[inline]
+    /// Inserts the result of calling the closure with the entry's id if the entry is vacant, and
+    /// returns a mutable reference to the value.
+    pub fn or_insert_with_key<F: FnOnce(K) -> T>(self, f: F) -> &'a mut T {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let val = f(entry.id());
+                entry.insert(val)
+            }
+        }
+    }
+
+    #[inline]
+    /// Calls the closure on the value if the entry is occupied.
+    pub fn and_modify<F: FnOnce(&mut T)>(self, f: F) -> Self {
+        match self {
+            Entry::Occupied(mut entry) => {
+                f(entry.get_mut());
+                Entry::Occupied(entry)
+            }
+            Entry::Vacant(entry) => Entry::Vacant(entry),
+        }
+    }
+}
+
+impl<'a, T: fmt::Debug + 'a, K: Key, A: AllocPolicy, M: RawMap<T>> fmt::Debug
+    for Entry<'a, T, K, A, M>
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Entry::Occupied(ref entry) => f.debug_tuple("Entry").field(entry).finish(),
+            Entry::Vacant(ref entry) => f.debug_tuple("Entry").field(entry).finish(),
+        }
+    }
+}
+
+impl<'a, T: Default + 'a, K: Key, A: AllocPolicy, M: RawMap<T>> Entry<'a, T, K, A, M> {
+    #[inline]
+    /// Inserts the default value if the entry is vacant, and returns a mutable reference to the
+    /// value.
+    pub fn or_default(self) -> &'a mut T {
+        self.or_insert_with(T::default)
+    }
+}
+
+/// A view into an id with a value.
+pub struct OccupiedEntry<'a, T: 'a, K: 'a = Id, A: 'a = LowestFree, M: 'a = IdMap<T, K, A>> {
+    map: &'a mut M,
+    id: Id,
+    marker: MapMarker<T, K, A>,
+}
+
+impl<'a, T: 'a, K: Key, A: AllocPolicy, M: RawMap<T>> OccupiedEntry<'a, T, K, A, M> {
+    #[inline]
+    pub(crate) fn new(map: &'a mut M, id: Id) -> Self {
+        OccupiedEntry {
+            map,
+            id,
+            marker: PhantomData,
+        }
+    }
+
+    #[inline]
+    /// Returns the id of this entry.
+    pub fn id(&self) -> K {
+        K::from_id(self.id)
+    }
+
+    #[inline]
+    /// Returns a reference to the value.
+    pub fn get(&self) -> &T {
+        self.map.value(self.id)
+    }
+
+    #[inline]
+    /// Returns a mutable reference to the value.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.map.value_mut(self.id)
+    }
+
+    #[inline]
+    /// Converts the entry into a mutable reference to the value, with the lifetime of the map.
+    pub fn into_mut(self) -> &'a mut T {
+        self.map.value_mut(self.id)
+    }
+
+    #[inline]
+    /// Replaces the value, returning the old value.
+    pub fn insert(&mut self, val: T) -> T {
+        mem::replace(self.get_mut(), val)
+    }
+
+    #[inline]
+    /// Removes the value from the map and returns it.
+    ///
+    /// In a `DenseIdMap`, the last value in the map takes its position.
+    pub fn remove(self) -> T {
+        self.remove_entry().1
+    }
+
+    #[inline]
+    /// Removes the value from the map and returns it along with its id.
+    pub fn remove_entry(self) -> (K, T) {
+        (self.id(), self.map.remove_id(self.id).unwrap())
+    }
+}
+
+impl<'a, T: fmt::Debug + 'a, K: Key, A: AllocPolicy, M: RawMap<T>> fmt::Debug
+    for OccupiedEntry<'a, T, K, A, M>
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("OccupiedEntry")
+            .field("id", &self.id())
+            .field("value", self.get())
+            .finish()
+    }
+}
+
+/// A view into an id without a value.
+pub struct VacantEntry<'a, T: 'a, K: 'a = Id, A: 'a = LowestFree, M: 'a = IdMap<T, K, A>> {
+    map: &'a mut M,
+    id: Id,
+    marker: MapMarker<T, K, A>,
+}
+
+impl<'a, T: 'a, K: Key, A: AllocPolicy, M: RawMap<T>> VacantEntry<'a, T, K, A, M> {
+    #[inline]
+    pub(crate) fn new(map: &'a mut M, id: Id) -> Self {
+        VacantEntry {
+            map,
+            id,
+            marker: PhantomData,
+        }
+    }
+
+    #[inline]
+    /// Returns the id of this entry.
+    pub fn id(&self) -> K {
+        K::from_id(self.id)
+    }
+
+    #[inline]
+    /// Inserts a value at this entry's id, and returns a mutable reference to it.
+    ///
+    /// In a `DenseIdMap`, the value is appended to the end of the map's slice.
+    pub fn insert(self, val: T) -> &'a mut T {
+        self.map.insert_vacant(self.id, val)
+    }
+
+    #[inline]
+    /// Inserts the result of calling the closure with this entry's id, and returns a mutable
+    /// reference to it.
+    pub fn insert_with<F: FnOnce(K) -> T>(self, f: F) -> &'a mut T {
+        let val = f(self.id());
+        self.insert(val)
+    }
+}
+
+impl<'a, T: 'a, K: Key, A: AllocPolicy, M: RawMap<T>> fmt::Debug for VacantEntry<'a, T, K, A, M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("VacantEntry").field(&self.id()).finish()
+    }
+}
@@ -0,0 +1,108 @@
+//! Error types returned by fallible map operations.
+
+#[cfg(feature = "alloc")]
+use alloc::collections;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::error::Error;
+
+use Id;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+/// The error returned by `get_many_mut` when the requested values cannot be borrowed together.
+pub enum GetManyError<K = Id> {
+    /// The id is not in the map.
+    Missing(K),
+    /// The id was requested more than once.
+    Duplicate(K),
+}
+
+impl<K: fmt::Debug> fmt::Display for GetManyError<K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GetManyError::Missing(ref id) => write!(f, "id {:?} is not in the map", id),
+            GetManyError::Duplicate(ref id) => {
+                write!(f, "id {:?} was requested more than once", id)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K: fmt::Debug> Error for GetManyError<K> {}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+/// The error returned when inserting into a fixed-capacity map which has no room for the value.
+///
+/// The rejected value can be recovered with `into_inner`.
+pub struct CapacityError<T> {
+    val: T,
+}
+
+impl<T> CapacityError<T> {
+    #[inline]
+    pub(crate) fn new(val: T) -> Self {
+        CapacityError { val }
+    }
+
+    #[inline]
+    /// Returns the value which could not be inserted.
+    pub fn into_inner(self) -> T {
+        self.val
+    }
+}
+
+impl<T> fmt::Debug for CapacityError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("CapacityError")
+    }
+}
+
+impl<T> fmt::Display for CapacityError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("the map is full")
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T> Error for CapacityError<T> {}
+
+#[cfg(feature = "alloc")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// The error returned by the fallible insertion and reservation methods of `IdMap`.
+pub enum TryReserveError {
+    /// The id is greater than the map's maximum id.
+    IdTooLarge(Id),
+    /// The allocator failed, or the required capacity overflowed.
+    Alloc(collections::TryReserveError),
+}
+
+#[cfg(feature = "alloc")]
+impl From<collections::TryReserveError> for TryReserveError {
+    #[inline]
+    fn from(err: collections::TryReserveError) -> Self {
+        TryReserveError::Alloc(err)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl fmt::Display for TryReserveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TryReserveError::IdTooLarge(id) => {
+                write!(f, "id {} is greater than the maximum id of the map", id)
+            }
+            TryReserveError::Alloc(ref err) => err.fmt(f),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for TryReserveError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            TryReserveError::IdTooLarge(_) => None,
+            TryReserveError::Alloc(ref err) => Some(err),
+        }
+    }
+}
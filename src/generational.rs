@@ -0,0 +1,510 @@
+//! A variant of [`IdMap`] whose ids are invalidated when their value is removed.
+//!
+//! [`IdMap`]: ../struct.IdMap.html
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::iter::{FromIterator, FusedIterator};
+use core::ops::{Index, IndexMut};
+
+use {GetManyError, IdMap, Values, ValuesMut};
+
+/// The type of the counter stored alongside each slot.
+pub type Generation = u32;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// An id into a `GenIdMap`, made up of a slot index and the generation of that slot at the time
+/// the value was inserted.
+pub struct GenId {
+    index: ::Id,
+    generation: Generation,
+}
+
+impl GenId {
+    #[inline]
+    /// Creates an id from its raw parts.
+    pub fn new(index: ::Id, generation: Generation) -> Self {
+        GenId { index, generation }
+    }
+
+    #[inline]
+    /// Returns the index of the slot this id refers to.
+    pub fn index(&self) -> ::Id {
+        self.index
+    }
+
+    #[inline]
+    /// Returns the generation of the slot this id refers to.
+    pub fn generation(&self) -> Generation {
+        self.generation
+    }
+}
+
+/// A container that gives each item a unique id, like `IdMap`, but where every slot carries a
+/// generation counter which is bumped when its value is removed. Ids from before the removal are
+/// rejected even after the slot is reused.
+#[derive(Clone)]
+pub struct GenIdMap<T> {
+    // The underlying storage, responsible for allocating slot indices.
+    map: IdMap<T>,
+    // The current generation of each slot. Slots past the end are at generation 0.
+    generations: Vec<Generation>,
+}
+
+impl<T> GenIdMap<T> {
+    #[inline]
+    /// Creates an empty `GenIdMap<T>`.
+    pub fn new() -> Self {
+        GenIdMap {
+            map: IdMap::new(),
+            generations: Vec::new(),
+        }
+    }
+
+    #[inline]
+    /// Creates a `GenIdMap<T>` with the specified capacity.
+    pub fn with_capacity(cap: usize) -> Self {
+        GenIdMap {
+            map: IdMap::with_capacity(cap),
+            generations: Vec::with_capacity(cap),
+        }
+    }
+
+    #[inline]
+    /// Removes all values from the map, invalidating all existing ids.
+    pub fn clear(&mut self) {
+        for index in self.map.ids() {
+            self.generations[index] = self.generations[index].wrapping_add(1);
+        }
+        self.map.clear();
+    }
+
+    #[inline]
+    /// Returns the id that a subsequent call to insert() will produce.
+    pub fn next_id(&self) -> GenId {
+        let index = self.map.next_id();
+        GenId::new(index, self.generation(index))
+    }
+
+    #[inline]
+    /// Returns the number of id-value pairs in the map.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    #[inline]
+    /// Returns true if the map contains no values.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    #[inline]
+    /// Returns the number of id-value pairs the map can hold before reallocating.
+    pub fn capacity(&self) -> usize {
+        self.map.capacity()
+    }
+
+    #[inline]
+    /// Resizes the map such that that `capacity() >= cap`.
+    pub fn reserve(&mut self, cap: usize) {
+        self.map.reserve(cap);
+        self.generations.reserve(cap);
+    }
+
+    #[inline]
+    /// Inserts a value into an empty slot in the map and returns its id.
+    pub fn insert(&mut self, val: T) -> GenId {
+        let index = self.map.insert(val);
+        if index == self.generations.len() {
+            self.generations.push(0);
+        }
+        GenId::new(index, self.generations[index])
+    }
+
+    #[inline]
+    /// Removes an id from the map, returning its value if the id is still valid.
+    pub fn remove(&mut self, id: GenId) -> Option<T> {
+        if self.contains(id) {
+            self.generations[id.index] = id.generation.wrapping_add(1);
+            self.map.remove(id.index)
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    /// Remove all values not satisfying the predicate.
+    pub fn retain<F: FnMut(GenId, &T) -> bool>(&mut self, mut pred: F) {
+        let generations = &mut self.generations;
+        self.map.retain(|index, val| {
+            if pred(GenId::new(index, generations[index]), val) {
+                true
+            } else {
+                generations[index] = generations[index].wrapping_add(1);
+                false
+            }
+        })
+    }
+
+    #[inline]
+    /// Returns true if the id is still valid.
+    pub fn contains(&self, id: GenId) -> bool {
+        self.map.contains(id.index) && self.generations[id.index] == id.generation
+    }
+
+    #[inline]
+    /// Returns a reference to the value for the specified id if it is still valid.
+    pub fn get(&self, id: GenId) -> Option<&T> {
+        if self.contains(id) {
+            self.map.get(id.index)
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    /// Returns a mutable reference to the value for the specified id if it is still valid.
+    pub fn get_mut(&mut self, id: GenId) -> Option<&mut T> {
+        if self.contains(id) {
+            self.map.get_mut(id.index)
+        } else {
+            None
+        }
+    }
+
+    /// Returns mutable references to the values for several distinct ids at once.
+    ///
+    /// Returns an error if any id is no longer valid, or if the same slot is requested more than
+    /// once.
+    pub fn get_many_mut<const N: usize>(
+        &mut self,
+        ids: [GenId; N],
+    ) -> Result<[&mut T; N], GetManyError<GenId>> {
+        if let Some(&id) = ids.iter().find(|&&id| !self.contains(id)) {
+            return Err(GetManyError::Missing(id));
+        }
+        self.map
+            .get_many_mut(ids.map(|id| id.index))
+            .map_err(|err| match err {
+                GetManyError::Duplicate(index) | GetManyError::Missing(index) => {
+                    GetManyError::Duplicate(*ids.iter().find(|id| id.index == index).unwrap())
+                }
+            })
+    }
+
+    #[inline]
+    /// Returns mutable references to the values for two distinct ids at once.
+    ///
+    /// Returns an error if either id is no longer valid, or if the ids are equal.
+    pub fn get2_mut(
+        &mut self,
+        a: GenId,
+        b: GenId,
+    ) -> Result<(&mut T, &mut T), GetManyError<GenId>> {
+        let [a, b] = self.get_many_mut([a, b])?;
+        Ok((a, b))
+    }
+
+    #[inline]
+    /// An iterator over ids, in increasing order.
+    pub fn ids(&self) -> Ids<'_> {
+        Ids {
+            ids: self.map.ids(),
+            generations: &self.generations,
+        }
+    }
+
+    #[inline]
+    /// An iterator over values, in order of increasing id.
+    pub fn values(&self) -> Values<'_, T> {
+        self.map.values()
+    }
+
+    #[inline]
+    /// A mutable iterator over values, in order of increasing id.
+    pub fn values_mut(&mut self) -> ValuesMut<'_, T> {
+        self.map.values_mut()
+    }
+
+    #[inline]
+    /// An iterator over id-value pairs, in order of increasing id.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            iter: self.map.iter(),
+            generations: &self.generations,
+        }
+    }
+
+    #[inline]
+    /// A mutable iterator over id-value pairs, in order of increasing id.
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        IterMut {
+            iter: self.map.iter_mut(),
+            generations: &self.generations,
+        }
+    }
+
+    #[cfg(all(test, feature = "std"))]
+    pub(crate) fn assert_invariant(&self) {
+        self.map.assert_invariant();
+        for index in self.map.ids() {
+            assert!(index < self.generations.len());
+        }
+    }
+
+    /// Returns the current generation of a slot.
+    fn generation(&self, index: ::Id) -> Generation {
+        self.generations.get(index).cloned().unwrap_or(0)
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for GenIdMap<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<T> Default for GenIdMap<T> {
+    #[inline]
+    fn default() -> Self {
+        GenIdMap::new()
+    }
+}
+
+impl<T> Extend<T> for GenIdMap<T> {
+    #[inline]
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for val in iter {
+            self.insert(val);
+        }
+    }
+}
+
+impl<T> FromIterator<T> for GenIdMap<T> {
+    #[inline]
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let map = IdMap::from_iter(iter);
+        let generations = vec![0; map.len()];
+        GenIdMap { map, generations }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a GenIdMap<T> {
+    type Item = (GenId, &'a T);
+    type IntoIter = Iter<'a, T>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a mut GenIdMap<T> {
+    type Item = (GenId, &'a mut T);
+    type IntoIter = IterMut<'a, T>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+impl<T> IntoIterator for GenIdMap<T> {
+    type Item = (GenId, T);
+    type IntoIter = IntoIter<T>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            iter: self.map.into_iter(),
+            generations: self.generations,
+        }
+    }
+}
+
+impl<T> Index<GenId> for GenIdMap<T> {
+    type Output = T;
+
+    #[inline]
+    fn index(&self, id: GenId) -> &Self::Output {
+        assert!(self.contains(id), "id {:?} is not valid", id);
+        &self.map[id.index]
+    }
+}
+
+impl<T> IndexMut<GenId> for GenIdMap<T> {
+    #[inline]
+    fn index_mut(&mut self, id: GenId) -> &mut Self::Output {
+        assert!(self.contains(id), "id {:?} is not valid", id);
+        &mut self.map[id.index]
+    }
+}
+
+#[derive(Clone, Debug)]
+/// An iterator over all ids, in increasing order.
+pub struct Ids<'a> {
+    ids: ::Ids<'a>,
+    generations: &'a [Generation],
+}
+
+impl<'a> Iterator for Ids<'a> {
+    type Item = GenId;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.ids
+            .next()
+            .map(|index| GenId::new(index, self.generations[index]))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.ids.size_hint()
+    }
+}
+
+impl<'a> DoubleEndedIterator for Ids<'a> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.ids
+            .next_back()
+            .map(|index| GenId::new(index, self.generations[index]))
+    }
+}
+
+impl<'a> ExactSizeIterator for Ids<'a> {
+    #[inline]
+    fn len(&self) -> usize {
+        self.ids.len()
+    }
+}
+
+impl<'a> FusedIterator for Ids<'a> {}
+
+#[derive(Debug)]
+/// An iterator over id-value pairs, in order of increasing id.
+pub struct Iter<'a, T: 'a> {
+    iter: ::Iter<'a, T>,
+    generations: &'a [Generation],
+}
+
+impl<'a, T: 'a> Iterator for Iter<'a, T> {
+    type Item = (GenId, &'a T);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        let (index, val) = self.iter.next()?;
+        Some((GenId::new(index, self.generations[index]), val))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<'a, T: 'a> DoubleEndedIterator for Iter<'a, T> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let (index, val) = self.iter.next_back()?;
+        Some((GenId::new(index, self.generations[index]), val))
+    }
+}
+
+impl<'a, T: 'a> ExactSizeIterator for Iter<'a, T> {
+    #[inline]
+    fn len(&self) -> usize {
+        self.iter.len()
+    }
+}
+
+impl<'a, T: 'a> FusedIterator for Iter<'a, T> {}
+
+impl<'a, T: 'a> Clone for Iter<'a, T> {
+    #[inline]
+    fn clone(&self) -> Self {
+        Iter {
+            iter: self.iter.clone(),
+            generations: self.generations,
+        }
+    }
+}
+
+#[derive(Debug)]
+/// A mutable iterator over id-value pairs, in order of increasing id.
+pub struct IterMut<'a, T: 'a> {
+    iter: ::IterMut<'a, T>,
+    generations: &'a [Generation],
+}
+
+impl<'a, T: 'a> Iterator for IterMut<'a, T> {
+    type Item = (GenId, &'a mut T);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        let (index, val) = self.iter.next()?;
+        Some((GenId::new(index, self.generations[index]), val))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<'a, T: 'a> DoubleEndedIterator for IterMut<'a, T> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let (index, val) = self.iter.next_back()?;
+        Some((GenId::new(index, self.generations[index]), val))
+    }
+}
+
+impl<'a, T: 'a> ExactSizeIterator for IterMut<'a, T> {
+    #[inline]
+    fn len(&self) -> usize {
+        self.iter.len()
+    }
+}
+
+impl<'a, T: 'a> FusedIterator for IterMut<'a, T> {}
+
+#[derive(Clone, Debug)]
+/// A consuming iterator over id-value pairs, in order of increasing id.
+pub struct IntoIter<T> {
+    iter: ::IntoIter<T>,
+    generations: Vec<Generation>,
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = (GenId, T);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        let (index, val) = self.iter.next()?;
+        Some((GenId::new(index, self.generations[index]), val))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<T> DoubleEndedIterator for IntoIter<T> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let (index, val) = self.iter.next_back()?;
+        Some((GenId::new(index, self.generations[index]), val))
+    }
+}
+
+impl<T> ExactSizeIterator for IntoIter<T> {
+    #[inline]
+    fn len(&self) -> usize {
+        self.iter.len()
+    }
+}
+
+impl<T> FusedIterator for IntoIter<T> {}
@@ -0,0 +1,452 @@
+//! Iteration over the ids shared by several maps.
+//!
+//! A join is created with the [`join!`] macro from up to four parts, each of which is one of:
+//!
+//! - `&map`, yielding `&T` for ids in the map.
+//! - `&mut map`, yielding `&mut T` for ids in the map.
+//! - `maybe(part)`, yielding `Option<_>` of the inner part without restricting the ids.
+//! - `without(&map)`, yielding `()` and skipping ids in the map.
+//!
+//! The first part must be `&map` or `&mut map`, which bounds the joined ids. A join which only
+//! has optional or negated parts does not compile:
+//!
+//! ```compile_fail
+//! # #[macro_use] extern crate id_map;
+//! # use id_map::IdMap;
+//! use id_map::join::{maybe, without};
+//!
+//! # fn main() {
+//! let a: IdMap<u32> = IdMap::new();
+//! let b: IdMap<u32> = IdMap::new();
+//! join!(maybe(&a), without(&b));
+//! # }
+//! ```
+//!
+//! The joined ids are found by combining the blocks of the underlying `IdSet`s, so ids missing
+//! from any required map are skipped a block at a time.
+//!
+//! # Examples
+//!
+//! ```
+//! # #[macro_use] extern crate id_map;
+//! # use id_map::IdMap;
+//! use id_map::join::{maybe, without};
+//!
+//! # fn main() {
+//! let positions: IdMap<_> = vec![0, 10, 20, 30].into_iter().collect();
+//! let mut velocities: IdMap<_> = vec![1, 2, 3].into_iter().collect();
+//! let mut frozen = IdMap::new();
+//! frozen.insert_at(1, ());
+//! let names: IdMap<_> = vec!["a"].into_iter().collect();
+//!
+//! for (_, pos, vel, name, ()) in join!(&positions, &mut velocities, maybe(&names), without(&frozen)) {
+//!     *vel += pos;
+//!     assert_eq!(name.is_some(), *pos == 0);
+//! }
+//! assert_eq!(velocities.values().cloned().collect::<Vec<_>>(), vec![1, 2, 23]);
+//! # }
+//! ```
+//!
+//! [`join!`]: ../macro.join.html
+
+use core::cmp;
+use core::iter::FusedIterator;
+use core::marker::PhantomData;
+use core::slice;
+
+use {AllocPolicy, Block, Id, IdMap, IdSet, Key, SecondaryIdMap, BITS};
+
+/// A participant in a join.
+pub trait JoinPart {
+    /// The key type of the joined maps.
+    type Key: Key;
+    /// The item yielded for each joined id.
+    type Item;
+
+    /// Restricts `word`, the block of candidate ids at index `idx`.
+    fn mask(&self, idx: usize, word: Block) -> Block;
+
+    /// The number of blocks which may contain joined ids, or `None` if this part does not
+    /// restrict the joined ids from above.
+    fn blocks_len(&self) -> Option<usize>;
+
+    /// Returns true if this part has an item for the id.
+    fn contains(&self, id: Id) -> bool;
+
+    /// Returns the item for an id. Ids are passed in strictly increasing order and only ever
+    /// ones that were not masked out, and for which `contains` returns true.
+    fn fetch(&mut self, id: Id) -> Self::Item;
+}
+
+/// A join part which only has items for the ids in a set, so it bounds the joined ids. The first
+/// part of a join must be required.
+pub trait RequiredPart: JoinPart {
+    /// Returns the set of ids with items.
+    fn ids(&self) -> &IdSet;
+}
+
+/// Conversion into a `JoinPart`, used by the `join!` macro.
+pub trait IntoJoinPart {
+    /// The part type.
+    type Part: JoinPart;
+
+    /// Converts into a join part.
+    fn into_join_part(self) -> Self::Part;
+}
+
+impl<P: JoinPart> IntoJoinPart for P {
+    type Part = P;
+
+    #[inline]
+    fn into_join_part(self) -> Self::Part {
+        self
+    }
+}
+
+#[derive(Debug)]
+/// A join part yielding shared references to the values of a map.
+pub struct Shared<'a, T: 'a, K = Id> {
+    ids: &'a IdSet,
+    values: &'a [Option<T>],
+    marker: PhantomData<K>,
+}
+
+impl<'a, T: 'a, K: Key> JoinPart for Shared<'a, T, K> {
+    type Key = K;
+    type Item = &'a T;
+
+    #[inline]
+    fn mask(&self, idx: usize, word: Block) -> Block {
+        word & self.ids.as_blocks().get(idx).cloned().unwrap_or(0)
+    }
+
+    #[inline]
+    fn blocks_len(&self) -> Option<usize> {
+        Some(self.ids.as_blocks().len())
+    }
+
+    #[inline]
+    fn contains(&self, id: Id) -> bool {
+        self.ids.contains(id)
+    }
+
+    #[inline]
+    fn fetch(&mut self, id: Id) -> Self::Item {
+        self.values[id].as_ref().unwrap()
+    }
+}
+
+impl<'a, T: 'a, K: Key> RequiredPart for Shared<'a, T, K> {
+    #[inline]
+    fn ids(&self) -> &IdSet {
+        self.ids
+    }
+}
+
+impl<'a, T: 'a, K: Key, A: AllocPolicy> IntoJoinPart for &'a IdMap<T, K, A> {
+    type Part = Shared<'a, T, K>;
+
+    #[inline]
+    fn into_join_part(self) -> Self::Part {
+        Shared {
+            ids: &self.state.ids,
+            values: &self.values,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, T: 'a, K: Key> IntoJoinPart for &'a SecondaryIdMap<T, K> {
+    type Part = Shared<'a, T, K>;
+
+    #[inline]
+    fn into_join_part(self) -> Self::Part {
+        (&self.map).into_join_part()
+    }
+}
+
+#[derive(Debug)]
+/// A join part yielding mutable references to the values of a map.
+pub struct Unique<'a, T: 'a, K = Id> {
+    ids: &'a IdSet,
+    // The id of the next slot in values.
+    front: Id,
+    values: slice::IterMut<'a, Option<T>>,
+    marker: PhantomData<K>,
+}
+
+impl<'a, T: 'a, K: Key> JoinPart for Unique<'a, T, K> {
+    type Key = K;
+    type Item = &'a mut T;
+
+    #[inline]
+    fn mask(&self, idx: usize, word: Block) -> Block {
+        word & self.ids.as_blocks().get(idx).cloned().unwrap_or(0)
+    }
+
+    #[inline]
+    fn blocks_len(&self) -> Option<usize> {
+        Some(self.ids.as_blocks().len())
+    }
+
+    #[inline]
+    fn contains(&self, id: Id) -> bool {
+        self.ids.contains(id)
+    }
+
+    #[inline]
+    fn fetch(&mut self, id: Id) -> Self::Item {
+        let n = id - self.front;
+        self.front = id + 1;
+        self.values.nth(n).unwrap().as_mut().unwrap()
+    }
+}
+
+impl<'a, T: 'a, K: Key> RequiredPart for Unique<'a, T, K> {
+    #[inline]
+    fn ids(&self) -> &IdSet {
+        self.ids
+    }
+}
+
+impl<'a, T: 'a, K: Key, A: AllocPolicy> IntoJoinPart for &'a mut IdMap<T, K, A> {
+    type Part = Unique<'a, T, K>;
+
+    #[inline]
+    fn into_join_part(self) -> Self::Part {
+        let IdMap {
+            ref state,
+            ref mut values,
+            ..
+        } = *self;
+        let ids = &state.ids;
+        Unique {
+            ids,
+            front: 0,
+            values: values.iter_mut(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, T: 'a, K: Key> IntoJoinPart for &'a mut SecondaryIdMap<T, K> {
+    type Part = Unique<'a, T, K>;
+
+    #[inline]
+    fn into_join_part(self) -> Self::Part {
+        (&mut self.map).into_join_part()
+    }
+}
+
+#[derive(Debug)]
+/// A join part yielding `Some` item of the inner part if it contains the id, and `None`
+/// otherwise. Created with `maybe`.
+pub struct Maybe<P> {
+    part: P,
+}
+
+#[inline]
+/// Makes a join part optional, so it no longer restricts the joined ids.
+pub fn maybe<P: IntoJoinPart>(part: P) -> Maybe<P::Part> {
+    Maybe {
+        part: part.into_join_part(),
+    }
+}
+
+impl<P: JoinPart> JoinPart for Maybe<P> {
+    type Key = P::Key;
+    type Item = Option<P::Item>;
+
+    #[inline]
+    fn mask(&self, _: usize, word: Block) -> Block {
+        word
+    }
+
+    #[inline]
+    fn blocks_len(&self) -> Option<usize> {
+        None
+    }
+
+    #[inline]
+    fn contains(&self, _: Id) -> bool {
+        true
+    }
+
+    #[inline]
+    fn fetch(&mut self, id: Id) -> Self::Item {
+        if self.part.contains(id) {
+            Some(self.part.fetch(id))
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug)]
+/// A join part yielding `()` for the ids which the inner part does not contain. Created with
+/// `without`.
+pub struct Without<P> {
+    part: P,
+}
+
+#[inline]
+/// Creates a join part which skips the ids in a map, such as an `IdMap` or a `SecondaryIdMap`.
+pub fn without<P: IntoJoinPart>(part: P) -> Without<P::Part> {
+    Without {
+        part: part.into_join_part(),
+    }
+}
+
+impl<P: JoinPart> JoinPart for Without<P> {
+    type Key = P::Key;
+    type Item = ();
+
+    #[inline]
+    fn mask(&self, idx: usize, word: Block) -> Block {
+        word & !self.part.mask(idx, !0)
+    }
+
+    #[inline]
+    fn blocks_len(&self) -> Option<usize> {
+        None
+    }
+
+    #[inline]
+    fn contains(&self, id: Id) -> bool {
+        !self.part.contains(id)
+    }
+
+    #[inline]
+    fn fetch(&mut self, _: Id) -> Self::Item {}
+}
+
+/// A tuple of up to four join parts, the first of which is required.
+pub trait JoinParts {
+    /// The key type of the joined maps.
+    type Key: Key;
+    /// The item yielded for each joined id, made up of the id and the item of each part.
+    type Item;
+
+    /// Restricts `word`, the block of candidate ids at index `idx`, by every part.
+    fn mask(&self, idx: usize, word: Block) -> Block;
+
+    /// The number of blocks which may contain joined ids.
+    fn blocks_len(&self) -> usize;
+
+    /// Returns the item of every part for an id.
+    fn fetch(&mut self, id: Id) -> Self::Item;
+}
+
+macro_rules! join_parts_impls {
+    ($first:ident $(, $name:ident)*) => {
+        #[allow(non_snake_case)]
+        impl<K: Key, $first: RequiredPart<Key = K>, $($name: JoinPart<Key = K>),*> JoinParts
+            for ($first, $($name,)*)
+        {
+            type Key = K;
+            type Item = (K, $first::Item, $($name::Item),*);
+
+            #[inline]
+            fn mask(&self, idx: usize, word: Block) -> Block {
+                let (ref $first, $(ref $name,)*) = *self;
+                let word = $first.mask(idx, word);
+                $(let word = $name.mask(idx, word);)*
+                word
+            }
+
+            #[inline]
+            fn blocks_len(&self) -> usize {
+                let (ref $first, $(ref $name,)*) = *self;
+                let len = $first.ids().as_blocks().len();
+                $(let len = min_len(len, $name.blocks_len());)*
+                len
+            }
+
+            #[inline]
+            fn fetch(&mut self, id: Id) -> Self::Item {
+                let (ref mut $first, $(ref mut $name,)*) = *self;
+                (K::from_id(id), $first.fetch(id), $($name.fetch(id)),*)
+            }
+        }
+    };
+}
+
+join_parts_impls!(A);
+join_parts_impls!(A, B);
+join_parts_impls!(A, B, C);
+join_parts_impls!(A, B, C, D);
+
+#[derive(Debug)]
+/// An iterator over the ids shared by several maps, in increasing order. Created with `join!`.
+pub struct Join<P> {
+    parts: P,
+    // The index of the next block to load, and the number of blocks.
+    idx: usize,
+    end: usize,
+    // The remaining ids in the current block, and the id of its first bit.
+    word: Block,
+    base: Id,
+}
+
+impl<P: JoinParts> Join<P> {
+    /// Creates a join of the given parts.
+    pub fn new(parts: P) -> Self {
+        let end = parts.blocks_len();
+        Join {
+            parts,
+            idx: 0,
+            end,
+            word: 0,
+            base: 0,
+        }
+    }
+}
+
+impl<P: JoinParts> Iterator for Join<P> {
+    type Item = P::Item;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.word == 0 {
+            if self.idx == self.end {
+                return None;
+            }
+            self.word = self.parts.mask(self.idx, !0);
+            self.base = self.idx * BITS;
+            self.idx += 1;
+        }
+
+        let id = self.base + self.word.trailing_zeros() as usize;
+        self.word &= self.word - 1;
+        Some(self.parts.fetch(id))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = (self.end - self.idx) * BITS;
+        (0, Some(remaining + self.word.count_ones() as usize))
+    }
+}
+
+impl<P: JoinParts> FusedIterator for Join<P> {}
+
+/// Restricts a block count by that of another part.
+#[inline]
+fn min_len(a: usize, b: Option<usize>) -> usize {
+    match b {
+        Some(b) => cmp::min(a, b),
+        None => a,
+    }
+}
+
+#[macro_export]
+/// Iterates over the ids shared by up to four maps, yielding the id followed by the item of
+/// each part. The first part must be `&map` or `&mut map`. See the [`join`] module for the kinds
+/// of part.
+///
+/// [`join`]: join/index.html
+macro_rules! join {
+    ($($part:expr),+ $(,)*) => {
+        $crate::join::Join::new(($($crate::join::IntoJoinPart::into_join_part($part),)+))
+    };
+}
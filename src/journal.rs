@@ -0,0 +1,376 @@
+//! A map which records its changes so that they can be undone and redone.
+
+use alloc::vec::Vec;
+use core::fmt;
+use core::mem;
+use core::ops::{Index, IndexMut};
+
+use {Id, IdMap, IdSet, Ids, Iter, Key};
+
+// The previous contents of each slot changed by a transaction, in the order they were changed.
+type Transaction<T> = Vec<(Id, Option<T>)>;
+
+/// A wrapper around an `IdMap` which records every change, so that it can be undone and redone.
+///
+/// Each change records the previous contents of the slot it touched, and changes are grouped into
+/// transactions which are undone as a whole. Changes made outside `transaction` form a transaction
+/// of their own. Undoing restores the exact ids the map held, so a removed value comes back at its
+/// original id and `next_id()` returns what it did before. Making a new change discards the
+/// transactions available to redo.
+///
+/// Removing or mutably borrowing a value clones it into the journal, so `T` must be `Clone`.
+///
+/// # Examples
+///
+/// ```
+/// # use id_map::JournaledIdMap;
+/// #
+/// let mut map = JournaledIdMap::new();
+/// let a = map.insert("a");
+/// let b = map.transaction(|map| {
+///     map.remove(a);
+///     map.insert("b")
+/// });
+/// assert_eq!(b, a);
+///
+/// assert!(map.undo());
+/// assert_eq!(map[a], "a");
+/// assert_eq!(map.next_id(), 1);
+///
+/// assert!(map.redo());
+/// assert_eq!(map[b], "b");
+/// ```
+#[derive(Clone)]
+pub struct JournaledIdMap<T, K = Id> {
+    map: IdMap<T, K>,
+    // Transactions which can be undone, most recent last.
+    undo: Vec<Transaction<T>>,
+    // Transactions which can be redone, most recently undone last.
+    redo: Vec<Transaction<T>>,
+    // The transaction being recorded.
+    pending: Transaction<T>,
+    // The number of nested calls to `transaction` in progress.
+    depth: usize,
+}
+
+impl<T: Clone> JournaledIdMap<T> {
+    #[inline]
+    /// Creates an empty `JournaledIdMap<T>`.
+    pub fn new() -> Self {
+        JournaledIdMap::with_key()
+    }
+}
+
+impl<T: Clone, K: Key> JournaledIdMap<T, K> {
+    #[inline]
+    /// Creates an empty `JournaledIdMap<T, K>` with a custom key type.
+    pub fn with_key() -> Self {
+        JournaledIdMap::from(IdMap::with_key())
+    }
+
+    #[inline]
+    /// Returns a reference to the underlying map.
+    pub fn as_map(&self) -> &IdMap<T, K> {
+        &self.map
+    }
+
+    #[inline]
+    /// Returns the underlying map, discarding the journal.
+    pub fn into_map(self) -> IdMap<T, K> {
+        self.map
+    }
+
+    #[inline]
+    /// Returns the id that a subsequent call to insert() will produce.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the map is full, as `IdMap::next_id` does.
+    pub fn next_id(&self) -> K {
+        self.map.next_id()
+    }
+
+    #[inline]
+    /// Returns the id that a subsequent call to insert() will produce, or `None` if the map is
+    /// full.
+    pub fn try_next_id(&self) -> Option<K> {
+        self.map.try_next_id()
+    }
+
+    #[inline]
+    /// Returns the number of id-value pairs in the map.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    #[inline]
+    /// Returns true if the map contains no values.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    #[inline]
+    /// Returns true if the map contains a value for the specified id.
+    pub fn contains(&self, id: K) -> bool {
+        self.map.contains(id)
+    }
+
+    #[inline]
+    /// Returns a reference to the value at the specified id if it is in the map.
+    pub fn get(&self, id: K) -> Option<&T> {
+        self.map.get(id)
+    }
+
+    #[inline]
+    /// An iterator over ids, in increasing order.
+    pub fn ids(&self) -> Ids<'_, K> {
+        self.map.ids()
+    }
+
+    #[inline]
+    /// An iterator over id-value pairs, in order of increasing id.
+    pub fn iter(&self) -> Iter<'_, T, K> {
+        self.map.iter()
+    }
+
+    /// Returns a mutable reference to the value at the specified id if it is in the map, and
+    /// records its current value.
+    pub fn get_mut(&mut self, id: K) -> Option<&mut T> {
+        let old = self.map.get(id)?.clone();
+        self.record(id.to_id(), Some(old));
+        self.map.get_mut(id)
+    }
+
+    /// Inserts a value into an empty slot in the map and returns its id.
+    pub fn insert(&mut self, val: T) -> K {
+        let id = self.map.insert(val);
+        self.record(id.to_id(), None);
+        id
+    }
+
+    /// Inserts a value at a specific id, returning the old value if it existed.
+    pub fn insert_at(&mut self, id: K, val: T) -> Option<T> {
+        let old = self.map.insert_at(id, val);
+        self.record(id.to_id(), old.clone());
+        old
+    }
+
+    /// Removes an id from the map, returning its value if it was previously in the map.
+    pub fn remove(&mut self, id: K) -> Option<T> {
+        let val = self.map.remove(id)?;
+        self.record(id.to_id(), Some(val.clone()));
+        Some(val)
+    }
+
+    /// Removes all ids in the set from the map.
+    pub fn remove_set(&mut self, set: &IdSet) {
+        self.transaction(|map| {
+            for id in map
+                .map
+                .as_set()
+                .intersection(set)
+                .into_iter()
+                .collect::<Vec<_>>()
+            {
+                let val = map.map.remove(K::from_id(id));
+                map.record(id, val);
+            }
+        })
+    }
+
+    /// Remove all values not satisfying the predicate.
+    pub fn retain<F: FnMut(K, &T) -> bool>(&mut self, mut pred: F) {
+        self.transaction(|map| {
+            // Record each value as it is removed, so none are lost if the predicate panics.
+            let JournaledIdMap {
+                ref mut map,
+                ref mut pending,
+                ..
+            } = *map;
+            for (id, val) in map.extract_if(|id, val| !pred(id, val)) {
+                pending.push((id.to_id(), Some(val)));
+            }
+        })
+    }
+
+    /// Removes all values from the map.
+    pub fn clear(&mut self) {
+        self.retain(|_, _| false);
+    }
+
+    /// Calls the closure, grouping all the changes it makes into a single transaction.
+    ///
+    /// Transactions may be nested, in which case the changes belong to the outermost one.
+    ///
+    /// If the closure panics, the changes it made before panicking are kept and still committed
+    /// as a transaction, so a single `undo` reverts them.
+    pub fn transaction<F: FnOnce(&mut Self) -> R, R>(&mut self, f: F) -> R {
+        let guard = TransactionGuard::new(self);
+        f(guard.map)
+    }
+
+    #[inline]
+    /// Returns true if there is a transaction which can be undone.
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    #[inline]
+    /// Returns true if there is a transaction which can be redone.
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Reverts the most recent transaction, returning false if there was none.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called during a transaction.
+    pub fn undo(&mut self) -> bool {
+        assert!(self.depth == 0, "cannot undo during a transaction");
+        match self.undo.pop() {
+            Some(tx) => {
+                let tx = self.revert(tx);
+                self.redo.push(tx);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Reapplies the most recently undone transaction, returning false if there was none.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called during a transaction.
+    pub fn redo(&mut self) -> bool {
+        assert!(self.depth == 0, "cannot redo during a transaction");
+        match self.redo.pop() {
+            Some(tx) => {
+                let tx = self.revert(tx);
+                self.undo.push(tx);
+                true
+            }
+            None => false,
+        }
+    }
+
+    #[inline]
+    /// Discards all transactions which can be undone or redone.
+    pub fn clear_history(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+
+    /// Records the previous contents of a slot which has just been changed.
+    fn record(&mut self, id: Id, old: Option<T>) {
+        self.pending.push((id, old));
+        if self.depth == 0 {
+            self.commit();
+        }
+    }
+
+    /// Ends the pending transaction, unless a call to `transaction` is still in progress.
+    fn commit(&mut self) {
+        if self.depth == 0 && !self.pending.is_empty() {
+            self.undo.push(mem::take(&mut self.pending));
+            self.redo.clear();
+        }
+    }
+
+    /// Restores the slots in a transaction, latest change first, and returns the transaction
+    /// which reverses it.
+    fn revert(&mut self, tx: Transaction<T>) -> Transaction<T> {
+        tx.into_iter()
+            .rev()
+            .map(|(id, old)| {
+                let current = match old {
+                    Some(val) => self.map.insert_at(K::from_id(id), val),
+                    None => self.map.remove(K::from_id(id)),
+                };
+                (id, current)
+            })
+            .collect()
+    }
+}
+
+// Ends a call to `transaction` when dropped, even if the closure panics.
+struct TransactionGuard<'a, T: Clone + 'a, K: Key + 'a> {
+    map: &'a mut JournaledIdMap<T, K>,
+}
+
+impl<'a, T: Clone, K: Key> TransactionGuard<'a, T, K> {
+    #[inline]
+    fn new(map: &'a mut JournaledIdMap<T, K>) -> Self {
+        map.depth += 1;
+        TransactionGuard { map }
+    }
+}
+
+impl<'a, T: Clone, K: Key> Drop for TransactionGuard<'a, T, K> {
+    #[inline]
+    fn drop(&mut self) {
+        self.map.depth -= 1;
+        self.map.commit();
+    }
+}
+
+impl<T: fmt::Debug, K: Key> fmt::Debug for JournaledIdMap<T, K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("JournaledIdMap")
+            .field("map", &self.map)
+            .field("undo", &self.undo.len())
+            .field("redo", &self.redo.len())
+            .finish()
+    }
+}
+
+impl<T: Clone, K: Key> Default for JournaledIdMap<T, K> {
+    #[inline]
+    fn default() -> Self {
+        JournaledIdMap::with_key()
+    }
+}
+
+impl<T: Clone, K: Key> From<IdMap<T, K>> for JournaledIdMap<T, K> {
+    /// Wraps a map, with no changes to undo.
+    #[inline]
+    fn from(map: IdMap<T, K>) -> Self {
+        JournaledIdMap {
+            map,
+            undo: Vec::new(),
+            redo: Vec::new(),
+            pending: Vec::new(),
+            depth: 0,
+        }
+    }
+}
+
+impl<'a, T: Clone, K: Key> IntoIterator for &'a JournaledIdMap<T, K> {
+    type Item = (K, &'a T);
+    type IntoIter = Iter<'a, T, K>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T: Clone, K: Key> Index<K> for JournaledIdMap<T, K> {
+    type Output = T;
+
+    #[inline]
+    fn index(&self, id: K) -> &Self::Output {
+        &self.map[id]
+    }
+}
+
+impl<T: Clone, K: Key> IndexMut<K> for JournaledIdMap<T, K> {
+    #[inline]
+    fn index_mut(&mut self, id: K) -> &mut Self::Output {
+        match self.get_mut(id) {
+            Some(val) => val,
+            None => panic!("id {} out of bounds", id.to_id()),
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use core::convert::TryFrom;
+use core::fmt;
+
+use Id;
+
+/// A type that can be used as the id of an `IdMap`.
+///
+/// Using a distinct key type for each map prevents an id from one map being used to index
+/// another by mistake. Newtype keys can be declared with the [`new_key_type!`] macro.
+///
+/// Keys may also be narrower than `Id`, so that ids stored outside the map take less space. A map
+/// never allocates an id greater than its key type's `MAX_ID`, and panics instead.
+///
+/// # Examples
+///
+#[cfg_attr(feature = "alloc", doc = "```")]
+#[cfg_attr(not(feature = "alloc"), doc = "```ignore")]
+/// # use id_map::IdMap;
+/// #
+/// let mut map = IdMap::<&str, u16>::with_key();
+/// let id: u16 = map.insert("a");
+/// assert_eq!(map[id], "a");
+/// assert_eq!(map.max_id(), u16::MAX);
+/// ```
+///
+/// [`new_key_type!`]: macro.new_key_type.html
+pub trait Key: Copy + Eq + fmt::Debug {
+    /// The largest id which can be represented by this key.
+    const MAX_ID: Id = Id::MAX;
+
+    /// Creates a key from a raw id.
+    ///
+    /// This may panic if the id is greater than `MAX_ID`.
+    fn from_id(id: Id) -> Self;
+
+    #[inline]
+    /// Creates a key from a raw id, or returns `None` if the id is greater than `MAX_ID`.
+    fn try_from_id(id: Id) -> Option<Self> {
+        if id <= Self::MAX_ID {
+            Some(Self::from_id(id))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the raw id of this key.
+    fn to_id(self) -> Id;
+}
+
+impl Key for Id {
+    #[inline]
+    fn from_id(id: Id) -> Self {
+        id
+    }
+
+    #[inline]
+    fn to_id(self) -> Id {
+        self
+    }
+}
+
+impl Key for u16 {
+    const MAX_ID: Id = u16::MAX as Id;
+
+    #[inline]
+    fn from_id(id: Id) -> Self {
+        match u16::try_from(id) {
+            Ok(id) => id,
+            Err(_) => panic!("id {} does not fit in a u16", id),
+        }
+    }
+
+    #[inline]
+    fn to_id(self) -> Id {
+        Id::from(self)
+    }
+}
+
+impl Key for u32 {
+    const MAX_ID: Id = max_id(u32::MAX as u64);
+
+    #[inline]
+    fn from_id(id: Id) -> Self {
+        match u32::try_from(id) {
+            Ok(id) => id,
+            Err(_) => panic!("id {} does not fit in a u32", id),
+        }
+    }
+
+    #[inline]
+    fn to_id(self) -> Id {
+        match Id::try_from(self) {
+            Ok(id) => id,
+            Err(_) => panic!("key {} does not fit in an id", self),
+        }
+    }
+}
+
+impl Key for u64 {
+    const MAX_ID: Id = max_id(u64::MAX);
+
+    #[inline]
+    fn from_id(id: Id) -> Self {
+        id as u64
+    }
+
+    #[inline]
+    fn to_id(self) -> Id {
+        match Id::try_from(self) {
+            Ok(id) => id,
+            Err(_) => panic!("key {} does not fit in an id", self),
+        }
+    }
+}
+
+/// Returns the smaller of `max` and `Id::MAX`.
+const fn max_id(max: u64) -> Id {
+    if max < Id::MAX as u64 {
+        max as Id
+    } else {
+        Id::MAX
+    }
+}
+
+#[macro_export]
+/// Declares newtype wrappers around `Id` implementing the `Key` trait.
+///
+/// A narrower integer key type can be wrapped instead by naming it in parentheses.
+///
+/// # Examples
+///
+#[cfg_attr(feature = "alloc", doc = "```")]
+#[cfg_attr(not(feature = "alloc"), doc = "```ignore")]
+/// # #[macro_use] extern crate id_map;
+/// # use id_map::IdMap;
+/// #
+/// new_key_type! {
+///     /// The id of a node.
+///     pub struct NodeId;
+///     struct EdgeId(u32);
+/// }
+///
+/// # fn main() {
+/// let mut nodes = IdMap::<&str, NodeId>::with_key();
+/// let node: NodeId = nodes.insert("root");
+/// assert_eq!(nodes[node], "root");
+/// assert_eq!(std::mem::size_of::<EdgeId>(), 4);
+/// # }
+/// ```
+macro_rules! new_key_type {
+    ($(#[$meta:meta])* $vis:vis struct $name:ident; $($rest:tt)*) => {
+        $crate::new_key_type! {
+            $(#[$meta])*
+            $vis struct $name($crate::Id);
+            $($rest)*
+        }
+    };
+    ($(#[$meta:meta])* $vis:vis struct $name:ident($int:ty); $($rest:tt)*) => {
+        $(#[$meta])*
+        #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        $vis struct $name($int);
+
+        impl $crate::Key for $name {
+            const MAX_ID: $crate::Id = <$int as $crate::Key>::MAX_ID;
+
+            #[inline]
+            fn from_id(id: $crate::Id) -> Self {
+                $name($crate::Key::from_id(id))
+            }
+
+            #[inline]
+            fn to_id(self) -> $crate::Id {
+                $crate::Key::to_id(self.0)
+            }
+        }
+
+        $crate::new_key_type! { $($rest)* }
+    };
+    () => {};
+}
@@ -0,0 +1,1155 @@
+use alloc::vec::{self, Vec};
+use core::iter::{FromIterator, FusedIterator};
+use core::marker::PhantomData;
+use core::ops::{Bound, Index, IndexMut, RangeBounds};
+use core::slice;
+use core::{cmp, fmt};
+
+use bits::{range_ids, raw_ids, BlockIds, RawIds};
+use raw::{IdState, RawMap};
+use {
+    AllocPolicy, Block, Drain, Entry, ExtractIf, GetManyError, Id, IdAllocator, IdMapDiff, IdRemap,
+    IdSet, Key, LowestFree, OccupiedEntry, TryReserveError, VacantEntry,
+};
+
+/// A container that gives each item a unique id. Internally all elements are stored contiguously.
+///
+/// Ids are handed out as values of the key type `K`, which defaults to the raw `Id`. The id used
+/// by each insert is chosen by the allocation policy `A`, which defaults to reusing the lowest free
+/// id. See the [`policy`] module for the alternatives.
+///
+/// [`policy`]: policy/index.html
+#[derive(Clone)]
+pub struct IdMap<T, K = Id, A = LowestFree> {
+    // The set of valid indices for values, the allocation policy and the maximum id.
+    pub(crate) state: IdState<A>,
+    // The buffer of values. Indices not in state.ids are invalid.
+    pub(crate) values: Vec<Option<T>>,
+    pub(crate) marker: PhantomData<K>,
+}
+
+impl<T> IdMap<T> {
+    #[inline]
+    /// Creates an empty `IdMap<T>`.
+    pub fn new() -> Self {
+        IdMap::with_key()
+    }
+
+    #[inline]
+    /// Creates an `IdMap<T>` with the specified capacity.
+    pub fn with_capacity(cap: usize) -> Self {
+        IdMap::with_capacity_and_key(cap)
+    }
+}
+
+impl<T, A: AllocPolicy> IdMap<T, Id, A> {
+    #[inline]
+    /// Creates an empty `IdMap<T, Id, A>` with the given allocation policy.
+    pub fn with_policy(policy: A) -> Self {
+        IdMap {
+            state: IdState::new(IdSet::new(), policy, Id::MAX),
+            values: Vec::new(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T, K: Key, A: AllocPolicy> IdMap<T, K, A> {
+    /// Creates a map which takes over the ids allocated by `alloc`, calling the closure for the
+    /// value of each id. Subsequent inserts continue with the allocator's policy.
+    pub fn from_allocator<F: FnMut(K) -> T>(alloc: IdAllocator<K, A>, f: F) -> Self {
+        let mut map = IdMap::with_key();
+        map.reset_allocator(alloc, f);
+        map
+    }
+
+    /// Replaces the ids of the map with those allocated by `alloc`, and its allocation policy
+    /// with the allocator's. Values at ids which are not allocated are dropped, and the closure is
+    /// called for the value of each allocated id which has none.
+    pub fn reset_allocator<F: FnMut(K) -> T>(&mut self, alloc: IdAllocator<K, A>, mut f: F) {
+        let IdAllocator { ids, policy, .. } = alloc;
+        for id in self.state.ids.difference(&ids) {
+            self.values[id] = None;
+        }
+        let len = raw_ids(&ids).next_back().map_or(0, |id| id + 1);
+        if self.values.len() < len {
+            self.values.resize_with(len, Default::default);
+        }
+        for id in ids.difference(&self.state.ids) {
+            self.values[id] = Some(f(K::from_id(id)));
+        }
+        self.state.ids = ids;
+        self.state.policy = policy;
+    }
+
+    #[inline]
+    /// Converts the map into an allocator of its ids, dropping the values.
+    pub fn into_allocator(self) -> IdAllocator<K, A> {
+        IdAllocator::from_parts(self.state.ids, self.state.policy)
+    }
+
+    #[inline]
+    /// Creates an empty `IdMap<T, K>` with a custom key type.
+    pub fn with_key() -> Self {
+        IdMap {
+            state: IdState::new(IdSet::new(), A::default(), K::MAX_ID),
+            values: Vec::new(),
+            marker: PhantomData,
+        }
+    }
+
+    #[inline]
+    /// Creates an `IdMap<T, K>` with a custom key type and the specified capacity.
+    pub fn with_capacity_and_key(cap: usize) -> Self {
+        IdMap {
+            state: IdState::new(IdSet::with_capacity(cap), A::default(), K::MAX_ID),
+            values: Vec::with_capacity(cap),
+            marker: PhantomData,
+        }
+    }
+
+    #[inline]
+    /// Removes all values from the map.
+    pub fn clear(&mut self) {
+        self.drop_values();
+        self.state.clear();
+    }
+
+    #[inline]
+    /// Returns the id that a subsequent call to insert() will produce.
+    ///
+    /// # Panics
+    ///
+    /// Panics if every id up to `K::MAX_ID` is in the map, so that the next id does not fit in a
+    /// `K`. Use `try_next_id` to check for a full map instead.
+    pub fn next_id(&self) -> K {
+        K::from_id(self.state.next_id())
+    }
+
+    #[inline]
+    /// Returns the id that a subsequent call to insert() will produce, or `None` if it is greater
+    /// than the maximum id of the map.
+    pub fn try_next_id(&self) -> Option<K> {
+        self.state.try_next_id().map(K::from_id)
+    }
+
+    #[inline]
+    /// Returns the largest id which may be inserted into the map.
+    pub fn max_id(&self) -> K {
+        K::from_id(self.state.max_id)
+    }
+
+    #[inline]
+    /// Sets the largest id which may be inserted into the map, which is `K::MAX_ID` by default.
+    ///
+    /// Inserting a larger id makes the `try_` methods return an error, and the other methods
+    /// panic, rather than growing the map to hold it. Ids already in the map are not affected.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use id_map::{IdMap, TryReserveError};
+    /// #
+    /// let mut map = IdMap::new();
+    /// map.set_max_id(1000);
+    /// assert_eq!(map.try_insert_at(1000, "a"), Ok(None));
+    /// assert_eq!(map.try_insert_at(1001, "b"), Err(TryReserveError::IdTooLarge(1001)));
+    /// ```
+    pub fn set_max_id(&mut self, max_id: K) {
+        self.state.max_id = max_id.to_id();
+    }
+
+    #[inline]
+    /// Returns the number of id-value pairs in the map.
+    pub fn len(&self) -> usize {
+        self.state.ids.len()
+    }
+
+    #[inline]
+    /// Returns true if the map contains no values.
+    pub fn is_empty(&self) -> bool {
+        self.state.ids.is_empty()
+    }
+
+    #[inline]
+    /// Returns the number of id-value pairs the map can hold before reallocating.
+    pub fn capacity(&self) -> usize {
+        cmp::min(self.state.ids.capacity(), self.values.capacity())
+    }
+
+    #[inline]
+    /// Resizes the map such that that `capacity() >= cap`.
+    pub fn reserve(&mut self, cap: usize) {
+        self.state.ids.reserve(cap);
+        self.values.reserve(cap.saturating_sub(self.values.len()));
+    }
+
+    #[inline]
+    /// Tries to resize the map such that `capacity() >= cap`, returning an error if the
+    /// allocation fails.
+    ///
+    /// Only the allocation for the values is fallible. The id set is grown afterwards with
+    /// `IdSet::reserve`, which aborts on allocation failure, but it needs only one bit per id.
+    pub fn try_reserve(&mut self, cap: usize) -> Result<(), TryReserveError> {
+        self.values
+            .try_reserve(cap.saturating_sub(self.values.len()))?;
+        self.state.ids.try_reserve(cap)
+    }
+
+    #[inline]
+    /// Resizes the map to minimize allocated memory.
+    pub fn shrink_to_fit(&mut self) {
+        // Slots after the largest id are always empty.
+        let len = raw_ids(&self.state.ids).next_back().map_or(0, |id| id + 1);
+        self.state.ids.shrink_to_fit();
+        self.values.truncate(len);
+        self.values.shrink_to_fit();
+    }
+
+    /// Moves all values to the ids `0..len()`, keeping their relative order, and returns the
+    /// mapping from old ids to new ids. Afterwards `shrink_to_fit()` can reclaim all the space
+    /// used by removed values.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use id_map::IdMap;
+    /// #
+    /// let mut map: IdMap<_> = (0..5).collect();
+    /// map.remove(1);
+    /// map.remove(2);
+    ///
+    /// let remap = map.compact();
+    /// assert_eq!(remap.get(3), Some(1));
+    /// assert_eq!(remap.get(1), None);
+    /// assert_eq!(map[1], 3);
+    /// assert_eq!(map.next_id(), 3);
+    /// ```
+    pub fn compact(&mut self) -> IdRemap<K> {
+        let values = &mut self.values;
+        let remap = self.state.compact(|old, new| {
+            if new != old {
+                // Every slot before old has already been moved from or filled.
+                values.swap(new, old);
+            }
+        });
+        self.values.truncate(self.state.ids.len());
+        remap
+    }
+
+    #[inline]
+    /// Returns a reference to the set of valid ids.
+    pub fn as_set(&self) -> &IdSet {
+        &self.state.ids
+    }
+
+    #[inline]
+    /// Inserts a value into an empty slot in the map and returns its id.
+    pub fn insert(&mut self, val: T) -> K {
+        let id = self.state.next_id();
+        self.insert_vacant(id, val);
+        K::from_id(id)
+    }
+
+    #[inline]
+    /// Inserts a value into an empty slot in the map and returns its id, or returns an error if
+    /// the id is too large or the map cannot grow to hold it.
+    pub fn try_insert(&mut self, val: T) -> Result<K, TryReserveError> {
+        let id = self.state.next_id();
+        self.try_grow(id)?;
+        self.insert_vacant(id, val);
+        Ok(K::from_id(id))
+    }
+
+    #[inline]
+    /// Inserts a value at a specific id, returning the old value if it existed.
+    pub fn insert_at(&mut self, id: K, val: T) -> Option<T> {
+        match self.entry(id) {
+            Entry::Occupied(mut entry) => Some(entry.insert(val)),
+            Entry::Vacant(entry) => {
+                entry.insert(val);
+                None
+            }
+        }
+    }
+
+    /// Inserts a value at a specific id, returning the old value if it existed, or returns an
+    /// error if the id is too large or the map cannot grow to hold it.
+    pub fn try_insert_at(&mut self, id: K, val: T) -> Result<Option<T>, TryReserveError> {
+        if !self.contains(id) {
+            self.try_grow(id.to_id())?;
+        }
+        Ok(self.insert_at(id, val))
+    }
+
+    #[inline]
+    /// Removes an id from the map, returning its value if it was previously in the map.
+    pub fn remove(&mut self, id: K) -> Option<T> {
+        self.remove_id(id.to_id())
+    }
+
+    #[inline]
+    /// If the id has a value, returns it, otherwise inserts a new value.
+    pub fn get_or_insert(&mut self, id: K, val: T) -> &mut T {
+        self.get_or_insert_with(id, || val)
+    }
+
+    #[inline]
+    /// If the id has a value, returns it, otherwise inserts a new value with the provided closure.
+    pub fn get_or_insert_with<F: FnOnce() -> T>(&mut self, id: K, f: F) -> &mut T {
+        self.entry(id).or_insert_with(f)
+    }
+
+    /// If the id has a value, returns it, otherwise inserts a new value with the provided closure,
+    /// or returns an error if the id is too large or the map cannot grow to hold it.
+    pub fn try_get_or_insert_with<F: FnOnce() -> T>(
+        &mut self,
+        id: K,
+        f: F,
+    ) -> Result<&mut T, TryReserveError> {
+        if !self.contains(id) {
+            self.try_grow(id.to_id())?;
+        }
+        Ok(self.get_or_insert_with(id, f))
+    }
+
+    #[inline]
+    /// Returns a vacant entry for the id that a subsequent call to insert() would produce. This
+    /// allows the id to be known before the value is constructed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use id_map::{Id, IdMap};
+    /// #
+    /// struct Node {
+    ///     id: Id,
+    /// }
+    ///
+    /// let mut map = IdMap::new();
+    /// let node = map.vacant_entry().insert_with(|id| Node { id });
+    /// let id = node.id;
+    /// assert_eq!(map[id].id, id);
+    /// ```
+    pub fn vacant_entry(&mut self) -> VacantEntry<'_, T, K, A> {
+        let id = self.state.next_id();
+        VacantEntry::new(self, id)
+    }
+
+    #[inline]
+    /// Gets the entry for the specified id for in-place manipulation.
+    pub fn entry(&mut self, id: K) -> Entry<'_, T, K, A> {
+        let id = id.to_id();
+        if self.state.ids.contains(id) {
+            Entry::Occupied(OccupiedEntry::new(self, id))
+        } else {
+            Entry::Vacant(VacantEntry::new(self, id))
+        }
+    }
+
+    #[inline]
+    /// Removes all ids in the set from the map.
+    pub fn remove_set(&mut self, set: &IdSet) {
+        for id in self.state.ids.intersection(set) {
+            self.state.policy.removed(id);
+            self.values[id] = None;
+        }
+
+        self.state.ids.inplace_difference(set);
+    }
+
+    /// Applies the differences from another map, as returned by `diff`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the diff inserts an id greater than the maximum id of the map. Use `try_apply`
+    /// for a diff which cannot be trusted.
+    pub fn apply(&mut self, diff: IdMapDiff<T, K>) {
+        diff.apply_to(self);
+    }
+
+    /// Applies the differences from another map, as returned by `diff`, or returns an error
+    /// without changing the map if the diff inserts an id which is too large or the map cannot
+    /// grow to hold it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use id_map::{IdMap, IdMapDiff, TryReserveError};
+    /// #
+    /// let mut map: IdMap<_> = vec!["a", "b"].into_iter().collect();
+    /// map.set_max_id(100);
+    ///
+    /// let mut diff = IdMapDiff::default();
+    /// diff.inserted.push((1_000_000, "c"));
+    /// assert_eq!(map.try_apply(diff), Err(TryReserveError::IdTooLarge(1_000_000)));
+    /// assert_eq!(map.len(), 2);
+    /// ```
+    pub fn try_apply(&mut self, diff: IdMapDiff<T, K>) -> Result<(), TryReserveError> {
+        if let Some(id) = diff.max_new_id(&self.state.ids) {
+            self.try_grow(id)?;
+        }
+        self.apply(diff);
+        Ok(())
+    }
+
+    #[inline]
+    /// Remove all values not satisfying the predicate.
+    pub fn retain<F: FnMut(K, &T) -> bool>(&mut self, mut pred: F) {
+        let values = &mut self.values;
+        self.state.retain(|id| {
+            if pred(K::from_id(id), values[id].as_ref().unwrap()) {
+                true
+            } else {
+                values[id] = None;
+                false
+            }
+        })
+    }
+
+    #[inline]
+    /// Removes all id-value pairs from the map, returning them as an iterator in order of
+    /// increasing id. The capacity of the map is unchanged.
+    pub fn drain(&mut self) -> Drain<'_, T, K, A> {
+        Drain::new(self, 0, Id::MAX)
+    }
+
+    #[inline]
+    /// Removes all id-value pairs with ids in the given range from the map, returning them as an
+    /// iterator in order of increasing id.
+    pub fn drain_range<R: RangeBounds<K>>(&mut self, range: R) -> Drain<'_, T, K, A> {
+        let (start, end) = id_range(range);
+        Drain::new(self, start, end)
+    }
+
+    #[inline]
+    /// Returns an iterator which removes and yields the id-value pairs for which the predicate
+    /// returns true, in order of increasing id. Pairs are only visited as the iterator is
+    /// advanced.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use id_map::IdMap;
+    /// #
+    /// let mut map: IdMap<_> = (0..6).collect();
+    /// let odd: Vec<_> = map.extract_if(|_, &mut val| val % 2 != 0).collect();
+    /// assert_eq!(odd, vec![(1, 1), (3, 3), (5, 5)]);
+    /// assert_eq!(map.len(), 3);
+    /// ```
+    pub fn extract_if<F: FnMut(K, &mut T) -> bool>(
+        &mut self,
+        pred: F,
+    ) -> ExtractIf<'_, T, F, K, A> {
+        ExtractIf::new(self, pred)
+    }
+
+    #[inline]
+    /// Returns true if the map contains a value for the specified id.
+    pub fn contains(&self, id: K) -> bool {
+        self.state.ids.contains(id.to_id())
+    }
+
+    #[inline]
+    /// Returns a reference to the value at the specified id if it is in the map.
+    pub fn get(&self, id: K) -> Option<&T> {
+        let id = id.to_id();
+        if self.state.ids.contains(id) {
+            Some(self.values[id].as_ref().unwrap())
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    /// Returns a mutable reference to the value at the specified id if it is in the map.
+    pub fn get_mut(&mut self, id: K) -> Option<&mut T> {
+        let id = id.to_id();
+        if self.state.ids.contains(id) {
+            Some(self.values[id].as_mut().unwrap())
+        } else {
+            None
+        }
+    }
+
+    /// Returns mutable references to the values at several distinct ids at once.
+    ///
+    /// Returns an error if any id is not in the map, or if the same id is requested more than
+    /// once.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use id_map::{GetManyError, IdMap};
+    /// #
+    /// let mut map: IdMap<_> = vec![1, 2, 3].into_iter().collect();
+    ///
+    /// let [a, c] = map.get_many_mut([0, 2]).unwrap();
+    /// std::mem::swap(a, c);
+    /// assert_eq!(map.values().cloned().collect::<Vec<_>>(), vec![3, 2, 1]);
+    ///
+    /// assert_eq!(map.get_many_mut([1, 5]), Err(GetManyError::Missing(5)));
+    /// assert_eq!(map.get_many_mut([1, 1]), Err(GetManyError::Duplicate(1)));
+    /// ```
+    pub fn get_many_mut<const N: usize>(
+        &mut self,
+        ids: [K; N],
+    ) -> Result<[&mut T; N], GetManyError<K>> {
+        // Visit the ids in increasing order, so duplicates are adjacent and the values can be
+        // split off in a single pass.
+        let mut order: [usize; N] = core::array::from_fn(|i| i);
+        order.sort_unstable_by_key(|&i| ids[i].to_id());
+        for (n, &i) in order.iter().enumerate() {
+            if !self.contains(ids[i]) {
+                return Err(GetManyError::Missing(ids[i]));
+            }
+            if n > 0 && ids[order[n - 1]].to_id() == ids[i].to_id() {
+                return Err(GetManyError::Duplicate(ids[i]));
+            }
+        }
+
+        let mut vals: [Option<&mut T>; N] = core::array::from_fn(|_| None);
+        let mut slots = self.values.iter_mut();
+        let mut front = 0;
+        for &i in &order {
+            let id = ids[i].to_id();
+            vals[i] = slots.nth(id - front).unwrap().as_mut();
+            front = id + 1;
+        }
+        Ok(vals.map(Option::unwrap))
+    }
+
+    #[inline]
+    /// Returns mutable references to the values at two distinct ids at once.
+    ///
+    /// Returns an error if either id is not in the map, or if the ids are equal.
+    pub fn get2_mut(&mut self, a: K, b: K) -> Result<(&mut T, &mut T), GetManyError<K>> {
+        let [a, b] = self.get_many_mut([a, b])?;
+        Ok((a, b))
+    }
+
+    #[inline]
+    /// An iterator over ids, in increasing order.
+    pub fn ids(&self) -> Ids<'_, K> {
+        Ids::new(raw_ids(&self.state.ids))
+    }
+
+    #[inline]
+    /// An iterator over values, in order of increasing id.
+    pub fn values(&self) -> Values<'_, T> {
+        Values {
+            ids: raw_ids(&self.state.ids),
+            values: &self.values,
+        }
+    }
+
+    #[inline]
+    /// A mutable iterator over values, in order of increasing id.
+    pub fn values_mut(&mut self) -> ValuesMut<'_, T> {
+        ValuesMut {
+            iter: IterMut::new(raw_ids(&self.state.ids), 0, &mut self.values),
+        }
+    }
+
+    #[inline]
+    /// An iterator over id-value pairs, in order of increasing id.
+    pub fn iter(&self) -> Iter<'_, T, K> {
+        Iter {
+            ids: raw_ids(&self.state.ids),
+            values: &self.values,
+            marker: PhantomData,
+        }
+    }
+
+    #[inline]
+    /// A mutable iterator over id-value pairs, in order of increasing id.
+    pub fn iter_mut(&mut self) -> IterMut<'_, T, K> {
+        IterMut::new(raw_ids(&self.state.ids), 0, &mut self.values)
+    }
+
+    #[inline]
+    /// An iterator over the ids in the given range, in increasing order. Only the part of the map
+    /// covered by the range is visited.
+    pub fn ids_in<R: RangeBounds<K>>(&self, range: R) -> Ids<'_, K> {
+        let (start, end) = id_range(range);
+        Ids::new(range_ids(&self.state.ids, start, end))
+    }
+
+    #[inline]
+    /// An iterator over id-value pairs with ids in the given range, in order of increasing id.
+    /// Only the part of the map covered by the range is visited.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use id_map::IdMap;
+    /// #
+    /// let map: IdMap<_> = (0..100).collect();
+    /// let range: Vec<_> = map.range(10..13).map(|(id, &val)| (id, val)).collect();
+    /// assert_eq!(range, vec![(10, 10), (11, 11), (12, 12)]);
+    /// ```
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Iter<'_, T, K> {
+        let (start, end) = id_range(range);
+        Iter {
+            ids: range_ids(&self.state.ids, start, end),
+            values: &self.values,
+            marker: PhantomData,
+        }
+    }
+
+    #[inline]
+    /// A mutable iterator over id-value pairs with ids in the given range, in order of increasing
+    /// id. Only the part of the map covered by the range is visited.
+    pub fn range_mut<R: RangeBounds<K>>(&mut self, range: R) -> IterMut<'_, T, K> {
+        let (start, end) = id_range(range);
+        IterMut::new(range_ids(&self.state.ids, start, end), 0, &mut self.values)
+    }
+
+    #[inline]
+    /// An iterator over id-value pairs with ids greater than or equal to `id`, in order of
+    /// increasing id.
+    pub fn iter_from(&self, id: K) -> Iter<'_, T, K> {
+        self.range(id..)
+    }
+
+    #[cfg(all(test, feature = "std"))]
+    pub(crate) fn assert_invariant(&self) {
+        self.state.assert_invariant();
+        // values.len() should be an upper bound on ids.
+        for id in &self.state.ids {
+            assert!(id < self.values.len())
+        }
+    }
+
+    /// Clear the values vec.
+    fn drop_values(&mut self) {
+        for id in &self.state.ids {
+            self.values[id] = None;
+        }
+    }
+
+    /// Checks that `id` may be inserted and allocates space for it and its value.
+    fn try_grow(&mut self, id: Id) -> Result<(), TryReserveError> {
+        self.state.try_reserve_id(id)?;
+        if self.values.len() <= id {
+            self.values
+                .try_reserve((id - self.values.len()).saturating_add(1))?;
+        }
+        Ok(())
+    }
+}
+
+impl<T, K: Key, A: AllocPolicy> RawMap<T> for IdMap<T, K, A> {
+    #[inline]
+    fn id_set(&self) -> &IdSet {
+        &self.state.ids
+    }
+
+    #[inline]
+    fn value(&self, id: Id) -> &T {
+        self.values[id].as_ref().unwrap()
+    }
+
+    #[inline]
+    fn value_mut(&mut self, id: Id) -> &mut T {
+        self.values[id].as_mut().unwrap()
+    }
+
+    fn insert_vacant(&mut self, id: Id, val: T) -> &mut T {
+        self.state.insert(id);
+        if self.values.len() < id + 1 {
+            self.values.resize_with(id + 1, Default::default);
+        }
+        self.values[id] = Some(val);
+        self.values[id].as_mut().unwrap()
+    }
+
+    #[inline]
+    fn remove_id(&mut self, id: Id) -> Option<T> {
+        if self.state.remove(id) {
+            self.values[id].take()
+        } else {
+            None
+        }
+    }
+}
+
+impl<T: Clone, K: Key, A: AllocPolicy> IdMap<T, K, A> {
+    #[inline]
+    /// Returns the differences between this map and `other`, comparing values with `PartialEq`.
+    /// Applying the result to this map makes it equal to `other`.
+    pub fn diff<B: AllocPolicy>(&self, other: &IdMap<T, K, B>) -> IdMapDiff<T, K>
+    where
+        T: PartialEq,
+    {
+        self.diff_by(other, |a, b| a == b)
+    }
+
+    /// Returns the differences between this map and `other`, using a closure to decide whether
+    /// two values are equal. Applying the result to this map makes it equal to `other`.
+    pub fn diff_by<B, F>(&self, other: &IdMap<T, K, B>, eq: F) -> IdMapDiff<T, K>
+    where
+        B: AllocPolicy,
+        F: FnMut(&T, &T) -> bool,
+    {
+        IdMapDiff::between(self, other, eq)
+    }
+}
+
+/// Converts a range of keys to the equivalent half-open range of raw ids.
+pub(crate) fn id_range<K: Key, R: RangeBounds<K>>(range: R) -> (Id, Id) {
+    let start = match range.start_bound() {
+        Bound::Included(id) => id.to_id(),
+        Bound::Excluded(id) => id.to_id().saturating_add(1),
+        Bound::Unbounded => 0,
+    };
+    let end = match range.end_bound() {
+        Bound::Included(id) => id.to_id().saturating_add(1),
+        Bound::Excluded(id) => id.to_id(),
+        Bound::Unbounded => Id::MAX,
+    };
+    (start, end)
+}
+
+impl<T: fmt::Debug, K: Key, A: AllocPolicy> fmt::Debug for IdMap<T, K, A> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{{")?;
+        let mut iter = self.iter();
+        if let Some((id, val)) = iter.next() {
+            write!(f, "{:?}: {:?}", id, val)?;
+            for (id, val) in iter {
+                write!(f, ", {:?}: {:?}", id, val)?;
+            }
+        }
+        write!(f, "}}")
+    }
+}
+
+impl<T, K: Key, A: AllocPolicy> Default for IdMap<T, K, A> {
+    #[inline]
+    fn default() -> Self {
+        IdMap::with_key()
+    }
+}
+
+impl<T: Eq, K, A> Eq for IdMap<T, K, A> {}
+
+impl<T: PartialEq, K, A> PartialEq for IdMap<T, K, A> {
+    fn eq(&self, other: &Self) -> bool {
+        self.state.ids == other.state.ids
+            && self
+                .state
+                .ids
+                .iter()
+                .zip(&other.state.ids)
+                .all(|(l, r)| self.values[l].as_ref().unwrap() == other.values[r].as_ref().unwrap())
+    }
+}
+
+impl<T, K: Key, A: AllocPolicy> Extend<T> for IdMap<T, K, A> {
+    #[inline]
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for val in iter {
+            self.insert(val);
+        }
+    }
+}
+
+impl<T> FromIterator<T> for IdMap<T> {
+    #[inline]
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let values = Vec::from_iter(iter.into_iter().map(Some));
+        let ids = IdSet::new_filled(values.len());
+        let policy = LowestFree {
+            space: values.len(),
+        };
+        IdMap {
+            state: IdState::new(ids, policy, Id::MAX),
+            values,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T, K: Key, A: AllocPolicy> FromIterator<(K, T)> for IdMap<T, K, A> {
+    #[inline]
+    fn from_iter<I: IntoIterator<Item = (K, T)>>(iter: I) -> Self {
+        let iter = iter.into_iter();
+        let mut map = IdMap::with_capacity_and_key(iter.size_hint().0);
+        for (id, val) in iter {
+            map.insert_at(id, val);
+        }
+        map
+    }
+}
+
+impl<'a, T, K: Key, A: AllocPolicy> IntoIterator for &'a IdMap<T, K, A> {
+    type Item = (K, &'a T);
+    type IntoIter = Iter<'a, T, K>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, T, K: Key, A: AllocPolicy> IntoIterator for &'a mut IdMap<T, K, A> {
+    type Item = (K, &'a mut T);
+    type IntoIter = IterMut<'a, T, K>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+impl<T, K: Key, A> IntoIterator for IdMap<T, K, A> {
+    type Item = (K, T);
+    type IntoIter = IntoIter<T, K>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        let len = self.state.ids.len();
+        let ids = BlockIds::new(self.state.ids.into_blocks().into_iter(), 0, len);
+        IntoIter {
+            ids,
+            front: 0,
+            back: self.values.len(),
+            values: self.values.into_iter(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T, K: Key, A: AllocPolicy> Index<K> for IdMap<T, K, A> {
+    type Output = T;
+
+    #[inline]
+    fn index(&self, id: K) -> &Self::Output {
+        let id = id.to_id();
+        assert!(self.state.ids.contains(id), "id {} out of bounds", id);
+        self.values[id].as_ref().unwrap()
+    }
+}
+
+impl<T, K: Key, A: AllocPolicy> IndexMut<K> for IdMap<T, K, A> {
+    #[inline]
+    fn index_mut(&mut self, id: K) -> &mut Self::Output {
+        let id = id.to_id();
+        assert!(self.state.ids.contains(id), "id {} out of bounds", id);
+        self.values[id].as_mut().unwrap()
+    }
+}
+
+#[derive(Clone, Debug)]
+/// An iterator over all ids, in increasing order.
+pub struct Ids<'a, K = Id> {
+    ids: RawIds<'a>,
+    marker: PhantomData<K>,
+}
+
+impl<'a, K> Ids<'a, K> {
+    #[inline]
+    pub(crate) fn new(ids: RawIds<'a>) -> Self {
+        Ids {
+            ids,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, K: Key> Iterator for Ids<'a, K> {
+    type Item = K;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.ids.next().map(K::from_id)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.ids.size_hint()
+    }
+}
+
+impl<'a, K: Key> DoubleEndedIterator for Ids<'a, K> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.ids.next_back().map(K::from_id)
+    }
+}
+
+impl<'a, K: Key> ExactSizeIterator for Ids<'a, K> {
+    #[inline]
+    fn len(&self) -> usize {
+        self.ids.len()
+    }
+}
+
+impl<'a, K: Key> FusedIterator for Ids<'a, K> {}
+
+#[derive(Debug)]
+/// An iterator over all values, in order of increasing id.
+pub struct Values<'a, T: 'a> {
+    ids: RawIds<'a>,
+    values: &'a [Option<T>],
+}
+
+impl<'a, T: 'a> Iterator for Values<'a, T> {
+    type Item = &'a T;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.ids.next().map(|id| self.values[id].as_ref().unwrap())
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.ids.size_hint()
+    }
+}
+
+impl<'a, T: 'a> DoubleEndedIterator for Values<'a, T> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.ids
+            .next_back()
+            .map(|id| self.values[id].as_ref().unwrap())
+    }
+}
+
+impl<'a, T: 'a> ExactSizeIterator for Values<'a, T> {
+    #[inline]
+    fn len(&self) -> usize {
+        self.ids.len()
+    }
+}
+
+impl<'a, T: 'a> FusedIterator for Values<'a, T> {}
+
+impl<'a, T: 'a> Clone for Values<'a, T> {
+    #[inline]
+    fn clone(&self) -> Self {
+        Values {
+            ids: self.ids.clone(),
+            values: self.values,
+        }
+    }
+}
+
+#[derive(Debug)]
+/// A mutable iterator over all values, in order of increasing id.
+pub struct ValuesMut<'a, T: 'a> {
+    iter: IterMut<'a, T>,
+}
+
+impl<'a, T: 'a> Iterator for ValuesMut<'a, T> {
+    type Item = &'a mut T;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|(_, val)| val)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<'a, T: 'a> DoubleEndedIterator for ValuesMut<'a, T> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter.next_back().map(|(_, val)| val)
+    }
+}
+
+impl<'a, T: 'a> ExactSizeIterator for ValuesMut<'a, T> {
+    #[inline]
+    fn len(&self) -> usize {
+        self.iter.len()
+    }
+}
+
+impl<'a, T: 'a> FusedIterator for ValuesMut<'a, T> {}
+
+#[derive(Debug)]
+/// An iterator over id-value pairs, in order of increasing id.
+pub struct Iter<'a, T: 'a, K = Id> {
+    ids: RawIds<'a>,
+    values: &'a [Option<T>],
+    marker: PhantomData<K>,
+}
+
+impl<'a, T: 'a, K: Key> Iterator for Iter<'a, T, K> {
+    type Item = (K, &'a T);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.ids
+            .next()
+            .map(|id| (K::from_id(id), self.values[id].as_ref().unwrap()))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.ids.size_hint()
+    }
+}
+
+impl<'a, T: 'a, K: Key> DoubleEndedIterator for Iter<'a, T, K> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.ids
+            .next_back()
+            .map(|id| (K::from_id(id), self.values[id].as_ref().unwrap()))
+    }
+}
+
+impl<'a, T: 'a, K: Key> ExactSizeIterator for Iter<'a, T, K> {
+    #[inline]
+    fn len(&self) -> usize {
+        self.ids.len()
+    }
+}
+
+impl<'a, T: 'a, K: Key> FusedIterator for Iter<'a, T, K> {}
+
+impl<'a, T: 'a, K> Clone for Iter<'a, T, K> {
+    #[inline]
+    fn clone(&self) -> Self {
+        Iter {
+            ids: self.ids.clone(),
+            values: self.values,
+            marker: PhantomData,
+        }
+    }
+}
+
+#[derive(Debug)]
+/// A mutable iterator over id-value pairs, in order of increasing id.
+pub struct IterMut<'a, T: 'a, K = Id> {
+    ids: RawIds<'a>,
+    // The ids of the next slot at either end of values.
+    front: Id,
+    back: Id,
+    values: slice::IterMut<'a, Option<T>>,
+    marker: PhantomData<K>,
+}
+
+impl<'a, T: 'a, K> IterMut<'a, T, K> {
+    #[inline]
+    /// Creates an iterator over the given ids, where the first slot of values has id `base`.
+    pub(crate) fn new(ids: RawIds<'a>, base: Id, values: &'a mut [Option<T>]) -> Self {
+        IterMut {
+            ids,
+            front: base,
+            back: base + values.len(),
+            values: values.iter_mut(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, T: 'a, K: Key> Iterator for IterMut<'a, T, K> {
+    type Item = (K, &'a mut T);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        let id = self.ids.next()?;
+        let n = id - self.front;
+        self.front = id + 1;
+
+        Some((
+            K::from_id(id),
+            self.values.nth(n).unwrap().as_mut().expect("id not in map"),
+        ))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.ids.size_hint()
+    }
+}
+
+impl<'a, T: 'a, K: Key> DoubleEndedIterator for IterMut<'a, T, K> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let id = self.ids.next_back()?;
+        let n = self.back - id - 1;
+        self.back = id;
+
+        Some((
+            K::from_id(id),
+            self.values
+                .nth_back(n)
+                .unwrap()
+                .as_mut()
+                .expect("id not in map"),
+        ))
+    }
+}
+
+impl<'a, T: 'a, K: Key> ExactSizeIterator for IterMut<'a, T, K> {
+    #[inline]
+    fn len(&self) -> usize {
+        self.ids.len()
+    }
+}
+
+impl<'a, T: 'a, K: Key> FusedIterator for IterMut<'a, T, K> {}
+
+#[derive(Clone, Debug)]
+/// A consuming iterator over id-value pairs, in order of increasing id.
+pub struct IntoIter<T, K = Id> {
+    ids: BlockIds<vec::IntoIter<Block>>,
+    // The ids of the next slot at either end of values.
+    front: Id,
+    back: Id,
+    values: vec::IntoIter<Option<T>>,
+    marker: PhantomData<K>,
+}
+
+impl<T, K: Key> Iterator for IntoIter<T, K> {
+    type Item = (K, T);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        let id = self.ids.next()?;
+        let n = id - self.front;
+        self.front = id + 1;
+
+        Some((
+            K::from_id(id),
+            self.values.nth(n).unwrap().expect("id not in map"),
+        ))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.ids.size_hint()
+    }
+}
+
+impl<T, K: Key> DoubleEndedIterator for IntoIter<T, K> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let id = self.ids.next_back()?;
+        let n = self.back - id - 1;
+        self.back = id;
+
+        Some((
+            K::from_id(id),
+            self.values.nth_back(n).unwrap().expect("id not in map"),
+        ))
+    }
+}
+
+impl<T, K: Key> ExactSizeIterator for IntoIter<T, K> {
+    #[inline]
+    fn len(&self) -> usize {
+        self.ids.len()
+    }
+}
+
+impl<T, K: Key> FusedIterator for IntoIter<T, K> {}
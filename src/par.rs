@@ -0,0 +1,278 @@
+//! Parallel iterators over maps, using rayon.
+//!
+//! The id space is split into chunks aligned with the blocks of the underlying `IdSet`, so empty
+//! blocks can be skipped without touching the values they cover.
+
+use std::fmt;
+
+use rayon::iter::plumbing::UnindexedConsumer;
+use rayon::prelude::*;
+
+use {AllocPolicy, Block, Id, IdMap, Key, LowestFree, BITS};
+
+impl<T, K: Key, A: AllocPolicy> IdMap<T, K, A> {
+    #[inline]
+    /// A parallel iterator over id-value pairs.
+    pub fn par_iter(&self) -> ParIter<'_, T, K, A> {
+        ParIter { map: self }
+    }
+
+    #[inline]
+    /// A mutable parallel iterator over id-value pairs.
+    pub fn par_iter_mut(&mut self) -> ParIterMut<'_, T, K, A> {
+        ParIterMut { map: self }
+    }
+
+    #[inline]
+    /// A parallel iterator over values.
+    pub fn par_values(&self) -> ParValues<'_, T, K, A> {
+        ParValues { map: self }
+    }
+
+    #[inline]
+    /// A mutable parallel iterator over values.
+    pub fn par_values_mut(&mut self) -> ParValuesMut<'_, T, K, A> {
+        ParValuesMut { map: self }
+    }
+
+    /// Remove all values not satisfying the predicate, evaluating it in parallel.
+    pub fn par_retain<F>(&mut self, pred: F)
+    where
+        T: Send,
+        K: Send + Sync,
+        F: Fn(K, &T) -> bool + Sync,
+    {
+        let pred = &pred;
+        let removed: Vec<Id> = chunks_mut(self.state.ids.as_blocks(), &mut self.values)
+            .flat_map_iter(|(base, chunk)| {
+                chunk
+                    .iter_mut()
+                    .enumerate()
+                    .filter_map(move |(offset, slot)| {
+                        let id = base + offset;
+                        match *slot {
+                            Some(ref val) if !pred(K::from_id(id), val) => Some(id),
+                            _ => None,
+                        }
+                    })
+            })
+            .collect();
+
+        for id in removed {
+            self.state.remove(id);
+            self.values[id] = None;
+        }
+    }
+}
+
+/// Parallel iterator over the non-empty chunks of values, paired with the id of their first slot.
+fn chunks<'a, T: Sync>(
+    blocks: &'a [Block],
+    values: &'a [Option<T>],
+) -> impl IndexedParallelIterator<Item = (Id, &'a [Option<T>])> + 'a {
+    values
+        .par_chunks(BITS)
+        .zip(blocks.par_iter())
+        .enumerate()
+        .map(|(idx, (chunk, &block))| (idx * BITS, if block == 0 { &[][..] } else { chunk }))
+}
+
+/// Mutable parallel iterator over the non-empty chunks of values, paired with the id of their
+/// first slot.
+fn chunks_mut<'a, T: Send>(
+    blocks: &'a [Block],
+    values: &'a mut [Option<T>],
+) -> impl IndexedParallelIterator<Item = (Id, &'a mut [Option<T>])> + 'a {
+    values
+        .par_chunks_mut(BITS)
+        .zip(blocks.par_iter())
+        .enumerate()
+        .map(|(idx, (chunk, &block))| (idx * BITS, if block == 0 { &mut [][..] } else { chunk }))
+}
+
+/// A parallel iterator over id-value pairs.
+pub struct ParIter<'a, T: 'a, K: 'a = Id, A: 'a = LowestFree> {
+    map: &'a IdMap<T, K, A>,
+}
+
+impl<'a, T: fmt::Debug + 'a, K: Key, A: AllocPolicy> fmt::Debug for ParIter<'a, T, K, A> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("ParIter").field(&self.map).finish()
+    }
+}
+
+impl<'a, T: Sync + 'a, K: Key + Send + Sync, A: AllocPolicy + Send + Sync> ParallelIterator
+    for ParIter<'a, T, K, A>
+{
+    type Item = (K, &'a T);
+
+    fn drive_unindexed<C: UnindexedConsumer<Self::Item>>(self, consumer: C) -> C::Result {
+        chunks(self.map.state.ids.as_blocks(), &self.map.values)
+            .flat_map_iter(|(base, chunk)| {
+                chunk.iter().enumerate().filter_map(move |(offset, slot)| {
+                    slot.as_ref().map(|val| (K::from_id(base + offset), val))
+                })
+            })
+            .drive_unindexed(consumer)
+    }
+}
+
+impl<'a, T: 'a, K: 'a, A: 'a> Clone for ParIter<'a, T, K, A> {
+    #[inline]
+    fn clone(&self) -> Self {
+        ParIter { map: self.map }
+    }
+}
+
+/// A mutable parallel iterator over id-value pairs.
+pub struct ParIterMut<'a, T: 'a, K: 'a = Id, A: 'a = LowestFree> {
+    map: &'a mut IdMap<T, K, A>,
+}
+
+impl<'a, T: fmt::Debug + 'a, K: Key, A: AllocPolicy> fmt::Debug for ParIterMut<'a, T, K, A> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("ParIterMut").field(&self.map).finish()
+    }
+}
+
+impl<'a, T: Send + 'a, K: Key + Send + Sync, A: AllocPolicy + Send + Sync> ParallelIterator
+    for ParIterMut<'a, T, K, A>
+{
+    type Item = (K, &'a mut T);
+
+    fn drive_unindexed<C: UnindexedConsumer<Self::Item>>(self, consumer: C) -> C::Result {
+        let IdMap {
+            ref state,
+            ref mut values,
+            ..
+        } = *self.map;
+        chunks_mut(state.ids.as_blocks(), values)
+            .flat_map_iter(|(base, chunk)| {
+                chunk
+                    .iter_mut()
+                    .enumerate()
+                    .filter_map(move |(offset, slot)| {
+                        slot.as_mut().map(|val| (K::from_id(base + offset), val))
+                    })
+            })
+            .drive_unindexed(consumer)
+    }
+}
+
+/// A parallel iterator over values.
+pub struct ParValues<'a, T: 'a, K: 'a = Id, A: 'a = LowestFree> {
+    map: &'a IdMap<T, K, A>,
+}
+
+impl<'a, T: fmt::Debug + 'a, K: Key, A: AllocPolicy> fmt::Debug for ParValues<'a, T, K, A> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("ParValues").field(&self.map).finish()
+    }
+}
+
+impl<'a, T: Sync + 'a, K: Key + Send + Sync, A: AllocPolicy + Send + Sync> ParallelIterator
+    for ParValues<'a, T, K, A>
+{
+    type Item = &'a T;
+
+    fn drive_unindexed<C: UnindexedConsumer<Self::Item>>(self, consumer: C) -> C::Result {
+        ParIter { map: self.map }
+            .map(|(_, val)| val)
+            .drive_unindexed(consumer)
+    }
+}
+
+impl<'a, T: 'a, K: 'a, A: 'a> Clone for ParValues<'a, T, K, A> {
+    #[inline]
+    fn clone(&self) -> Self {
+        ParValues { map: self.map }
+    }
+}
+
+/// A mutable parallel iterator over values.
+pub struct ParValuesMut<'a, T: 'a, K: 'a = Id, A: 'a = LowestFree> {
+    map: &'a mut IdMap<T, K, A>,
+}
+
+impl<'a, T: fmt::Debug + 'a, K: Key, A: AllocPolicy> fmt::Debug for ParValuesMut<'a, T, K, A> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("ParValuesMut").field(&self.map).finish()
+    }
+}
+
+impl<'a, T: Send + 'a, K: Key + Send + Sync, A: AllocPolicy + Send + Sync> ParallelIterator
+    for ParValuesMut<'a, T, K, A>
+{
+    type Item = &'a mut T;
+
+    fn drive_unindexed<C: UnindexedConsumer<Self::Item>>(self, consumer: C) -> C::Result {
+        ParIterMut { map: self.map }
+            .map(|(_, val)| val)
+            .drive_unindexed(consumer)
+    }
+}
+
+/// A consuming parallel iterator over id-value pairs.
+pub struct IntoParIter<T, K = Id, A = LowestFree> {
+    map: IdMap<T, K, A>,
+}
+
+impl<T: fmt::Debug, K: Key, A: AllocPolicy> fmt::Debug for IntoParIter<T, K, A> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("IntoParIter").field(&self.map).finish()
+    }
+}
+
+impl<T: Send, K: Key + Send + Sync, A: Send> ParallelIterator for IntoParIter<T, K, A> {
+    type Item = (K, T);
+
+    fn drive_unindexed<C: UnindexedConsumer<Self::Item>>(self, consumer: C) -> C::Result {
+        let IdMap {
+            state, mut values, ..
+        } = self.map;
+        chunks_mut(state.ids.as_blocks(), &mut values)
+            .flat_map_iter(|(base, chunk)| {
+                chunk
+                    .iter_mut()
+                    .enumerate()
+                    .filter_map(move |(offset, slot)| {
+                        slot.take().map(|val| (K::from_id(base + offset), val))
+                    })
+            })
+            .drive_unindexed(consumer)
+    }
+}
+
+impl<'a, T: Sync + 'a, K: Key + Send + Sync, A: AllocPolicy + Send + Sync> IntoParallelIterator
+    for &'a IdMap<T, K, A>
+{
+    type Item = (K, &'a T);
+    type Iter = ParIter<'a, T, K, A>;
+
+    #[inline]
+    fn into_par_iter(self) -> Self::Iter {
+        self.par_iter()
+    }
+}
+
+impl<'a, T: Send + 'a, K: Key + Send + Sync, A: AllocPolicy + Send + Sync> IntoParallelIterator
+    for &'a mut IdMap<T, K, A>
+{
+    type Item = (K, &'a mut T);
+    type Iter = ParIterMut<'a, T, K, A>;
+
+    #[inline]
+    fn into_par_iter(self) -> Self::Iter {
+        self.par_iter_mut()
+    }
+}
+
+impl<T: Send, K: Key + Send + Sync, A: Send> IntoParallelIterator for IdMap<T, K, A> {
+    type Item = (K, T);
+    type Iter = IntoParIter<T, K, A>;
+
+    #[inline]
+    fn into_par_iter(self) -> Self::Iter {
+        IntoParIter { map: self }
+    }
+}
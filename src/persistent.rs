@@ -0,0 +1,781 @@
+//! A persistent map whose clones share storage until they are modified.
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cmp;
+use core::fmt;
+use core::iter::{FromIterator, FusedIterator};
+use core::marker::PhantomData;
+use core::ops::Index;
+
+use {AllocPolicy, Id, IdMap, Key};
+
+// The log2 of the number of slots in each node.
+const BITS: u32 = 5;
+const WIDTH: usize = 1 << BITS;
+
+#[derive(Clone)]
+struct Node<T> {
+    // The number of values in the subtree, which is never zero.
+    len: usize,
+    slots: Slots<T>,
+}
+
+// Clippy only sees the size of the branch variant. A leaf is as large whenever a value is at least
+// pointer-sized.
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone)]
+enum Slots<T> {
+    // The values of a leaf, which is always at level 0.
+    Leaf([Option<T>; WIDTH]),
+    // The children of a branch, which are one level lower. Empty children are None.
+    Branch([Option<Arc<Node<T>>>; WIDTH]),
+}
+
+impl<T> Node<T> {
+    #[inline]
+    fn new(level: u32) -> Self {
+        Node {
+            len: 0,
+            slots: if level == 0 {
+                Slots::Leaf([const { None }; WIDTH])
+            } else {
+                Slots::Branch([const { None }; WIDTH])
+            },
+        }
+    }
+
+    #[inline]
+    fn get(&self, level: u32, id: Id) -> Option<&T> {
+        self.leaf(level, id).ok()?[index(id, 0)].as_ref()
+    }
+
+    /// Returns the values of the leaf holding an id, or the level of the first missing node on
+    /// its path.
+    fn leaf(&self, mut level: u32, id: Id) -> Result<&[Option<T>; WIDTH], u32> {
+        let mut node = self;
+        loop {
+            match node.slots {
+                Slots::Leaf(ref values) => return Ok(values),
+                Slots::Branch(ref children) => {
+                    node = children[index(id, level)].as_ref().ok_or(level - 1)?;
+                    level -= 1;
+                }
+            }
+        }
+    }
+
+    /// Returns the smallest id not in the subtree which is at least `from`, or `None` if every id
+    /// in the subtree from `from` onwards is occupied.
+    fn vacant_from(&self, level: u32, mut from: Id) -> Option<Id> {
+        if self.len == span(level) {
+            return None;
+        }
+        match self.slots {
+            Slots::Leaf(ref values) => values[index(from, 0)..]
+                .iter()
+                .position(Option::is_none)
+                .map(|pos| from + pos),
+            Slots::Branch(ref children) => {
+                for child in &children[index(from, level)..] {
+                    match *child {
+                        Some(ref child) => {
+                            if let Some(id) = child.vacant_from(level - 1, from) {
+                                return Some(id);
+                            }
+                        }
+                        None => return Some(from),
+                    }
+                    // Move to the first id of the next child.
+                    from = (from | (span(level - 1) - 1)) + 1;
+                }
+                None
+            }
+        }
+    }
+
+    #[cfg(all(test, feature = "std"))]
+    fn assert_invariant(&self, level: u32) {
+        let len = match self.slots {
+            Slots::Leaf(ref values) => {
+                assert_eq!(level, 0);
+                values.iter().filter(|v| v.is_some()).count()
+            }
+            Slots::Branch(ref children) => {
+                assert!(level != 0);
+                let mut len = 0;
+                for child in children.iter().flatten() {
+                    child.assert_invariant(level - 1);
+                    len += child.len;
+                }
+                len
+            }
+        };
+        assert!(len != 0);
+        assert_eq!(self.len, len);
+    }
+}
+
+impl<T: Clone> Node<T> {
+    /// Inserts a value into the subtree, copying any shared nodes on the path to it.
+    fn insert(&mut self, level: u32, id: Id, val: T) -> Option<T> {
+        let old = match self.slots {
+            Slots::Leaf(ref mut values) => values[index(id, 0)].replace(val),
+            Slots::Branch(ref mut children) => {
+                let child = children[index(id, level)]
+                    .get_or_insert_with(|| Arc::new(Node::new(level - 1)));
+                Arc::make_mut(child).insert(level - 1, id, val)
+            }
+        };
+        if old.is_none() {
+            self.len += 1;
+        }
+        old
+    }
+
+    /// Returns a mutable reference to a value in the subtree, copying any shared nodes on the
+    /// path to it.
+    fn get_mut(&mut self, level: u32, id: Id) -> Option<&mut T> {
+        match self.slots {
+            Slots::Leaf(ref mut values) => values[index(id, 0)].as_mut(),
+            Slots::Branch(ref mut children) => {
+                Arc::make_mut(children[index(id, level)].as_mut()?).get_mut(level - 1, id)
+            }
+        }
+    }
+}
+
+/// Removes an id which is in the subtree, dropping any nodes left empty.
+fn remove<T: Clone>(slot: &mut Option<Arc<Node<T>>>, level: u32, id: Id) -> T {
+    let node = slot.as_mut().unwrap();
+    if node.len == 1 {
+        // Drop the subtree rather than copying it just to empty it.
+        return take(slot.take().unwrap(), level, id);
+    }
+    let node = Arc::make_mut(node);
+    node.len -= 1;
+    match node.slots {
+        Slots::Leaf(ref mut values) => values[index(id, 0)].take().unwrap(),
+        Slots::Branch(ref mut children) => remove(&mut children[index(id, level)], level - 1, id),
+    }
+}
+
+/// Moves a value out of a subtree, or clones it if the subtree is shared.
+fn take<T: Clone>(node: Arc<Node<T>>, level: u32, id: Id) -> T {
+    match Arc::try_unwrap(node) {
+        Ok(Node {
+            slots: Slots::Leaf(mut values),
+            ..
+        }) => values[index(id, 0)].take().unwrap(),
+        Ok(Node {
+            slots: Slots::Branch(mut children),
+            ..
+        }) => take(children[index(id, level)].take().unwrap(), level - 1, id),
+        Err(node) => node.get(level, id).unwrap().clone(),
+    }
+}
+
+/// A map from ids to values whose clones share storage, so that taking a snapshot is O(1).
+///
+/// Values are stored in a trie of fixed-size nodes behind reference counts. Cloning the map only
+/// copies a pointer, and modifying a map copies just the nodes on the path to the id it touches
+/// which are shared with another clone, so a write costs O(log n) whether or not the map has been
+/// cloned. Ids are allocated lowest first, as for an `IdMap` with the default policy. Methods
+/// which modify the map require `T: Clone` so that shared nodes can be copied.
+///
+/// # Examples
+///
+/// ```
+/// # use id_map::PersistentIdMap;
+/// #
+/// let mut map = PersistentIdMap::new();
+/// let a = map.insert("a");
+/// let snapshot = map.clone();
+///
+/// let b = map.insert("b");
+/// map.remove(a);
+///
+/// assert_eq!(snapshot.get(a), Some(&"a"));
+/// assert!(!snapshot.contains(b));
+/// assert_eq!(map.iter().collect::<Vec<_>>(), [(b, &"b")]);
+/// ```
+pub struct PersistentIdMap<T, K = Id> {
+    // The root of the trie, or None if the map is empty.
+    root: Option<Arc<Node<T>>>,
+    // The level of the root. This is the smallest level that can hold the largest id, and zero
+    // for an empty map.
+    height: u32,
+    // The number of id-value pairs in the map.
+    len: usize,
+    // The smallest id not in the map.
+    space: Id,
+    marker: PhantomData<K>,
+}
+
+impl<T> PersistentIdMap<T> {
+    #[inline]
+    /// Creates an empty `PersistentIdMap<T>`.
+    pub fn new() -> Self {
+        PersistentIdMap::with_key()
+    }
+}
+
+impl<T, K: Key> PersistentIdMap<T, K> {
+    #[inline]
+    /// Creates an empty `PersistentIdMap<T, K>` with a custom key type.
+    pub fn with_key() -> Self {
+        PersistentIdMap {
+            root: None,
+            height: 0,
+            len: 0,
+            space: 0,
+            marker: PhantomData,
+        }
+    }
+
+    #[inline]
+    /// Removes all values from the map. Clones of the map are not affected.
+    pub fn clear(&mut self) {
+        self.root = None;
+        self.height = 0;
+        self.len = 0;
+        self.space = 0;
+    }
+
+    #[inline]
+    /// Returns the id that a subsequent call to insert() will produce.
+    ///
+    /// # Panics
+    ///
+    /// Panics if every id up to `K::MAX_ID` is in the map. Use `try_next_id` to check for this
+    /// instead.
+    pub fn next_id(&self) -> K {
+        K::from_id(self.space)
+    }
+
+    #[inline]
+    /// Returns the id that a subsequent call to insert() will produce, or `None` if it does not
+    /// fit in a `K`.
+    pub fn try_next_id(&self) -> Option<K> {
+        K::try_from_id(self.space)
+    }
+
+    #[inline]
+    /// Returns the number of id-value pairs in the map.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    /// Returns true if the map contains no values.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    #[inline]
+    /// Returns true if the two maps share all of their storage, meaning that one is a clone of the
+    /// other and neither has been modified since. Empty maps have no storage, so any two of them
+    /// compare equal.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        match (&self.root, &other.root) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+
+    #[inline]
+    /// Returns true if the map contains a value for the specified id.
+    pub fn contains(&self, id: K) -> bool {
+        self.get(id).is_some()
+    }
+
+    #[inline]
+    /// Returns a reference to the value at the specified id if it is in the map.
+    pub fn get(&self, id: K) -> Option<&T> {
+        let id = id.to_id();
+        if !fits(id, self.height) {
+            return None;
+        }
+        self.root.as_ref()?.get(self.height, id)
+    }
+
+    #[inline]
+    /// An iterator over ids, in increasing order.
+    pub fn ids(&self) -> Ids<'_, T, K> {
+        Ids { iter: self.iter() }
+    }
+
+    #[inline]
+    /// An iterator over id-value pairs, in order of increasing id.
+    pub fn iter(&self) -> Iter<'_, T, K> {
+        Iter {
+            root: self.root.as_deref(),
+            height: self.height,
+            front: 0,
+            back: span(self.height),
+            len: self.len,
+            marker: PhantomData,
+        }
+    }
+
+    /// Returns the smallest id not in the map which is at least `from`.
+    fn vacant_from(&self, from: Id) -> Id {
+        match self.root {
+            Some(ref root) if fits(from, self.height) => root
+                .vacant_from(self.height, from)
+                .unwrap_or_else(|| span(self.height)),
+            _ => from,
+        }
+    }
+
+    /// Adds levels above the root until it can hold `id`.
+    fn grow(&mut self, id: Id) {
+        while !fits(id, self.height) {
+            if let Some(root) = self.root.take() {
+                let mut node = Node::new(self.height + 1);
+                node.len = root.len;
+                if let Slots::Branch(ref mut children) = node.slots {
+                    children[0] = Some(root);
+                }
+                self.root = Some(Arc::new(node));
+            }
+            self.height += 1;
+        }
+    }
+
+    /// Removes levels above the root while they only hold the lowest ids.
+    fn shrink(&mut self) {
+        while self.height != 0 {
+            let child = match self.root {
+                Some(ref root) => match root.slots {
+                    Slots::Branch(ref children) if children[1..].iter().all(Option::is_none) => {
+                        children[0].clone()
+                    }
+                    _ => return,
+                },
+                None => {
+                    self.height = 0;
+                    return;
+                }
+            };
+            self.root = child;
+            self.height -= 1;
+        }
+    }
+
+    #[cfg(all(test, feature = "std"))]
+    pub(crate) fn assert_invariant(&self) {
+        match self.root {
+            Some(ref root) => {
+                root.assert_invariant(self.height);
+                assert_eq!(self.len, root.len);
+                if let Slots::Branch(ref children) = root.slots {
+                    assert!(children[1..].iter().any(Option::is_some));
+                }
+            }
+            None => {
+                assert_eq!(self.height, 0);
+                assert_eq!(self.len, 0);
+            }
+        }
+        assert_eq!(self.space, self.vacant_from(0));
+    }
+
+    #[cfg(all(test, feature = "std"))]
+    /// Returns the number of nodes in this map which are not shared with `other`.
+    pub(crate) fn unshared_nodes(&self, other: &Self) -> usize {
+        fn count<T>(node: &Arc<Node<T>>, other: Option<&Arc<Node<T>>>) -> usize {
+            if other.is_some_and(|other| Arc::ptr_eq(node, other)) {
+                return 0;
+            }
+            let children = match node.slots {
+                Slots::Branch(ref children) => children,
+                Slots::Leaf(_) => return 1,
+            };
+            let others = other.and_then(|other| match other.slots {
+                Slots::Branch(ref others) => Some(others),
+                Slots::Leaf(_) => None,
+            });
+            let mut unshared = 1;
+            for (i, child) in children.iter().enumerate() {
+                if let Some(ref child) = *child {
+                    let other = others.and_then(|others| others[i].as_ref());
+                    unshared += count(child, other);
+                }
+            }
+            unshared
+        }
+
+        let other = if self.height == other.height {
+            other.root.as_ref()
+        } else {
+            None
+        };
+        self.root.as_ref().map_or(0, |root| count(root, other))
+    }
+}
+
+impl<T: Clone, K: Key> PersistentIdMap<T, K> {
+    #[inline]
+    /// Inserts a value into an empty slot in the map and returns its id.
+    pub fn insert(&mut self, val: T) -> K {
+        let id = K::from_id(self.space);
+        self.insert_at(id, val);
+        id
+    }
+
+    /// Inserts a value at a specific id, returning the old value if it existed.
+    pub fn insert_at(&mut self, id: K, val: T) -> Option<T> {
+        let id = id.to_id();
+        self.grow(id);
+        let height = self.height;
+        let root = self.root.get_or_insert_with(|| Arc::new(Node::new(height)));
+        let old = Arc::make_mut(root).insert(height, id, val);
+        if old.is_none() {
+            self.len += 1;
+            if id == self.space {
+                self.space = self.vacant_from(id + 1);
+            }
+        }
+        old
+    }
+
+    /// Removes an id from the map, returning its value if it was previously in the map.
+    pub fn remove(&mut self, id: K) -> Option<T> {
+        if !self.contains(id) {
+            return None;
+        }
+        let id = id.to_id();
+        let val = remove(&mut self.root, self.height, id);
+        self.shrink();
+        self.len -= 1;
+        self.space = cmp::min(self.space, id);
+        Some(val)
+    }
+
+    #[inline]
+    /// Returns a mutable reference to the value at the specified id if it is in the map.
+    pub fn get_mut(&mut self, id: K) -> Option<&mut T> {
+        if !self.contains(id) {
+            return None;
+        }
+        Arc::make_mut(self.root.as_mut()?).get_mut(self.height, id.to_id())
+    }
+}
+
+/// Returns the slot within a node at `level` which holds an id.
+#[inline]
+fn index(id: Id, level: u32) -> usize {
+    (id >> (BITS * level)) & (WIDTH - 1)
+}
+
+/// Returns the number of ids held by a node at `level`, saturating at `Id::MAX`.
+#[inline]
+fn span(level: u32) -> Id {
+    1usize.checked_shl(BITS * (level + 1)).unwrap_or(Id::MAX)
+}
+
+/// Returns true if a trie whose root is at `height` can hold an id.
+#[inline]
+fn fits(id: Id, height: u32) -> bool {
+    (id >> BITS)
+        .checked_shr(BITS * height)
+        .map_or(true, |rest| rest == 0)
+}
+
+impl<T, K> Clone for PersistentIdMap<T, K> {
+    #[inline]
+    fn clone(&self) -> Self {
+        PersistentIdMap {
+            root: self.root.clone(),
+            height: self.height,
+            len: self.len,
+            space: self.space,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T: fmt::Debug, K: Key> fmt::Debug for PersistentIdMap<T, K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<T, K: Key> Default for PersistentIdMap<T, K> {
+    #[inline]
+    fn default() -> Self {
+        PersistentIdMap::with_key()
+    }
+}
+
+impl<T: Eq, K: Key> Eq for PersistentIdMap<T, K> {}
+
+impl<T: PartialEq, K: Key> PartialEq for PersistentIdMap<T, K> {
+    fn eq(&self, other: &Self) -> bool {
+        self.ptr_eq(other) || self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Clone, K: Key> Extend<T> for PersistentIdMap<T, K> {
+    #[inline]
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for val in iter {
+            self.insert(val);
+        }
+    }
+}
+
+impl<T: Clone> FromIterator<T> for PersistentIdMap<T> {
+    #[inline]
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut map = PersistentIdMap::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<T, K: Key, A: AllocPolicy> From<IdMap<T, K, A>> for PersistentIdMap<T, K> {
+    /// Converts an `IdMap`, keeping its ids. The new map allocates ids lowest first.
+    fn from(map: IdMap<T, K, A>) -> Self {
+        let len = map.len();
+        // The nodes of the current level, with their index within that level, built bottom up.
+        let mut nodes: Vec<(Id, Node<T>)> = Vec::new();
+        for (id, val) in map {
+            let id = id.to_id();
+            if nodes.last().map_or(true, |&(idx, _)| idx != id >> BITS) {
+                nodes.push((id >> BITS, Node::new(0)));
+            }
+            let node = &mut nodes.last_mut().unwrap().1;
+            if let Slots::Leaf(ref mut values) = node.slots {
+                values[index(id, 0)] = Some(val);
+            }
+            node.len += 1;
+        }
+
+        let mut height = 0;
+        while nodes.len() > 1 || nodes.first().is_some_and(|&(idx, _)| idx != 0) {
+            height += 1;
+            let mut parents: Vec<(Id, Node<T>)> = Vec::new();
+            for (idx, node) in nodes {
+                if parents
+                    .last()
+                    .map_or(true, |&(parent, _)| parent != idx >> BITS)
+                {
+                    parents.push((idx >> BITS, Node::new(height)));
+                }
+                let parent = &mut parents.last_mut().unwrap().1;
+                parent.len += node.len;
+                if let Slots::Branch(ref mut children) = parent.slots {
+                    children[idx & (WIDTH - 1)] = Some(Arc::new(node));
+                }
+            }
+            nodes = parents;
+        }
+
+        let mut map = PersistentIdMap {
+            root: nodes.pop().map(|(_, node)| Arc::new(node)),
+            height,
+            len,
+            space: 0,
+            marker: PhantomData,
+        };
+        map.space = map.vacant_from(0);
+        map
+    }
+}
+
+impl<T: Clone, K: Key> From<PersistentIdMap<T, K>> for IdMap<T, K> {
+    /// Converts a `PersistentIdMap`, keeping its ids. Values still shared with a clone of the map
+    /// are cloned, and the others are moved.
+    fn from(map: PersistentIdMap<T, K>) -> Self {
+        /// Moves the values of a subtree starting at `base` into `out`, cloning shared nodes.
+        fn drain<T: Clone>(node: Arc<Node<T>>, level: u32, base: Id, out: &mut Vec<(Id, T)>) {
+            let node = Arc::try_unwrap(node).unwrap_or_else(|node| (*node).clone());
+            match node.slots {
+                Slots::Leaf(values) => out.extend(
+                    IntoIterator::into_iter(values)
+                        .enumerate()
+                        .filter_map(|(offset, val)| Some((base + offset, val?))),
+                ),
+                Slots::Branch(children) => {
+                    for (i, child) in IntoIterator::into_iter(children).enumerate() {
+                        if let Some(child) = child {
+                            drain(child, level - 1, base + (i << (BITS * level)), out);
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut values = Vec::with_capacity(map.len);
+        if let Some(root) = map.root {
+            drain(root, map.height, 0, &mut values);
+        }
+        values
+            .into_iter()
+            .map(|(id, val)| (K::from_id(id), val))
+            .collect()
+    }
+}
+
+impl<'a, T, K: Key> IntoIterator for &'a PersistentIdMap<T, K> {
+    type Item = (K, &'a T);
+    type IntoIter = Iter<'a, T, K>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T, K: Key> Index<K> for PersistentIdMap<T, K> {
+    type Output = T;
+
+    #[inline]
+    fn index(&self, id: K) -> &Self::Output {
+        match self.get(id) {
+            Some(val) => val,
+            None => panic!("id {} out of bounds", id.to_id()),
+        }
+    }
+}
+
+/// An iterator over ids, in increasing order.
+pub struct Ids<'a, T: 'a, K = Id> {
+    iter: Iter<'a, T, K>,
+}
+
+impl<'a, T: 'a, K: Key> fmt::Debug for Ids<'a, T, K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+impl<'a, T: 'a, K: Key> Iterator for Ids<'a, T, K> {
+    type Item = K;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|(id, _)| id)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<'a, T: 'a, K: Key> DoubleEndedIterator for Ids<'a, T, K> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter.next_back().map(|(id, _)| id)
+    }
+}
+
+impl<'a, T: 'a, K: Key> ExactSizeIterator for Ids<'a, T, K> {
+    #[inline]
+    fn len(&self) -> usize {
+        self.iter.len()
+    }
+}
+
+impl<'a, T: 'a, K: Key> FusedIterator for Ids<'a, T, K> {}
+
+impl<'a, T: 'a, K: 'a> Clone for Ids<'a, T, K> {
+    #[inline]
+    fn clone(&self) -> Self {
+        Ids {
+            iter: self.iter.clone(),
+        }
+    }
+}
+
+/// An iterator over id-value pairs, in order of increasing id.
+pub struct Iter<'a, T: 'a, K = Id> {
+    root: Option<&'a Node<T>>,
+    height: u32,
+    // The range of ids which have not been visited yet.
+    front: Id,
+    back: Id,
+    // The number of values not yet yielded.
+    len: usize,
+    marker: PhantomData<K>,
+}
+
+impl<'a, T: fmt::Debug + 'a, K: Key> fmt::Debug for Iter<'a, T, K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+impl<'a, T: 'a, K: Key> Iterator for Iter<'a, T, K> {
+    type Item = (K, &'a T);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.len != 0 {
+            match self.root?.leaf(self.height, self.front) {
+                Ok(values) => {
+                    let id = self.front;
+                    self.front += 1;
+                    if let Some(ref val) = values[index(id, 0)] {
+                        self.len -= 1;
+                        return Some((K::from_id(id), val));
+                    }
+                }
+                // Skip the missing node.
+                Err(level) => self.front = (self.front | (span(level) - 1)) + 1,
+            }
+        }
+        None
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, T: 'a, K: Key> DoubleEndedIterator for Iter<'a, T, K> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        while self.len != 0 {
+            match self.root?.leaf(self.height, self.back - 1) {
+                Ok(values) => {
+                    self.back -= 1;
+                    if let Some(ref val) = values[index(self.back, 0)] {
+                        self.len -= 1;
+                        return Some((K::from_id(self.back), val));
+                    }
+                }
+                Err(level) => self.back = (self.back - 1) & !(span(level) - 1),
+            }
+        }
+        None
+    }
+}
+
+impl<'a, T: 'a, K: Key> ExactSizeIterator for Iter<'a, T, K> {
+    #[inline]
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+impl<'a, T: 'a, K: Key> FusedIterator for Iter<'a, T, K> {}
+
+impl<'a, T: 'a, K: 'a> Clone for Iter<'a, T, K> {
+    #[inline]
+    fn clone(&self) -> Self {
+        Iter {
+            root: self.root,
+            height: self.height,
+            front: self.front,
+            back: self.back,
+            len: self.len,
+            marker: PhantomData,
+        }
+    }
+}
@@ -0,0 +1,312 @@
+//! Policies deciding which id an `IdMap` hands out next.
+//!
+//! The policy is the third type parameter of [`IdMap`], and defaults to [`LowestFree`].
+//!
+//! # Examples
+//!
+//! ```
+//! # use id_map::{Id, IdMap};
+//! use id_map::policy::FreeList;
+//!
+//! let mut map: IdMap<_, Id, FreeList> = IdMap::with_key();
+//! for i in 0..4 {
+//!     map.insert(i);
+//! }
+//! map.remove(1);
+//! map.remove(2);
+//! assert_eq!(map.next_id(), 2);
+//! ```
+//!
+//! [`IdMap`]: ../struct.IdMap.html
+//! [`LowestFree`]: struct.LowestFree.html
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::cmp;
+
+use {Id, IdSet};
+
+/// A strategy for allocating ids in an `IdMap`.
+///
+/// The map notifies the policy of every change to its set of ids, and the policy keeps track of
+/// the id that the next insert will use. That id must never be in the map.
+pub trait AllocPolicy: Default {
+    /// Returns the id that the next insert will use.
+    fn next_id(&self) -> Id;
+
+    /// Called after `id` is added to `ids`, either by allocation or at an id chosen by the caller.
+    fn inserted(&mut self, ids: &IdSet, id: Id);
+
+    /// Called after `id` is removed from the map.
+    fn removed(&mut self, id: Id);
+
+    /// Called before every id in `ids` is removed from the map at once.
+    fn cleared(&mut self, ids: &IdSet);
+
+    /// Rebuilds the policy for a map whose ids were replaced by `ids`.
+    fn reset(&mut self, ids: &IdSet);
+
+    /// Returns the first id of a run of `n` ids which are not in `ids`, for `reserve_block`.
+    ///
+    /// By default this is the lowest such run.
+    fn block_start(&self, ids: &IdSet, n: usize) -> Id {
+        vacant_run(ids, 0, n)
+    }
+
+    #[doc(hidden)]
+    /// Checks the internal consistency of the policy against the map.
+    fn assert_invariant(&self, ids: &IdSet) {
+        assert!(!ids.contains(self.next_id()));
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+/// Reuses the lowest free id, which keeps the map as dense as possible.
+///
+/// Removal is O(1), but allocating may scan forward over the occupied ids.
+pub struct LowestFree {
+    // The smallest id not in the map.
+    pub(crate) space: Id,
+}
+
+impl AllocPolicy for LowestFree {
+    #[inline]
+    fn next_id(&self) -> Id {
+        self.space
+    }
+
+    #[inline]
+    fn inserted(&mut self, ids: &IdSet, id: Id) {
+        if id == self.space {
+            self.space = vacant_from(ids, id + 1);
+        }
+    }
+
+    #[inline]
+    fn removed(&mut self, id: Id) {
+        self.space = cmp::min(self.space, id);
+    }
+
+    #[inline]
+    fn cleared(&mut self, _: &IdSet) {
+        self.space = 0;
+    }
+
+    #[inline]
+    fn reset(&mut self, ids: &IdSet) {
+        self.space = vacant_from(ids, 0);
+    }
+
+    #[inline]
+    fn block_start(&self, ids: &IdSet, n: usize) -> Id {
+        vacant_run(ids, self.space, n)
+    }
+
+    fn assert_invariant(&self, ids: &IdSet) {
+        for id in 0..self.space {
+            assert!(ids.contains(id));
+        }
+        assert!(!ids.contains(self.space));
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+/// Reuses the most recently freed id first.
+///
+/// Allocation and removal are O(1), except when inserting at a chosen id which is waiting to be
+/// reused.
+pub struct FreeList {
+    // Freed ids, most recent last. None of them are in the map.
+    free: Vec<Id>,
+    // The smallest id which has never been allocated and is not in the map.
+    end: Id,
+}
+
+impl AllocPolicy for FreeList {
+    #[inline]
+    fn next_id(&self) -> Id {
+        self.free.last().cloned().unwrap_or(self.end)
+    }
+
+    #[inline]
+    fn inserted(&mut self, ids: &IdSet, id: Id) {
+        if self.free.last() == Some(&id) {
+            self.free.pop();
+        } else if id == self.end {
+            self.end = vacant_from(ids, id + 1);
+        } else if id < self.end {
+            if let Some(pos) = self.free.iter().rposition(|&free| free == id) {
+                self.free.remove(pos);
+            }
+        }
+    }
+
+    #[inline]
+    fn removed(&mut self, id: Id) {
+        // Ids past the end are found again by scanning forward from it.
+        if id < self.end {
+            self.free.push(id);
+        }
+    }
+
+    #[inline]
+    fn cleared(&mut self, _: &IdSet) {
+        self.free.clear();
+        self.end = 0;
+    }
+
+    fn reset(&mut self, ids: &IdSet) {
+        // Hand out the gaps lowest first.
+        self.end = last_id(ids).map_or(0, |id| id + 1);
+        self.free = (0..self.end)
+            .rev()
+            .filter(|&id| !ids.contains(id))
+            .collect();
+    }
+
+    #[inline]
+    fn block_start(&self, ids: &IdSet, n: usize) -> Id {
+        vacant_run(ids, self.end, n)
+    }
+
+    fn assert_invariant(&self, ids: &IdSet) {
+        for &id in &self.free {
+            assert!(id < self.end && !ids.contains(id));
+        }
+        assert!(!ids.contains(self.end));
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+/// Reuses freed ids in the order they were freed, but only once `N` further ids have been freed
+/// after them.
+///
+/// Delaying reuse makes it more likely that a stale id is noticed, because it refers to a missing
+/// value rather than to an unrelated new one. With `N = 0` ids are reused first in, first out.
+pub struct Quarantine<const N: usize> {
+    // Freed ids, oldest first. None of them are in the map.
+    queue: VecDeque<Id>,
+    // The smallest id which has never been allocated and is not in the map.
+    end: Id,
+}
+
+impl<const N: usize> AllocPolicy for Quarantine<N> {
+    #[inline]
+    fn next_id(&self) -> Id {
+        if self.queue.len() > N {
+            self.queue[0]
+        } else {
+            self.end
+        }
+    }
+
+    #[inline]
+    fn inserted(&mut self, ids: &IdSet, id: Id) {
+        if self.queue.front() == Some(&id) {
+            self.queue.pop_front();
+        } else if id == self.end {
+            self.end = vacant_from(ids, id + 1);
+        } else if id < self.end {
+            if let Some(pos) = self.queue.iter().position(|&free| free == id) {
+                self.queue.remove(pos);
+            }
+        }
+    }
+
+    #[inline]
+    fn removed(&mut self, id: Id) {
+        // Ids past the end are found again by scanning forward from it.
+        if id < self.end {
+            self.queue.push_back(id);
+        }
+    }
+
+    fn cleared(&mut self, ids: &IdSet) {
+        let end = self.end;
+        self.queue.extend(ids.iter().filter(|&id| id < end));
+    }
+
+    fn reset(&mut self, ids: &IdSet) {
+        self.end = last_id(ids).map_or(0, |id| id + 1);
+        self.queue = (0..self.end).filter(|&id| !ids.contains(id)).collect();
+    }
+
+    #[inline]
+    fn block_start(&self, ids: &IdSet, n: usize) -> Id {
+        vacant_run(ids, self.end, n)
+    }
+
+    fn assert_invariant(&self, ids: &IdSet) {
+        for &id in &self.queue {
+            assert!(id < self.end && !ids.contains(id));
+        }
+        assert!(!ids.contains(self.end));
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+/// Never reuses an id, always allocating one past the largest id the map has held.
+///
+/// Ids are only reused after the map is rebuilt by `compact`.
+pub struct Monotonic {
+    // One past the largest id the map has held.
+    end: Id,
+}
+
+impl AllocPolicy for Monotonic {
+    #[inline]
+    fn next_id(&self) -> Id {
+        self.end
+    }
+
+    #[inline]
+    fn inserted(&mut self, _: &IdSet, id: Id) {
+        self.end = cmp::max(self.end, id + 1);
+    }
+
+    #[inline]
+    fn removed(&mut self, _: Id) {}
+
+    #[inline]
+    fn cleared(&mut self, _: &IdSet) {}
+
+    #[inline]
+    fn reset(&mut self, ids: &IdSet) {
+        self.end = last_id(ids).map_or(0, |id| id + 1);
+    }
+
+    #[inline]
+    fn block_start(&self, _: &IdSet, _: usize) -> Id {
+        self.end
+    }
+
+    fn assert_invariant(&self, ids: &IdSet) {
+        if let Some(id) = last_id(ids) {
+            assert!(id < self.end);
+        }
+    }
+}
+
+/// Returns the smallest id not in the set which is at least `from`.
+fn vacant_from(ids: &IdSet, mut from: Id) -> Id {
+    while ids.contains(from) {
+        from += 1;
+    }
+    from
+}
+
+/// Returns the first id of the lowest run of `n` ids not in the set which starts at least at
+/// `from`.
+fn vacant_run(ids: &IdSet, mut from: Id, n: usize) -> Id {
+    loop {
+        match ::bits::next_id(ids, from) {
+            Some(id) if id - from < n => from = id + 1,
+            _ => return from,
+        }
+    }
+}
+
+/// Returns the largest id in the set.
+fn last_id(ids: &IdSet) -> Option<Id> {
+    ::bits::raw_ids(ids).next_back()
+}
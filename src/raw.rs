@@ -0,0 +1,147 @@
+//! The parts of a map which do not depend on how its values are stored, shared by `IdMap` and
+//! `DenseIdMap`.
+
+use core::marker::PhantomData;
+
+use {AllocPolicy, Id, IdMap, IdRemap, IdSet, Key, TryReserveError};
+
+/// Marks the parameters of a type which borrows a `RawMap`, and only uses them through the map.
+pub(crate) type MapMarker<T, K, A> = PhantomData<fn() -> (T, K, A)>;
+
+/// The ids of a map: the set of ids with values, the policy choosing the next id to insert, and
+/// the largest id which may be inserted.
+#[derive(Clone)]
+pub(crate) struct IdState<A> {
+    pub(crate) ids: IdSet,
+    pub(crate) policy: A,
+    pub(crate) max_id: Id,
+}
+
+impl<A: AllocPolicy> IdState<A> {
+    #[inline]
+    pub(crate) fn new(ids: IdSet, policy: A, max_id: Id) -> Self {
+        IdState {
+            ids,
+            policy,
+            max_id,
+        }
+    }
+
+    #[inline]
+    /// Returns the id that the next insert will use.
+    pub(crate) fn next_id(&self) -> Id {
+        self.policy.next_id()
+    }
+
+    #[inline]
+    /// Returns the id that the next insert will use, or `None` if it is greater than the maximum
+    /// id.
+    pub(crate) fn try_next_id(&self) -> Option<Id> {
+        let id = self.policy.next_id();
+        if id <= self.max_id {
+            Some(id)
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    /// Returns an error if `id` is greater than the maximum id, or if the set cannot grow to hold
+    /// it. Otherwise inserting it will not allocate.
+    pub(crate) fn try_reserve_id(&mut self, id: Id) -> Result<(), TryReserveError> {
+        if id > self.max_id {
+            return Err(TryReserveError::IdTooLarge(id));
+        }
+        self.ids.try_reserve_id(id)
+    }
+
+    #[inline]
+    /// Adds an id which is not in the set.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the id is greater than the maximum id.
+    pub(crate) fn insert(&mut self, id: Id) {
+        assert!(
+            id <= self.max_id,
+            "id {} is greater than the maximum id {}",
+            id,
+            self.max_id
+        );
+        self.ids.insert(id);
+        self.policy.inserted(&self.ids, id);
+    }
+
+    #[inline]
+    /// Removes an id, returning true if it was in the set.
+    pub(crate) fn remove(&mut self, id: Id) -> bool {
+        if self.ids.remove(id) {
+            self.policy.removed(id);
+            true
+        } else {
+            false
+        }
+    }
+
+    #[inline]
+    /// Removes every id.
+    pub(crate) fn clear(&mut self) {
+        self.policy.cleared(&self.ids);
+        self.ids.clear();
+    }
+
+    /// Removes the ids for which the closure returns false, in increasing order.
+    pub(crate) fn retain<F: FnMut(Id) -> bool>(&mut self, mut keep: F) {
+        let policy = &mut self.policy;
+        self.ids.retain(|id| {
+            if keep(id) {
+                true
+            } else {
+                policy.removed(id);
+                false
+            }
+        })
+    }
+
+    /// Renumbers the ids to `0..len`, keeping their order, and returns the mapping from old ids to
+    /// new ids. The closure is called with each old and new id in increasing order, to move its
+    /// value.
+    pub(crate) fn compact<K: Key, F: FnMut(Id, Id)>(&mut self, mut move_value: F) -> IdRemap<K> {
+        let len = self.ids.len();
+        let mut new_ids = IdMap::with_capacity_and_key(len);
+        for (new, old) in self.ids.iter().enumerate() {
+            move_value(old, new);
+            new_ids.insert_at(K::from_id(old), K::from_id(new));
+        }
+        self.ids = IdSet::new_filled(len);
+        self.policy.reset(&self.ids);
+        IdRemap::new(new_ids)
+    }
+
+    #[cfg(all(test, feature = "std"))]
+    pub(crate) fn assert_invariant(&self) {
+        // The policy should agree with the set of ids.
+        self.policy.assert_invariant(&self.ids);
+    }
+}
+
+/// The storage of a map's values, which the entry and draining types are generic over.
+///
+/// This is public only so that it can bound their type parameters. It is not exported, so it
+/// cannot be named or implemented outside the crate.
+pub trait RawMap<T> {
+    /// Returns the set of ids with values.
+    fn id_set(&self) -> &IdSet;
+
+    /// Returns the value at an id in the set.
+    fn value(&self, id: Id) -> &T;
+
+    /// Returns the value at an id in the set mutably.
+    fn value_mut(&mut self, id: Id) -> &mut T;
+
+    /// Inserts a value at an id which is not in the set.
+    fn insert_vacant(&mut self, id: Id, val: T) -> &mut T;
+
+    /// Removes the value at an id, returning it if the id was in the set.
+    fn remove_id(&mut self, id: Id) -> Option<T>;
+}
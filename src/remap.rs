@@ -0,0 +1,164 @@
+use alloc::vec::Vec;
+
+use {AllocPolicy, Id, IdMap, IdSet, Iter, Key};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// A translation from old ids to new ids, returned by `IdMap::compact`.
+pub struct IdRemap<K: Key = Id> {
+    // The new id of each old id which was in the map.
+    new_ids: IdMap<K, K>,
+}
+
+impl<K: Key> IdRemap<K> {
+    #[inline]
+    pub(crate) fn new(new_ids: IdMap<K, K>) -> Self {
+        IdRemap { new_ids }
+    }
+
+    #[inline]
+    /// Returns the number of ids in the remapping.
+    pub fn len(&self) -> usize {
+        self.new_ids.len()
+    }
+
+    #[inline]
+    /// Returns true if the remapping is empty.
+    pub fn is_empty(&self) -> bool {
+        self.new_ids.is_empty()
+    }
+
+    #[inline]
+    /// Returns true if no id was changed by the remapping.
+    pub fn is_identity(&self) -> bool {
+        self.new_ids.iter().all(|(old, &new)| old == new)
+    }
+
+    #[inline]
+    /// Returns the new id for an old id, or `None` if the id was not in the map.
+    pub fn get(&self, old: K) -> Option<K> {
+        self.new_ids.get(old).cloned()
+    }
+
+    #[inline]
+    /// An iterator over pairs of old and new ids, in order of increasing id.
+    pub fn iter(&self) -> IdRemapIter<'_, K> {
+        IdRemapIter {
+            iter: self.new_ids.iter(),
+        }
+    }
+
+    /// Moves each value in another map to the new id for its old id. Values whose ids are not in
+    /// the remapping are dropped.
+    ///
+    /// The map keeps its maximum id, and its allocation policy is rebuilt for the new ids.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a new id is greater than the map's maximum id. The map is left unchanged in that
+    /// case.
+    pub fn apply_to_map<T, A: AllocPolicy>(&self, map: &mut IdMap<T, K, A>) {
+        // Check every new id before moving any values, so that a panic leaves the map intact.
+        let max_id = map.state.max_id;
+        for old in map.ids() {
+            if let Some(new) = self.get(old) {
+                assert!(
+                    new.to_id() <= max_id,
+                    "id {} is greater than the maximum id {}",
+                    new.to_id(),
+                    max_id
+                );
+            }
+        }
+
+        let mut ids = IdSet::with_capacity(self.len());
+        let mut values = Vec::with_capacity(self.len());
+        for (old, val) in map.drain() {
+            if let Some(new) = self.get(old) {
+                let new = new.to_id();
+                ids.insert(new);
+                if values.len() <= new {
+                    values.resize_with(new + 1, Default::default);
+                }
+                values[new] = Some(val);
+            }
+        }
+        map.state.ids = ids;
+        map.values = values;
+        map.state.policy.reset(&map.state.ids);
+    }
+
+    /// Calls the closure with the old and new id of each value which was moved, in order of
+    /// increasing old id, so that ids stored elsewhere can be updated.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use id_map::IdMap;
+    /// #
+    /// let mut map: IdMap<_> = (0..4).collect();
+    /// map.remove(1);
+    /// let mut selected = vec![0, 3];
+    ///
+    /// let remap = map.compact();
+    /// remap.apply_with(|old, new| {
+    ///     for id in &mut selected {
+    ///         if *id == old {
+    ///             *id = new;
+    ///         }
+    ///     }
+    /// });
+    /// assert_eq!(selected, [0, 2]);
+    /// ```
+    pub fn apply_with<F: FnMut(K, K)>(&self, mut f: F) {
+        for (old, new) in self {
+            if old != new {
+                f(old, new);
+            }
+        }
+    }
+
+    /// Returns the set of new ids for a set of old ids. Ids not in the remapping are skipped.
+    pub fn apply_to_set(&self, set: &IdSet) -> IdSet {
+        set.iter()
+            .filter_map(|old| self.get(K::from_id(old)))
+            .map(K::to_id)
+            .collect()
+    }
+}
+
+impl<'a, K: Key> IntoIterator for &'a IdRemap<K> {
+    type Item = (K, K);
+    type IntoIter = IdRemapIter<'a, K>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[derive(Clone, Debug)]
+/// An iterator over pairs of old and new ids, in order of increasing id.
+pub struct IdRemapIter<'a, K: 'a = Id> {
+    iter: Iter<'a, K, K>,
+}
+
+impl<'a, K: Key> Iterator for IdRemapIter<'a, K> {
+    type Item = (K, K);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|(old, &new)| (old, new))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<'a, K: Key> ExactSizeIterator for IdRemapIter<'a, K> {
+    #[inline]
+    fn len(&self) -> usize {
+        self.iter.len()
+    }
+}
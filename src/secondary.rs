@@ -0,0 +1,370 @@
+//! A side table of values keyed by the ids of another map.
+
+use core::fmt;
+use core::iter::{FromIterator, FusedIterator};
+use core::ops::{Index, IndexMut};
+
+use {
+    AllocPolicy, GetManyError, Id, IdMap, IdSet, IntoIter, Iter, IterMut, Key, Values, ValuesMut,
+};
+
+/// A side table of values keyed by the ids of another map.
+///
+/// Unlike `IdMap`, a `SecondaryIdMap` never allocates ids itself: values are always inserted at an
+/// id taken from the primary map.
+///
+/// # Examples
+///
+/// ```
+/// # use id_map::{IdMap, SecondaryIdMap};
+/// #
+/// let mut names = IdMap::new();
+/// let mut ages = SecondaryIdMap::new();
+///
+/// let alice = names.insert("alice");
+/// let bob = names.insert("bob");
+/// ages.insert(alice, 31);
+/// ages.insert(bob, 27);
+///
+/// names.remove(bob);
+/// ages.retain_live(&names);
+/// assert!(!ages.contains(bob));
+/// assert_eq!(ages[alice], 31);
+/// ```
+#[derive(Clone, PartialEq, Eq)]
+pub struct SecondaryIdMap<T, K = Id> {
+    // The storage of values. Only ids given by the caller are ever inserted.
+    pub(crate) map: IdMap<T, K, Unallocated>,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+/// The allocation policy of a `SecondaryIdMap`. Since it never allocates ids, there is nothing to
+/// keep track of when values are inserted and removed.
+pub(crate) struct Unallocated;
+
+impl AllocPolicy for Unallocated {
+    fn next_id(&self) -> Id {
+        unreachable!("a SecondaryIdMap does not allocate ids")
+    }
+
+    #[inline]
+    fn inserted(&mut self, _: &IdSet, _: Id) {}
+
+    #[inline]
+    fn removed(&mut self, _: Id) {}
+
+    #[inline]
+    fn cleared(&mut self, _: &IdSet) {}
+
+    #[inline]
+    fn reset(&mut self, _: &IdSet) {}
+
+    fn assert_invariant(&self, _: &IdSet) {}
+}
+
+impl<T> SecondaryIdMap<T> {
+    #[inline]
+    /// Creates an empty `SecondaryIdMap<T>`.
+    pub fn new() -> Self {
+        SecondaryIdMap::with_key()
+    }
+
+    #[inline]
+    /// Creates a `SecondaryIdMap<T>` with the specified capacity.
+    pub fn with_capacity(cap: usize) -> Self {
+        SecondaryIdMap::with_capacity_and_key(cap)
+    }
+}
+
+impl<T, K: Key> SecondaryIdMap<T, K> {
+    #[inline]
+    /// Creates an empty `SecondaryIdMap<T, K>` with a custom key type.
+    pub fn with_key() -> Self {
+        SecondaryIdMap {
+            map: IdMap::with_key(),
+        }
+    }
+
+    #[inline]
+    /// Creates a `SecondaryIdMap<T, K>` with a custom key type and the specified capacity.
+    pub fn with_capacity_and_key(cap: usize) -> Self {
+        SecondaryIdMap {
+            map: IdMap::with_capacity_and_key(cap),
+        }
+    }
+
+    #[inline]
+    /// Removes all values from the map.
+    pub fn clear(&mut self) {
+        self.map.clear();
+    }
+
+    #[inline]
+    /// Returns the number of id-value pairs in the map.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    #[inline]
+    /// Returns true if the map contains no values.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    #[inline]
+    /// Returns the number of id-value pairs the map can hold before reallocating.
+    pub fn capacity(&self) -> usize {
+        self.map.capacity()
+    }
+
+    #[inline]
+    /// Resizes the map such that that `capacity() >= cap`.
+    pub fn reserve(&mut self, cap: usize) {
+        self.map.reserve(cap);
+    }
+
+    #[inline]
+    /// Resizes the map to minimize allocated memory.
+    pub fn shrink_to_fit(&mut self) {
+        self.map.shrink_to_fit();
+    }
+
+    #[inline]
+    /// Returns a reference to the set of ids with values.
+    pub fn as_set(&self) -> &IdSet {
+        self.map.as_set()
+    }
+
+    #[inline]
+    /// Inserts a value at the given id, returning the old value if it existed.
+    pub fn insert(&mut self, id: K, val: T) -> Option<T> {
+        self.map.insert_at(id, val)
+    }
+
+    #[inline]
+    /// Removes an id from the map, returning its value if it was previously in the map.
+    pub fn remove(&mut self, id: K) -> Option<T> {
+        self.map.remove(id)
+    }
+
+    #[inline]
+    /// If the id has a value, returns it, otherwise inserts a new value with the provided closure.
+    pub fn get_or_insert_with<F: FnOnce() -> T>(&mut self, id: K, f: F) -> &mut T {
+        self.map.get_or_insert_with(id, f)
+    }
+
+    #[inline]
+    /// Remove all values not satisfying the predicate.
+    pub fn retain<F: FnMut(K, &T) -> bool>(&mut self, pred: F) {
+        self.map.retain(pred)
+    }
+
+    #[inline]
+    /// Removes all values whose ids are not in the set.
+    pub fn retain_in(&mut self, set: &IdSet) {
+        let stale = self.map.as_set().difference(set).into_set();
+        self.map.remove_set(&stale);
+    }
+
+    #[inline]
+    /// Removes all values whose ids are no longer in the primary map.
+    pub fn retain_live<U, A: AllocPolicy>(&mut self, primary: &IdMap<U, K, A>) {
+        self.retain_in(primary.as_set())
+    }
+
+    #[inline]
+    /// Returns true if the map contains a value for the specified id.
+    pub fn contains(&self, id: K) -> bool {
+        self.map.contains(id)
+    }
+
+    #[inline]
+    /// Returns a reference to the value at the specified id if it is in the map.
+    pub fn get(&self, id: K) -> Option<&T> {
+        self.map.get(id)
+    }
+
+    #[inline]
+    /// Returns a mutable reference to the value at the specified id if it is in the map.
+    pub fn get_mut(&mut self, id: K) -> Option<&mut T> {
+        self.map.get_mut(id)
+    }
+
+    #[inline]
+    /// Returns mutable references to the values at several distinct ids at once.
+    ///
+    /// Returns an error if any id is not in the map, or if the same id is requested more than
+    /// once.
+    pub fn get_many_mut<const N: usize>(
+        &mut self,
+        ids: [K; N],
+    ) -> Result<[&mut T; N], GetManyError<K>> {
+        self.map.get_many_mut(ids)
+    }
+
+    #[inline]
+    /// Returns mutable references to the values at two distinct ids at once.
+    ///
+    /// Returns an error if either id is not in the map, or if the ids are equal.
+    pub fn get2_mut(&mut self, a: K, b: K) -> Result<(&mut T, &mut T), GetManyError<K>> {
+        self.map.get2_mut(a, b)
+    }
+
+    #[inline]
+    /// Removes all id-value pairs from the map, returning them as an iterator in order of
+    /// increasing id.
+    pub fn drain(&mut self) -> Drain<'_, T, K> {
+        Drain {
+            inner: self.map.drain(),
+        }
+    }
+
+    #[inline]
+    /// An iterator over ids, in increasing order.
+    pub fn ids(&self) -> ::Ids<'_, K> {
+        self.map.ids()
+    }
+
+    #[inline]
+    /// An iterator over values, in order of increasing id.
+    pub fn values(&self) -> Values<'_, T> {
+        self.map.values()
+    }
+
+    #[inline]
+    /// A mutable iterator over values, in order of increasing id.
+    pub fn values_mut(&mut self) -> ValuesMut<'_, T> {
+        self.map.values_mut()
+    }
+
+    #[inline]
+    /// An iterator over id-value pairs, in order of increasing id.
+    pub fn iter(&self) -> Iter<'_, T, K> {
+        self.map.iter()
+    }
+
+    #[inline]
+    /// A mutable iterator over id-value pairs, in order of increasing id.
+    pub fn iter_mut(&mut self) -> IterMut<'_, T, K> {
+        self.map.iter_mut()
+    }
+
+    #[cfg(all(test, feature = "std"))]
+    pub(crate) fn assert_invariant(&self) {
+        self.map.assert_invariant();
+    }
+}
+
+impl<T: fmt::Debug, K: Key> fmt::Debug for SecondaryIdMap<T, K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.map, f)
+    }
+}
+
+impl<T, K: Key> Default for SecondaryIdMap<T, K> {
+    #[inline]
+    fn default() -> Self {
+        SecondaryIdMap::with_key()
+    }
+}
+
+impl<T, K: Key> Extend<(K, T)> for SecondaryIdMap<T, K> {
+    #[inline]
+    fn extend<I: IntoIterator<Item = (K, T)>>(&mut self, iter: I) {
+        for (id, val) in iter {
+            self.insert(id, val);
+        }
+    }
+}
+
+impl<T, K: Key> FromIterator<(K, T)> for SecondaryIdMap<T, K> {
+    #[inline]
+    fn from_iter<I: IntoIterator<Item = (K, T)>>(iter: I) -> Self {
+        SecondaryIdMap {
+            map: IdMap::from_iter(iter),
+        }
+    }
+}
+
+impl<'a, T, K: Key> IntoIterator for &'a SecondaryIdMap<T, K> {
+    type Item = (K, &'a T);
+    type IntoIter = Iter<'a, T, K>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, T, K: Key> IntoIterator for &'a mut SecondaryIdMap<T, K> {
+    type Item = (K, &'a mut T);
+    type IntoIter = IterMut<'a, T, K>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+impl<T, K: Key> IntoIterator for SecondaryIdMap<T, K> {
+    type Item = (K, T);
+    type IntoIter = IntoIter<T, K>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.map.into_iter()
+    }
+}
+
+impl<T, K: Key> Index<K> for SecondaryIdMap<T, K> {
+    type Output = T;
+
+    #[inline]
+    fn index(&self, id: K) -> &Self::Output {
+        &self.map[id]
+    }
+}
+
+impl<T, K: Key> IndexMut<K> for SecondaryIdMap<T, K> {
+    #[inline]
+    fn index_mut(&mut self, id: K) -> &mut Self::Output {
+        &mut self.map[id]
+    }
+}
+
+/// An iterator which removes and yields all id-value pairs of a `SecondaryIdMap`, in order of
+/// increasing id.
+///
+/// Any pairs left when the iterator is dropped are removed then.
+pub struct Drain<'a, T: 'a, K: Key + 'a = Id> {
+    inner: ::Drain<'a, T, K, Unallocated>,
+}
+
+impl<'a, T: 'a, K: Key> Iterator for Drain<'a, T, K> {
+    type Item = (K, T);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, T: 'a, K: Key> ExactSizeIterator for Drain<'a, T, K> {
+    #[inline]
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+}
+
+impl<'a, T: 'a, K: Key> FusedIterator for Drain<'a, T, K> {}
+
+impl<'a, T: fmt::Debug + 'a, K: Key> fmt::Debug for Drain<'a, T, K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.inner.fmt(f)
+    }
+}
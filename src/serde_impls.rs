@@ -0,0 +1,323 @@
+//! Serialization of maps as a mapping from raw ids to values, so that holes are preserved.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use core::{cmp, fmt};
+
+use serde::de::{self, Deserialize, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+use {Id, IdMap, IdMapDiff, Key, TryReserveError};
+
+const MAP_FIELDS: &[&str] = &["max_id", "values"];
+
+/// Serializes the map as its maximum id, or `None` if it is `K::MAX_ID`, and a mapping from raw
+/// ids to values.
+///
+/// Only maps with the default `LowestFree` policy are supported, since their next id is
+/// determined by their ids, so a round trip reproduces the ids, the values, `next_id()` and
+/// `max_id()` exactly.
+impl<T: Serialize, K: Key> Serialize for IdMap<T, K> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let max_id = self.max_id().to_id();
+        let max_id = if max_id == K::MAX_ID {
+            None
+        } else {
+            Some(max_id)
+        };
+
+        let mut map = serializer.serialize_struct("IdMap", MAP_FIELDS.len())?;
+        map.serialize_field("max_id", &max_id)?;
+        map.serialize_field("values", &RawValues(self))?;
+        map.end()
+    }
+}
+
+// Serializes the values of a map keyed by raw ids.
+struct RawValues<'a, T: 'a, K: 'a>(&'a IdMap<T, K>);
+
+impl<'a, T: Serialize, K: Key> Serialize for RawValues<'a, T, K> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.iter().map(|(id, val)| (id.to_id(), val)))
+    }
+}
+
+/// Deserializes a map written by `Serialize`.
+///
+/// Duplicate ids, and ids greater than the maximum id, are rejected, as is a map which cannot be
+/// allocated. Since a single large id needs room for every smaller one, input which cannot be
+/// trusted should be read with `IdMap::deserialize_with_max_id` instead.
+impl<'de, T: Deserialize<'de>, K: Key> Deserialize<'de> for IdMap<T, K> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        IdMap::deserialize_with_max_id(deserializer, K::from_id(K::MAX_ID))
+    }
+}
+
+impl<T, K: Key> IdMap<T, K> {
+    /// Deserializes a map written by `Serialize`, rejecting any id greater than `max_id` before
+    /// allocating room for it.
+    ///
+    /// The map's maximum id is the smaller of `max_id` and the one it was serialized with.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate serde_json;
+    /// # extern crate id_map;
+    /// # use id_map::IdMap;
+    /// #
+    /// # fn main() {
+    /// let json = r#"{"max_id":null,"values":{"3":"a","5000":"b"}}"#;
+    ///
+    /// let mut de = serde_json::Deserializer::from_str(json);
+    /// let map = IdMap::<String>::deserialize_with_max_id(&mut de, 10_000).unwrap();
+    /// assert_eq!((map.len(), map.max_id()), (2, 10_000));
+    ///
+    /// let mut de = serde_json::Deserializer::from_str(json);
+    /// assert!(IdMap::<String>::deserialize_with_max_id(&mut de, 1000).is_err());
+    /// # }
+    /// ```
+    pub fn deserialize_with_max_id<'de, D>(deserializer: D, max_id: K) -> Result<Self, D::Error>
+    where
+        T: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_struct(
+            "IdMap",
+            MAP_FIELDS,
+            IdMapVisitor {
+                max_id: max_id.to_id(),
+                marker: PhantomData,
+            },
+        )
+    }
+}
+
+struct IdMapVisitor<T, K> {
+    // The largest id the caller accepts.
+    max_id: Id,
+    marker: PhantomData<IdMap<T, K>>,
+}
+
+impl<'de, T: Deserialize<'de>, K: Key> Visitor<'de> for IdMapVisitor<T, K> {
+    type Value = IdMap<T, K>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a maximum id and a map of ids to values")
+    }
+
+    fn visit_seq<M: SeqAccess<'de>>(self, mut access: M) -> Result<Self::Value, M::Error> {
+        let max_id: Option<Id> = access
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let values: RawEntries<T> = access
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        map_from_raw(max_id, self.max_id, values.0)
+    }
+
+    fn visit_map<M: MapAccess<'de>>(self, mut access: M) -> Result<Self::Value, M::Error> {
+        let mut max_id = None;
+        let mut values = None;
+        while let Some(field) = access.next_key::<String>()? {
+            match field.as_str() {
+                "max_id" if max_id.is_none() => max_id = Some(access.next_value()?),
+                "values" if values.is_none() => {
+                    values = Some(access.next_value::<RawEntries<T>>()?.0)
+                }
+                "max_id" | "values" => {
+                    return Err(de::Error::custom(format_args!(
+                        "duplicate field `{}`",
+                        field
+                    )))
+                }
+                _ => {
+                    access.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        map_from_raw(
+            max_id.ok_or_else(|| de::Error::missing_field("max_id"))?,
+            self.max_id,
+            values.ok_or_else(|| de::Error::missing_field("values"))?,
+        )
+    }
+}
+
+// Builds a map from its serialized maximum id and values, with its maximum id capped at `limit`.
+fn map_from_raw<T, K: Key, E: de::Error>(
+    max_id: Option<Id>,
+    limit: Id,
+    values: Vec<(Id, T)>,
+) -> Result<IdMap<T, K>, E> {
+    let mut map: IdMap<T, K> = IdMap::with_capacity_and_key(values.len());
+    if let Some(max_id) = max_id {
+        map.set_max_id(key(max_id)?);
+    }
+    if limit < map.max_id().to_id() {
+        map.set_max_id(K::from_id(limit));
+    }
+    for (id, val) in values {
+        let key = key(id)?;
+        if map.contains(key) {
+            return Err(E::custom(format_args!("duplicate id {}", id)));
+        }
+        map.try_insert_at(key, val).map_err(E::custom)?;
+    }
+    Ok(map)
+}
+
+// Deserializes a mapping from raw ids to values, in the order they appear.
+struct RawEntries<T>(Vec<(Id, T)>);
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for RawEntries<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(RawEntriesVisitor {
+            marker: PhantomData,
+        })
+    }
+}
+
+struct RawEntriesVisitor<T> {
+    marker: PhantomData<T>,
+}
+
+impl<'de, T: Deserialize<'de>> Visitor<'de> for RawEntriesVisitor<T> {
+    type Value = RawEntries<T>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a map of ids to values")
+    }
+
+    fn visit_map<M: MapAccess<'de>>(self, mut access: M) -> Result<Self::Value, M::Error> {
+        // Don't trust the size hint with a huge allocation.
+        let cap = cmp::min(access.size_hint().unwrap_or(0), 4096);
+        let mut entries = Vec::with_capacity(cap);
+        while let Some(entry) = access.next_entry()? {
+            entries.push(entry);
+        }
+        Ok(RawEntries(entries))
+    }
+}
+
+const DIFF_FIELDS: &[&str] = &["inserted", "removed", "changed"];
+
+impl<T: Serialize, K: Key> Serialize for IdMapDiff<T, K> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut diff = serializer.serialize_struct("IdMapDiff", DIFF_FIELDS.len())?;
+        diff.serialize_field("inserted", &RawPairs(&self.inserted))?;
+        diff.serialize_field("removed", &RawKeys(&self.removed))?;
+        diff.serialize_field("changed", &RawPairs(&self.changed))?;
+        diff.end()
+    }
+}
+
+// Serializes id-value pairs with raw ids.
+struct RawPairs<'a, T: 'a, K: 'a>(&'a [(K, T)]);
+
+impl<'a, T: Serialize, K: Key> Serialize for RawPairs<'a, T, K> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.0.iter().map(|&(id, ref val)| (id.to_id(), val)))
+    }
+}
+
+// Serializes keys as raw ids.
+struct RawKeys<'a, K: 'a>(&'a [K]);
+
+impl<'a, K: Key> Serialize for RawKeys<'a, K> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.0.iter().map(|id| id.to_id()))
+    }
+}
+
+/// Deserializes a diff written by `Serialize`, rejecting ids which do not fit the key type.
+///
+/// The ids are not otherwise limited, so a diff which cannot be trusted should be applied with
+/// `IdMap::try_apply`, which checks them against the map's maximum id before allocating.
+impl<'de, T: Deserialize<'de>, K: Key> Deserialize<'de> for IdMapDiff<T, K> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_struct(
+            "IdMapDiff",
+            DIFF_FIELDS,
+            IdMapDiffVisitor {
+                marker: PhantomData,
+            },
+        )
+    }
+}
+
+struct IdMapDiffVisitor<T, K> {
+    marker: PhantomData<IdMapDiff<T, K>>,
+}
+
+impl<'de, T: Deserialize<'de>, K: Key> Visitor<'de> for IdMapDiffVisitor<T, K> {
+    type Value = IdMapDiff<T, K>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a diff of inserted, removed and changed ids")
+    }
+
+    fn visit_seq<M: SeqAccess<'de>>(self, mut access: M) -> Result<Self::Value, M::Error> {
+        let inserted: Vec<(Id, T)> = access
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let removed: Vec<Id> = access
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        let changed: Vec<(Id, T)> = access
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(2, &self))?;
+        diff_from_raw(inserted, removed, changed)
+    }
+
+    fn visit_map<M: MapAccess<'de>>(self, mut access: M) -> Result<Self::Value, M::Error> {
+        let mut inserted = None;
+        let mut removed = None;
+        let mut changed = None;
+        while let Some(field) = access.next_key::<String>()? {
+            match field.as_str() {
+                "inserted" if inserted.is_none() => inserted = Some(access.next_value()?),
+                "removed" if removed.is_none() => removed = Some(access.next_value()?),
+                "changed" if changed.is_none() => changed = Some(access.next_value()?),
+                "inserted" | "removed" | "changed" => {
+                    return Err(de::Error::custom(format_args!(
+                        "duplicate field `{}`",
+                        field
+                    )))
+                }
+                _ => {
+                    access.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        diff_from_raw(
+            inserted.ok_or_else(|| de::Error::missing_field("inserted"))?,
+            removed.ok_or_else(|| de::Error::missing_field("removed"))?,
+            changed.ok_or_else(|| de::Error::missing_field("changed"))?,
+        )
+    }
+}
+
+fn diff_from_raw<T, K: Key, E: de::Error>(
+    inserted: Vec<(Id, T)>,
+    removed: Vec<Id>,
+    changed: Vec<(Id, T)>,
+) -> Result<IdMapDiff<T, K>, E> {
+    Ok(IdMapDiff {
+        inserted: inserted
+            .into_iter()
+            .map(|(id, val)| Ok((key(id)?, val)))
+            .collect::<Result<_, E>>()?,
+        removed: removed.into_iter().map(key).collect::<Result<_, E>>()?,
+        changed: changed
+            .into_iter()
+            .map(|(id, val)| Ok((key(id)?, val)))
+            .collect::<Result<_, E>>()?,
+    })
+}
+
+// Converts a deserialized id to a key, failing if it does not fit.
+fn key<K: Key, E: de::Error>(id: Id) -> Result<K, E> {
+    K::try_from_id(id).ok_or_else(|| E::custom(TryReserveError::IdTooLarge(id)))
+}
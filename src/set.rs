@@ -0,0 +1,376 @@
+//! A growable bitset of ids, which the maps use to record their ids.
+//!
+//! [`IdSet`] is the same type with or without the `std` feature, and provides the part of the
+//! `id-set` crate's API which the maps use and return.
+//!
+//! [`IdSet`]: struct.IdSet.html
+
+use alloc::vec::Vec;
+use core::fmt;
+use core::iter::{FromIterator, FusedIterator};
+use core::ops::Range;
+
+use bits::BlockIds;
+use {Id, TryReserveError};
+
+/// The type of the words of an `IdSet`.
+pub type Block = u32;
+
+/// The number of ids in a `Block`.
+pub const BITS: usize = 32;
+
+#[derive(Clone, Default)]
+/// A set of ids, stored as a bitset.
+pub struct IdSet {
+    blocks: Vec<Block>,
+    // The number of set bits.
+    len: usize,
+}
+
+impl IdSet {
+    #[inline]
+    /// Creates an empty `IdSet`.
+    pub fn new() -> Self {
+        IdSet::default()
+    }
+
+    /// Creates an `IdSet` containing the ids `0..n`.
+    pub fn new_filled(n: usize) -> Self {
+        let mut blocks = Vec::with_capacity(n.div_ceil(BITS));
+        blocks.resize(n / BITS, !0);
+        if n % BITS != 0 {
+            blocks.push((1 << (n % BITS)) - 1);
+        }
+        IdSet { blocks, len: n }
+    }
+
+    #[inline]
+    /// Creates an empty `IdSet` with room for the ids `0..n`.
+    pub fn with_capacity(n: usize) -> Self {
+        IdSet {
+            blocks: Vec::with_capacity(n.div_ceil(BITS)),
+            len: 0,
+        }
+    }
+
+    #[inline]
+    /// Returns the number of ids in the set.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    /// Returns true if the set contains no ids.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    #[inline]
+    /// Returns the number of ids the set can hold before reallocating.
+    pub fn capacity(&self) -> usize {
+        self.blocks.capacity().saturating_mul(BITS)
+    }
+
+    #[inline]
+    /// Resizes the set such that `capacity() >= cap`.
+    pub fn reserve(&mut self, cap: usize) {
+        self.blocks
+            .reserve(cap.div_ceil(BITS).saturating_sub(self.blocks.len()));
+    }
+
+    #[inline]
+    /// Tries to resize the set such that `capacity() >= cap`, returning an error if the allocation
+    /// fails.
+    pub fn try_reserve(&mut self, cap: usize) -> Result<(), TryReserveError> {
+        self.blocks
+            .try_reserve(cap.div_ceil(BITS).saturating_sub(self.blocks.len()))?;
+        Ok(())
+    }
+
+    #[inline]
+    /// Tries to make room for `id` to be inserted without reallocating, returning an error if the
+    /// allocation fails.
+    pub fn try_reserve_id(&mut self, id: Id) -> Result<(), TryReserveError> {
+        let len = id / BITS + 1;
+        if self.blocks.len() < len {
+            self.blocks.try_reserve(len - self.blocks.len())?;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    /// Resizes the set to minimize allocated memory.
+    pub fn shrink_to_fit(&mut self) {
+        let len = self.blocks.iter().rposition(|&word| word != 0);
+        self.blocks.truncate(len.map_or(0, |idx| idx + 1));
+        self.blocks.shrink_to_fit();
+    }
+
+    #[inline]
+    /// Removes all ids from the set.
+    pub fn clear(&mut self) {
+        self.blocks.clear();
+        self.len = 0;
+    }
+
+    #[inline]
+    /// Adds an id to the set, returning true if it was not already in the set.
+    pub fn insert(&mut self, id: Id) -> bool {
+        let (idx, mask) = (id / BITS, 1 << (id % BITS));
+        if self.blocks.len() <= idx {
+            self.blocks.resize(idx + 1, 0);
+        }
+        if self.blocks[idx] & mask != 0 {
+            return false;
+        }
+        self.blocks[idx] |= mask;
+        self.len += 1;
+        true
+    }
+
+    #[inline]
+    /// Removes an id from the set, returning true if it was in the set.
+    pub fn remove(&mut self, id: Id) -> bool {
+        let mask = 1 << (id % BITS);
+        match self.blocks.get_mut(id / BITS) {
+            Some(word) if *word & mask != 0 => {
+                *word &= !mask;
+                self.len -= 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    #[inline]
+    /// Returns true if the set contains the id.
+    pub fn contains(&self, id: Id) -> bool {
+        self.blocks
+            .get(id / BITS)
+            .is_some_and(|word| word & (1 << (id % BITS)) != 0)
+    }
+
+    /// Removes all ids not satisfying the predicate.
+    pub fn retain<F: FnMut(Id) -> bool>(&mut self, mut pred: F) {
+        for (idx, word) in self.blocks.iter_mut().enumerate() {
+            let mut bits = *word;
+            while bits != 0 {
+                let bit = bits.trailing_zeros() as usize;
+                bits &= bits - 1;
+                if !pred(idx * BITS + bit) {
+                    *word &= !(1 << bit);
+                    self.len -= 1;
+                }
+            }
+        }
+    }
+
+    #[inline]
+    /// Returns the words of the set. Id `id` is bit `id % BITS` of word `id / BITS`.
+    pub fn as_blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+
+    #[inline]
+    /// Converts the set into its words, laid out as in `as_blocks`.
+    pub fn into_blocks(self) -> Vec<Block> {
+        self.blocks
+    }
+
+    #[inline]
+    /// An iterator over the ids in the set, in increasing order.
+    pub fn iter(&self) -> Iter<'_> {
+        Combination::new(self, self, |a, _| a, self.blocks.len()).into_iter()
+    }
+
+    #[inline]
+    /// The ids in both sets.
+    pub fn intersection<'a>(&'a self, other: &'a IdSet) -> Combination<'a> {
+        let len = Ord::min(self.blocks.len(), other.blocks.len());
+        Combination::new(self, other, |a, b| a & b, len)
+    }
+
+    #[inline]
+    /// The ids in this set but not in `other`.
+    pub fn difference<'a>(&'a self, other: &'a IdSet) -> Combination<'a> {
+        Combination::new(self, other, |a, b| a & !b, self.blocks.len())
+    }
+
+    /// Removes the ids in `other` from the set.
+    pub fn inplace_difference(&mut self, other: &IdSet) {
+        for (word, &other) in self.blocks.iter_mut().zip(&other.blocks) {
+            self.len -= (*word & other).count_ones() as usize;
+            *word &= !other;
+        }
+    }
+
+    /// Returns the words of the set without trailing empty words.
+    fn trimmed(&self) -> &[Block] {
+        let len = self.blocks.iter().rposition(|&word| word != 0);
+        &self.blocks[..len.map_or(0, |idx| idx + 1)]
+    }
+}
+
+impl fmt::Debug for IdSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self).finish()
+    }
+}
+
+impl Eq for IdSet {}
+
+impl PartialEq for IdSet {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.trimmed() == other.trimmed()
+    }
+}
+
+impl Extend<Id> for IdSet {
+    #[inline]
+    fn extend<I: IntoIterator<Item = Id>>(&mut self, iter: I) {
+        for id in iter {
+            self.insert(id);
+        }
+    }
+}
+
+impl FromIterator<Id> for IdSet {
+    #[inline]
+    fn from_iter<I: IntoIterator<Item = Id>>(iter: I) -> Self {
+        let mut set = IdSet::new();
+        set.extend(iter);
+        set
+    }
+}
+
+impl<'a> IntoIterator for &'a IdSet {
+    type Item = Id;
+    type IntoIter = Iter<'a>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// The words of one or two sets, combined word by word.
+#[derive(Clone, Debug)]
+struct Combined<'a> {
+    a: &'a [Block],
+    b: &'a [Block],
+    op: fn(Block, Block) -> Block,
+    range: Range<usize>,
+}
+
+impl<'a> Combined<'a> {
+    #[inline]
+    fn word(&self, idx: usize) -> Block {
+        let word = |blocks: &[Block]| blocks.get(idx).cloned().unwrap_or(0);
+        (self.op)(word(self.a), word(self.b))
+    }
+}
+
+impl<'a> Iterator for Combined<'a> {
+    type Item = Block;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.range.next().map(|idx| self.word(idx))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.range.size_hint()
+    }
+}
+
+impl<'a> DoubleEndedIterator for Combined<'a> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.range.next_back().map(|idx| self.word(idx))
+    }
+}
+
+impl<'a> ExactSizeIterator for Combined<'a> {}
+
+#[derive(Clone, Debug)]
+/// A lazy combination of two sets, as returned by `IdSet::intersection`, which can be iterated or
+/// collected into a new set.
+pub struct Combination<'a> {
+    blocks: Combined<'a>,
+}
+
+impl<'a> Combination<'a> {
+    #[inline]
+    fn new(a: &'a IdSet, b: &'a IdSet, op: fn(Block, Block) -> Block, len: usize) -> Self {
+        Combination {
+            blocks: Combined {
+                a: &a.blocks,
+                b: &b.blocks,
+                op,
+                range: 0..len,
+            },
+        }
+    }
+
+    /// Collects the ids into a new set.
+    pub fn into_set(self) -> IdSet {
+        let blocks: Vec<_> = self.blocks.collect();
+        let len = blocks.iter().map(|word| word.count_ones() as usize).sum();
+        IdSet { blocks, len }
+    }
+}
+
+impl<'a> IntoIterator for Combination<'a> {
+    type Item = Id;
+    type IntoIter = Iter<'a>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        let len = self
+            .blocks
+            .clone()
+            .map(|word| word.count_ones() as usize)
+            .sum();
+        Iter {
+            ids: BlockIds::new(self.blocks, 0, len),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+/// An iterator over the ids of a set, or of a combination of two sets, in increasing order.
+pub struct Iter<'a> {
+    ids: BlockIds<Combined<'a>>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = Id;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.ids.next()
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.ids.size_hint()
+    }
+}
+
+impl<'a> DoubleEndedIterator for Iter<'a> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.ids.next_back()
+    }
+}
+
+impl<'a> ExactSizeIterator for Iter<'a> {
+    #[inline]
+    fn len(&self) -> usize {
+        self.ids.len()
+    }
+}
+
+impl<'a> FusedIterator for Iter<'a> {}
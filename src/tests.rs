@@ -1,5 +1,8 @@
 use super::*;
 
+use std::iter::FromIterator;
+use std::ops::Bound;
+
 #[test]
 fn id_map() {
     let mut ids = IdMap::<Box<u32>>::new();
@@ -56,7 +59,7 @@ fn iter_mut() {
     let mut refs: Vec<&mut u32> = ids.values_mut().collect();
 
     refs.sort();
-    refs.dedup_by(|l, r| *l as *mut u32 == *r as *mut u32);
+    refs.dedup_by(|l, r| std::ptr::eq(*l, *r));
 
     assert_eq!(refs.len(), 5)
 }
@@ -130,6 +133,7 @@ fn ubsan() {
     ids2.retain(|_, &Test(val)| val % 2 != 0);
 
     ids2.clear();
+    ids2.assert_invariant();
     ids2.clone_from(&ids);
 
     ids2.shrink_to_fit();
@@ -209,6 +213,16 @@ fn remove_set() {
     assert_eq!(vals, expected);
 }
 
+#[test]
+fn clear() {
+    let mut ids = IdMap::from_iter(0..5);
+    ids.remove(1);
+    ids.clear();
+    ids.assert_invariant();
+    assert_eq!(ids.next_id(), 0);
+    assert_eq!(ids.insert(5), 0);
+}
+
 #[test]
 fn next_id() {
     let mut map1 = IdMap::new();
@@ -237,3 +251,1545 @@ fn next_id() {
     map3.remove_set(&set);
     assert_eq!(map3.next_id(), 1);
 }
+
+#[test]
+fn generational() {
+    let mut map = GenIdMap::new();
+    map.assert_invariant();
+
+    let a = map.insert("a");
+    let b = map.insert("b");
+    map.assert_invariant();
+    assert_eq!(a.index(), 0);
+    assert_eq!(b.index(), 1);
+
+    assert_eq!(map.remove(a), Some("a"));
+    map.assert_invariant();
+    assert_eq!(map.remove(a), None);
+
+    let c = map.insert("c");
+    map.assert_invariant();
+    assert_eq!(c.index(), a.index());
+    assert_ne!(c, a);
+    assert!(!map.contains(a));
+    assert_eq!(map.get(a), None);
+    assert_eq!(map.get_mut(a), None);
+    assert_eq!(map[c], "c");
+    assert_eq!(map[b], "b");
+
+    map.retain(|_, &val| val != "b");
+    map.assert_invariant();
+    assert!(!map.contains(b));
+    assert_eq!(map.next_id(), GenId::new(1, 1));
+
+    let ids: Vec<_> = map.iter().map(|(id, _)| id).collect();
+    assert_eq!(ids, vec![c]);
+    assert_eq!(map.into_iter().collect::<Vec<_>>(), vec![(c, "c")]);
+}
+
+#[test]
+#[should_panic(expected = "is not valid")]
+fn generational_stale_index() {
+    let mut map = GenIdMap::new();
+    let id = map.insert(0);
+    map.remove(id);
+    map.insert(1);
+
+    map[id] = 2;
+}
+
+#[test]
+fn key() {
+    new_key_type! {
+        struct NodeId;
+    }
+
+    let mut nodes = IdMap::<&str, NodeId>::with_key();
+    let a = nodes.insert("a");
+    let b = nodes.insert("b");
+    nodes.assert_invariant();
+    assert_eq!(a, NodeId(0));
+    assert_eq!(nodes[b], "b");
+    assert_eq!(nodes.next_id(), NodeId(2));
+
+    assert_eq!(nodes.remove(a), Some("a"));
+    assert_eq!(nodes.insert_at(NodeId(4), "c"), None);
+    nodes.assert_invariant();
+    assert_eq!(nodes.ids().collect::<Vec<_>>(), vec![b, NodeId(4)]);
+
+    let copy: IdMap<_, NodeId> = nodes.iter().map(|(id, &val)| (id, val)).collect();
+    assert_eq!(copy, nodes);
+    assert_eq!(format!("{:?}", copy), r#"{NodeId(1): "b", NodeId(4): "c"}"#);
+}
+
+#[test]
+fn entry() {
+    let mut ids = IdMap::from_iter(0..5);
+
+    match ids.entry(2) {
+        Entry::Occupied(mut entry) => {
+            assert_eq!(entry.id(), 2);
+            assert_eq!(*entry.get(), 2);
+            *entry.get_mut() += 10;
+            assert_eq!(entry.insert(20), 12);
+            assert_eq!(entry.remove_entry(), (2, 20));
+        }
+        Entry::Vacant(_) => panic!("expected occupied entry"),
+    }
+    ids.assert_invariant();
+    assert_eq!(ids.next_id(), 2);
+
+    assert_eq!(*ids.entry(2).or_insert_with_key(|id| id * 100), 200);
+    ids.assert_invariant();
+    assert_eq!(ids.next_id(), 5);
+
+    assert_eq!(*ids.entry(2).and_modify(|val| *val += 1).or_default(), 201);
+    assert_eq!(*ids.entry(7).and_modify(|val| *val += 1).or_default(), 0);
+    ids.assert_invariant();
+    assert_eq!(*ids.entry(5).or_insert(50), 50);
+    ids.assert_invariant();
+    assert_eq!(ids.next_id(), 6);
+
+    match ids.entry(6) {
+        Entry::Vacant(entry) => assert_eq!(entry.id(), 6),
+        Entry::Occupied(_) => panic!("expected vacant entry"),
+    }
+    ids.assert_invariant();
+    assert!(!ids.contains(6));
+
+    if let Entry::Occupied(entry) = ids.entry(3) {
+        if *entry.get() % 2 != 0 {
+            assert_eq!(entry.remove(), 3);
+        }
+    }
+    ids.assert_invariant();
+    assert_eq!(ids.next_id(), 3);
+}
+
+#[test]
+fn vacant_entry() {
+    let mut ids = IdMap::from_iter(0..5);
+    ids.remove(2);
+
+    {
+        let entry = ids.vacant_entry();
+        assert_eq!(entry.id(), 2);
+    }
+    ids.assert_invariant();
+    assert!(!ids.contains(2));
+    assert_eq!(ids.next_id(), 2);
+    assert_eq!(ids.len(), 4);
+
+    assert_eq!(*ids.vacant_entry().insert_with(|id| id * 10), 20);
+    ids.assert_invariant();
+    assert_eq!(ids.next_id(), 5);
+
+    assert_eq!(*ids.vacant_entry().insert(7), 7);
+    ids.assert_invariant();
+    assert_eq!(ids[5], 7);
+}
+
+#[test]
+#[cfg(feature = "serde")]
+fn serde() {
+    let mut ids = IdMap::from_iter(0..5);
+    ids.remove(1);
+    ids.remove(3);
+    ids.insert_at(8, 8);
+
+    let json = serde_json::to_string(&ids).unwrap();
+    assert_eq!(
+        json,
+        r#"{"max_id":null,"values":{"0":0,"2":2,"4":4,"8":8}}"#
+    );
+
+    let de: IdMap<u32> = serde_json::from_str(&json).unwrap();
+    de.assert_invariant();
+    assert_eq!(de, ids);
+    assert_eq!(de.as_set(), ids.as_set());
+    assert_eq!(de.next_id(), 1);
+    assert_eq!(de.max_id(), Id::MAX);
+
+    ids.set_max_id(100);
+    let json = serde_json::to_string(&ids).unwrap();
+    assert_eq!(json, r#"{"max_id":100,"values":{"0":0,"2":2,"4":4,"8":8}}"#);
+    let de: IdMap<u32> = serde_json::from_str(&json).unwrap();
+    assert_eq!(de.max_id(), 100);
+    let de: IdMap<u32> = serde_json::from_str(r#"[100,{"0":0,"2":2,"4":4,"8":8}]"#).unwrap();
+    assert_eq!((de.max_id(), de.next_id()), (100, 1));
+    assert_eq!(de, ids);
+
+    let err = serde_json::from_str::<IdMap<u32>>(r#"{"max_id":null,"values":{"0":0,"0":1}}"#)
+        .unwrap_err();
+    assert!(err.to_string().contains("duplicate id 0"));
+    let err = serde_json::from_str::<IdMap<u32>>(r#"{"max_id":5,"values":{"8":0}}"#).unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("id 8 is greater than the maximum id"));
+
+    // Sparse maps round trip however large their ids are.
+    let mut sparse = IdMap::new();
+    sparse.insert_at(5000, 0u32);
+    let json = serde_json::to_string(&sparse).unwrap();
+    let de: IdMap<u32> = serde_json::from_str(&json).unwrap();
+    de.assert_invariant();
+    assert_eq!((de.as_set(), de.next_id()), (sparse.as_set(), 0));
+
+    // An explicit limit rejects large ids before anything is allocated.
+    let json = format!(r#"{{"max_id":null,"values":{{"{}":0}}}}"#, Id::MAX);
+    let mut deserializer = serde_json::Deserializer::from_str(&json);
+    let err = IdMap::<u32>::deserialize_with_max_id(&mut deserializer, 4096).unwrap_err();
+    assert!(err.to_string().contains("is greater than the maximum id"));
+    let mut deserializer = serde_json::Deserializer::from_str(r#"{"max_id":9000,"values":{}}"#);
+    let de = IdMap::<u32>::deserialize_with_max_id(&mut deserializer, 4096).unwrap();
+    assert_eq!(de.max_id(), 4096);
+    let mut deserializer = serde_json::Deserializer::from_str(r#"{"max_id":10,"values":{}}"#);
+    let de = IdMap::<u32>::deserialize_with_max_id(&mut deserializer, 4096).unwrap();
+    assert_eq!(de.max_id(), 10);
+    let err =
+        serde_json::from_str::<IdMap<u32, u16>>(r#"{"max_id":70000,"values":{}}"#).unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("id 70000 is greater than the maximum id"));
+
+    let diff = ids.diff(&IdMap::from_iter(0..3));
+    let json = serde_json::to_string(&diff).unwrap();
+    assert_eq!(json, r#"{"inserted":[[1,1]],"removed":[4,8],"changed":[]}"#);
+    let de: IdMapDiff<u32> = serde_json::from_str(&json).unwrap();
+    assert_eq!(de, diff);
+    let de: IdMapDiff<u32> = serde_json::from_str(r#"[[[1,1]],[4,8],[]]"#).unwrap();
+    assert_eq!(de, diff);
+    let err = serde_json::from_str::<IdMapDiff<u32>>(r#"{"inserted":[]}"#).unwrap_err();
+    assert!(err.to_string().contains("missing field `removed`"));
+    let json = r#"{"inserted":[],"removed":[70000],"changed":[]}"#;
+    assert!(serde_json::from_str::<IdMapDiff<u32, u16>>(json).is_err());
+
+    // Untrusted diffs are checked against the maximum id of the map they are applied to.
+    let json = format!(
+        r#"{{"inserted":[[{},0]],"removed":[0],"changed":[]}}"#,
+        Id::MAX
+    );
+    let de: IdMapDiff<u32> = serde_json::from_str(&json).unwrap();
+    let mut replica = ids.clone();
+    assert_eq!(
+        replica.try_apply(de),
+        Err(TryReserveError::IdTooLarge(Id::MAX))
+    );
+    assert_eq!(replica, ids);
+}
+
+#[test]
+#[cfg(feature = "rayon")]
+fn par_iter() {
+    use rayon::prelude::*;
+
+    let mut ids = IdMap::from_iter(0..1000);
+    ids.remove_set(&(100..300).collect());
+    ids.retain(|id, _| id % 7 != 0);
+
+    let mut pairs: Vec<_> = ids.par_iter().map(|(id, &val)| (id, val)).collect();
+    pairs.sort();
+    assert_eq!(
+        pairs,
+        ids.iter().map(|(id, &val)| (id, val)).collect::<Vec<_>>()
+    );
+    assert_eq!(ids.par_values().sum::<u32>(), ids.values().sum::<u32>());
+
+    ids.par_iter_mut().for_each(|(id, val)| *val += id as u32);
+    ids.par_values_mut().for_each(|val| *val *= 2);
+    for (id, &val) in &ids {
+        assert_eq!(val, id as u32 * 4);
+    }
+
+    let mut owned: Vec<_> = ids.clone().into_par_iter().collect();
+    owned.sort();
+    assert_eq!(owned, ids.clone().into_iter().collect::<Vec<_>>());
+
+    let mut expected = ids.clone();
+    expected.retain(|id, _| id % 3 != 0);
+    ids.par_retain(|id, _| id % 3 != 0);
+    ids.assert_invariant();
+    assert_eq!(ids, expected);
+    assert_eq!(ids.next_id(), 0);
+}
+
+#[test]
+fn iter_first_id_vacant() {
+    let mut ids = IdMap::from_iter(0..5);
+    ids.remove(0);
+    ids.remove(1);
+
+    assert_eq!(
+        ids.values_mut().map(|&mut val| val).collect::<Vec<_>>(),
+        vec![2, 3, 4]
+    );
+    assert_eq!(
+        ids.iter_mut().map(|(id, _)| id).collect::<Vec<_>>(),
+        vec![2, 3, 4]
+    );
+    assert_eq!(
+        ids.into_iter().collect::<Vec<_>>(),
+        vec![(2, 2), (3, 3), (4, 4)]
+    );
+}
+
+#[test]
+fn double_ended() {
+    let mut ids = IdMap::from_iter(0..100);
+    ids.retain(|id, _| id % 3 == 1);
+
+    let forward: Vec<_> = ids.ids().collect();
+    let mut backward: Vec<_> = ids.ids().rev().collect();
+    backward.reverse();
+    assert_eq!(forward, backward);
+    assert_eq!(ids.ids().next_back(), Some(97));
+    assert_eq!(ids.values().next_back(), Some(&97));
+
+    let mut iter = ids.iter();
+    assert_eq!(iter.next(), Some((1, &1)));
+    assert_eq!(iter.next_back(), Some((97, &97)));
+    assert_eq!(iter.len(), 31);
+    let middle: Vec<_> = iter.by_ref().map(|(id, _)| id).collect();
+    assert_eq!(middle, (1..32).map(|n| n * 3 + 1).collect::<Vec<_>>());
+    assert_eq!(iter.next(), None);
+    assert_eq!(iter.next_back(), None);
+
+    // Alternate ends so that both meet in the middle of a block.
+    let mut iter = ids.iter_mut();
+    let mut seen = Vec::new();
+    while let Some((id, val)) = iter.next() {
+        seen.push((id, *val));
+        match iter.next_back() {
+            Some((id, val)) => seen.push((id, *val)),
+            None => break,
+        }
+    }
+    seen.sort();
+    assert_eq!(
+        seen,
+        ids.iter().map(|(id, &val)| (id, val)).collect::<Vec<_>>()
+    );
+
+    for val in ids.values_mut().rev().take(2) {
+        *val = 0;
+    }
+    assert_eq!(ids[94], 0);
+    assert_eq!(ids[97], 0);
+
+    let mut iter = ids.clone().into_iter();
+    assert_eq!(iter.next_back(), Some((97, 0)));
+    assert_eq!(iter.next(), Some((1, 1)));
+    assert_eq!(iter.len(), 31);
+    assert_eq!(iter.rev().nth(1), Some((91, 91)));
+
+    let map = GenIdMap::from_iter(0..5);
+    assert_eq!(map.iter().next_back(), Some((GenId::new(4, 0), &4)));
+}
+
+#[test]
+fn range() {
+    let mut ids = IdMap::from_iter(0..200);
+    ids.retain(|id, _| id % 5 != 0);
+
+    for &(start, end) in &[
+        (0, 200),
+        (3, 7),
+        (31, 33),
+        (32, 64),
+        (30, 170),
+        (64, 64),
+        (190, 400),
+    ] {
+        let expected: Vec<_> = (start..end).filter(|id| ids.contains(*id)).collect();
+        let actual: Vec<_> = ids.range(start..end).map(|(id, _)| id).collect();
+        assert_eq!(actual, expected);
+        assert_eq!(ids.ids_in(start..end).len(), expected.len());
+        assert_eq!(ids.ids_in(start..end).rev().collect::<Vec<_>>(), {
+            let mut rev = expected.clone();
+            rev.reverse();
+            rev
+        });
+    }
+
+    assert_eq!(ids.ids_in(..=3).collect::<Vec<_>>(), vec![1, 2, 3]);
+    assert_eq!(
+        ids.iter_from(197).collect::<Vec<_>>(),
+        vec![(197, &197), (198, &198), (199, &199)]
+    );
+    assert_eq!(ids.range(500..).next(), None);
+    assert_eq!(
+        ids.range((Bound::Excluded(10), Bound::Included(10))).next(),
+        None
+    );
+
+    for (id, val) in ids.range_mut(40..45) {
+        *val = id * 10;
+    }
+    assert_eq!(ids[39], 39);
+    assert_eq!(ids[41], 410);
+    assert_eq!(ids[44], 440);
+    assert_eq!(ids[46], 46);
+    assert_eq!(
+        ids.range_mut(40..45).next_back().map(|(id, _)| id),
+        Some(44)
+    );
+}
+
+#[test]
+fn drain() {
+    let mut ids = IdMap::from_iter(0..100);
+    let cap = ids.capacity();
+
+    let drained: Vec<_> = ids.drain().collect();
+    ids.assert_invariant();
+    assert_eq!(drained, (0..100).map(|n| (n, n)).collect::<Vec<_>>());
+    assert!(ids.is_empty());
+    assert_eq!(ids.capacity(), cap);
+    assert_eq!(ids.next_id(), 0);
+
+    let mut ids = IdMap::from_iter(0..100);
+    {
+        let mut drain = ids.drain_range(40..60);
+        assert_eq!(drain.len(), 20);
+        assert_eq!(drain.next(), Some((40, 40)));
+        assert_eq!(drain.len(), 19);
+    }
+    ids.assert_invariant();
+    assert_eq!(ids.len(), 80);
+    assert_eq!(ids.next_id(), 40);
+    assert!(!ids.contains(59));
+    assert!(ids.contains(60));
+
+    // Leaking the iterator only removes the pairs it yielded.
+    let mut drain = ids.drain_range(..10);
+    drain.next();
+    drain.next();
+    std::mem::forget(drain);
+    ids.assert_invariant();
+    assert_eq!(ids.len(), 78);
+    assert_eq!(ids.next_id(), 0);
+}
+
+#[test]
+fn extract_if() {
+    let mut ids = IdMap::from_iter(0..100);
+
+    {
+        let mut iter = ids.extract_if(|id, val| {
+            *val += 1;
+            id % 10 == 5
+        });
+        assert_eq!(iter.next(), Some((5, 6)));
+        assert_eq!(iter.next(), Some((15, 16)));
+    }
+    ids.assert_invariant();
+    assert_eq!(ids.next_id(), 5);
+    assert_eq!(ids.len(), 98);
+    // Only visited values were modified.
+    assert_eq!(ids[14], 15);
+    assert_eq!(ids[16], 16);
+
+    let rest: Vec<_> = ids
+        .extract_if(|id, _| id % 10 == 5)
+        .map(|(id, _)| id)
+        .collect();
+    ids.assert_invariant();
+    assert_eq!(rest, (2..10).map(|n| n * 10 + 5).collect::<Vec<_>>());
+    assert_eq!(ids.len(), 90);
+}
+
+#[test]
+fn compact() {
+    let mut ids = IdMap::from_iter(0..100);
+    ids.retain(|id, _| id % 3 == 0);
+    let mut other = ids.clone();
+    other.remove(9);
+    let set: IdSet = ids.ids().filter(|id| id % 2 == 0).collect();
+
+    let remap = ids.compact();
+    ids.assert_invariant();
+    assert_eq!(ids.len(), 34);
+    assert_eq!(ids.next_id(), 34);
+    assert_eq!(
+        ids.values().cloned().collect::<Vec<_>>(),
+        (0..34).map(|n| n * 3).collect::<Vec<_>>()
+    );
+    assert!(!remap.is_identity());
+    assert_eq!(remap.get(9), Some(3));
+    assert_eq!(remap.get(10), None);
+    assert_eq!(
+        remap.iter().take(2).collect::<Vec<_>>(),
+        vec![(0, 0), (3, 1)]
+    );
+
+    ids.shrink_to_fit();
+    ids.assert_invariant();
+    assert!(ids.capacity() >= 34 && ids.capacity() < 100);
+
+    let mut free: IdMap<_, Id, policy::FreeList> = IdMap::with_key();
+    for (id, &val) in &other {
+        free.insert_at(id, val);
+    }
+    let mut small = other.clone();
+    small.set_max_id(10);
+    let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        remap.apply_to_map(&mut small);
+    }));
+    assert!(res.is_err());
+    small.assert_invariant();
+    assert_eq!(small, other);
+
+    let mut moved = Vec::new();
+    remap.apply_with(|old, new| moved.push((old, new)));
+    assert_eq!(moved.len(), 33);
+    assert_eq!(moved[..2], [(3, 1), (6, 2)]);
+
+    other.set_max_id(40);
+    remap.apply_to_map(&mut other);
+    other.assert_invariant();
+    assert!(!other.contains(3));
+    assert_eq!(other[4], 12);
+    assert_eq!(other.max_id(), 40);
+    assert_eq!(other.next_id(), 3);
+
+    remap.apply_to_map(&mut free);
+    free.assert_invariant();
+    assert_eq!(free.next_id(), 3);
+    assert_eq!(
+        remap.apply_to_set(&set),
+        (0..34).filter(|id| id % 2 == 0).collect()
+    );
+
+    assert!(ids.compact().is_identity());
+}
+
+#[test]
+fn secondary() {
+    let mut primary = IdMap::from_iter(0..10);
+    let mut secondary = SecondaryIdMap::new();
+
+    for id in primary.ids() {
+        if id % 2 == 0 {
+            secondary.insert(id, id * 10);
+        }
+    }
+    secondary.assert_invariant();
+    assert_eq!(secondary.len(), 5);
+    assert_eq!(secondary.insert(4, 44), Some(40));
+    assert_eq!(secondary[4], 44);
+    assert_eq!(secondary.remove(0), Some(0));
+    assert_eq!(secondary.insert(0, 0), None);
+    secondary.assert_invariant();
+
+    // Inserting into the side table doesn't consume ids from the primary map.
+    assert_eq!(primary.next_id(), 10);
+    primary.remove(2);
+    primary.remove(3);
+    primary.remove(8);
+
+    secondary.retain_live(&primary);
+    secondary.assert_invariant();
+    assert_eq!(secondary.ids().collect::<Vec<_>>(), vec![0, 4, 6]);
+
+    secondary.retain_in(&(0..5).collect());
+    secondary.assert_invariant();
+    assert_eq!(
+        secondary.clone().into_iter().collect::<Vec<_>>(),
+        vec![(0, 0), (4, 44)]
+    );
+
+    let mut drain: secondary::Drain<_> = secondary.drain();
+    assert_eq!(drain.len(), 2);
+    assert_eq!(drain.next(), Some((0, 0)));
+    drop(drain);
+    assert!(secondary.is_empty());
+}
+
+#[test]
+fn join() {
+    use join::{maybe, without};
+
+    let a: IdMap<u32> = (0..100).collect();
+    let mut b = IdMap::new();
+    let mut c = SecondaryIdMap::new();
+    let mut d = IdMap::new();
+    for id in (0..200).filter(|id| id % 2 == 0) {
+        b.insert_at(id, id as u32 * 10);
+    }
+    for id in (0..100).filter(|id| id % 3 == 0) {
+        c.insert(id, -(id as i32));
+    }
+    for id in 40..70 {
+        d.insert_at(id, ());
+    }
+
+    let joined: Vec<_> = join!(&a, &b).map(|(id, &x, &y)| (id, x, y)).collect();
+    assert_eq!(joined.len(), 50);
+    assert!(joined
+        .iter()
+        .all(|&(id, x, y)| x == id as u32 && y == x * 10));
+
+    for (id, x, y, _) in join!(&a, &mut b, &c) {
+        assert_eq!(id % 6, 0);
+        *y += x;
+    }
+    assert_eq!(b[6], 66);
+    assert_eq!(b[4], 40);
+    assert_eq!(b[102], 1020);
+
+    let ids: Vec<_> = join!(&c, without(&d)).map(|(id, _, ())| id).collect();
+    assert_eq!(ids.len(), 24);
+    assert!(ids.iter().all(|&id| id % 3 == 0 && !(40..70).contains(&id)));
+    let ids: Vec<_> = join!(&d, without(&c)).map(|(id, _, ())| id).collect();
+    assert_eq!(ids.len(), 20);
+    assert!(ids.iter().all(|&id| id % 3 != 0));
+
+    let mut seen = 0;
+    for (id, _, y, z) in join!(&a, maybe(&mut b), maybe(&mut c)) {
+        assert_eq!(y.is_some(), id % 2 == 0);
+        assert_eq!(z.is_some(), id % 3 == 0);
+        if let Some(z) = z {
+            *z = 1;
+        }
+        seen += 1;
+    }
+    assert_eq!(seen, 100);
+    assert!(c.values().all(|&z| z == 1));
+
+    let empty: IdMap<u32> = IdMap::new();
+    assert_eq!(join!(&empty, &a).count(), 0);
+}
+
+#[test]
+fn get_many_mut() {
+    let mut map: IdMap<_> = (0..100).collect();
+    map.remove(50);
+
+    {
+        let [a, b, c] = map.get_many_mut([70, 3, 40]).unwrap();
+        assert_eq!((*a, *b, *c), (70, 3, 40));
+        *a += 1000;
+        std::mem::swap(b, c);
+    }
+    assert_eq!((map[70], map[3], map[40]), (1070, 40, 3));
+    map.assert_invariant();
+
+    assert_eq!(map.get_many_mut([3, 50]), Err(GetManyError::Missing(50)));
+    assert_eq!(map.get_many_mut([3, 200]), Err(GetManyError::Missing(200)));
+    assert_eq!(map.get_many_mut([3, 4, 3]), Err(GetManyError::Duplicate(3)));
+    assert_eq!(map.get_many_mut::<0>([]), Ok([]));
+    assert_eq!(map.get2_mut(0, 0), Err(GetManyError::Duplicate(0)));
+
+    let (x, y) = map.get2_mut(99, 1).unwrap();
+    *x += *y;
+    assert_eq!(map[99], 100);
+
+    let mut gen_map: GenIdMap<u32> = (0..4).collect();
+    let ids: Vec<_> = gen_map.ids().collect();
+    gen_map.remove(ids[1]);
+    let stale = ids[1];
+    let fresh = gen_map.insert(10);
+    assert_eq!(
+        gen_map.get_many_mut([ids[0], stale]),
+        Err(GetManyError::Missing(stale))
+    );
+    assert_eq!(
+        gen_map.get2_mut(fresh, fresh),
+        Err(GetManyError::Duplicate(fresh))
+    );
+    let (a, b) = gen_map.get2_mut(ids[0], fresh).unwrap();
+    std::mem::swap(a, b);
+    assert_eq!(gen_map[fresh], 0);
+}
+
+fn check_policy<A: AllocPolicy>(expected: &[Id]) {
+    let mut map: IdMap<Id, Id, A> = IdMap::with_key();
+    for i in 0..8 {
+        assert_eq!(map.insert(i), i);
+        map.assert_invariant();
+    }
+    for &id in &[5, 1, 6, 3] {
+        map.remove(id);
+        map.assert_invariant();
+    }
+    map.insert_at(20, 20);
+    map.remove(20);
+    map.insert_at(6, 6);
+    map.assert_invariant();
+
+    let mut ids = Vec::new();
+    for _ in 0..expected.len() {
+        let id = map.next_id();
+        assert_eq!(map.insert(id), id);
+        map.assert_invariant();
+        ids.push(id);
+    }
+    assert_eq!(ids, expected);
+
+    map.clear();
+    map.assert_invariant();
+    map.insert(0);
+    map.assert_invariant();
+    map.compact();
+    map.assert_invariant();
+    assert_eq!(map.next_id(), 1);
+}
+
+#[test]
+fn alloc_policy() {
+    use policy::{FreeList, Monotonic, Quarantine};
+
+    check_policy::<LowestFree>(&[1, 3, 5, 8, 9]);
+    check_policy::<FreeList>(&[3, 1, 5, 8, 9]);
+    check_policy::<Quarantine<0>>(&[5, 1, 3, 8, 9]);
+    check_policy::<Quarantine<2>>(&[5, 8, 9, 10, 11]);
+    check_policy::<Monotonic>(&[21, 22, 23]);
+
+    let mut map: IdMap<u32, Id, FreeList> = IdMap::with_policy(FreeList::default());
+    map.extend(0..4);
+    map.retain(|_, &val| val % 2 == 0);
+    map.assert_invariant();
+    assert_eq!(map.insert(10), 3);
+    map.remove_set(&vec![0, 2].into_iter().collect());
+    map.assert_invariant();
+    assert_eq!(map.insert(11), 2);
+    assert_eq!(map.insert(12), 0);
+    assert_eq!(map.insert(13), 1);
+    assert_eq!(map.insert(14), 4);
+}
+
+#[test]
+fn allocator() {
+    use policy::Monotonic;
+
+    let mut alloc = IdAllocator::new();
+    for i in 0..10 {
+        assert_eq!(alloc.allocate(), i);
+    }
+    alloc.free(2);
+    alloc.free(5);
+    alloc.free(6);
+    alloc.free(7);
+    assert!(!alloc.free(7));
+    alloc.assert_invariant();
+
+    assert_eq!(alloc.reserve_block(2), 5);
+    alloc.assert_invariant();
+    assert_eq!(alloc.next_id(), 2);
+    assert_eq!(alloc.reserve_block(3), 10);
+    assert_eq!(alloc.reserve_block(0), 2);
+    assert_eq!(alloc.allocate(), 2);
+    assert_eq!(alloc.allocate(), 7);
+    assert_eq!(alloc.len(), 13);
+    alloc.assert_invariant();
+
+    let mut map = IdMap::from_allocator(alloc.clone(), |id| id * 10);
+    map.assert_invariant();
+    assert_eq!(map.len(), 13);
+    assert_eq!(map[12], 120);
+    assert_eq!(map.insert(0), 13);
+
+    alloc.free(0);
+    alloc.free(12);
+    map[1] = 1;
+    map.reset_allocator(alloc.clone(), |_| 0);
+    map.assert_invariant();
+    assert_eq!(
+        map.ids().collect::<Vec<_>>(),
+        alloc.ids().collect::<Vec<_>>()
+    );
+    assert_eq!(map[1], 1);
+    assert_eq!(map.next_id(), 0);
+
+    let alloc = map.into_allocator();
+    assert_eq!(alloc.len(), 11);
+
+    let mut alloc: IdAllocator<Id, Monotonic> = IdAllocator::with_key();
+    alloc.reserve_block(4);
+    alloc.free(1);
+    alloc.free(2);
+    assert_eq!(alloc.reserve_block(2), 4);
+    assert_eq!(alloc.allocate(), 6);
+    alloc.assert_invariant();
+}
+
+#[test]
+fn dense() {
+    let mut map = DenseIdMap::new();
+    for i in 0..10 {
+        assert_eq!(map.insert(i * 10), i);
+        map.assert_invariant();
+    }
+    assert_eq!(map.remove(3), Some(30));
+    assert_eq!(map.remove(3), None);
+    map.assert_invariant();
+    assert_eq!(map.as_slice()[3], 90);
+    assert_eq!(map.insert_at(3, 33), None);
+    assert_eq!(map.insert_at(3, 34), Some(33));
+    assert_eq!(map.insert_at(20, 200), None);
+    map.assert_invariant();
+    assert_eq!(map.next_id(), 10);
+
+    map.retain(|id, _| id % 2 == 0);
+    map.assert_invariant();
+    let mut ids: Vec<_> = map.ids().collect();
+    ids.sort();
+    assert_eq!(ids, vec![0, 2, 4, 6, 8, 20]);
+    assert_eq!(map.values().len(), 6);
+    for val in map.as_mut_slice() {
+        *val += 1;
+    }
+    assert_eq!(map[20], 201);
+    assert_eq!(*map.get_or_insert_with(1, || 11), 11);
+    assert_eq!(*map.get_or_insert(1, 12), 11);
+    map.assert_invariant();
+
+    let sparse: IdMap<_> = map.clone().into();
+    sparse.assert_invariant();
+    assert_eq!(sparse.len(), map.len());
+    assert!(map.iter().all(|(id, val)| sparse[id] == *val));
+    let dense: DenseIdMap<_> = sparse.into();
+    dense.assert_invariant();
+    assert_eq!(dense, map);
+    assert_eq!(dense.next_id(), map.next_id());
+
+    let mut removed = map.clone();
+    removed.remove_set(&(0..5).collect());
+    removed.assert_invariant();
+    assert_eq!(removed.len(), 3);
+    assert!(!removed.contains(4) && removed.contains(6));
+
+    let mut pairs: Vec<_> = map.clone().into_iter().collect();
+    pairs.sort();
+    assert_eq!(pairs[0], (0, 1));
+    assert_eq!(pairs.len(), 7);
+
+    let ranged: Vec<_> = map.range(2..=8).map(|(id, &val)| (id, val)).collect();
+    assert_eq!(ranged, vec![(2, 21), (4, 41), (6, 61), (8, 81)]);
+    assert_eq!(map.ids_in(..4).collect::<Vec<_>>(), vec![0, 1, 2]);
+    assert_eq!(map.iter_from(8).len(), 2);
+    for (_, val) in map.range_mut(4..) {
+        *val *= 10;
+    }
+    assert_eq!((map[2], map[4], map[20]), (21, 410, 2010));
+    let (a, b) = map.get2_mut(20, 0).unwrap();
+    std::mem::swap(a, b);
+    assert_eq!((map[0], map[20]), (2010, 1));
+    assert_eq!(map.get_many_mut([0, 3]), Err(GetManyError::Missing(3)));
+    assert_eq!(map.get_many_mut([4, 4]), Err(GetManyError::Duplicate(4)));
+
+    *map.entry(3).or_default() += 3;
+    map.entry(3).and_modify(|val| *val += 1);
+    assert_eq!(map[3], 4);
+    match map.entry(1) {
+        dense::Entry::Occupied(entry) => assert_eq!(entry.remove_entry(), (1, 11)),
+        dense::Entry::Vacant(_) => panic!("id 1 should be occupied"),
+    }
+    let id = map.next_id();
+    assert_eq!(*map.vacant_entry().insert_with(|id| id), id);
+    map.assert_invariant();
+
+    let extracted: Vec<_> = map.extract_if(|id, _| id > 3 && id < 10).collect();
+    assert_eq!(extracted, vec![(4, 410), (6, 610), (8, 810)]);
+    assert_eq!(map.drain_range(10..).collect::<Vec<_>>(), vec![(20, 1)]);
+    map.assert_invariant();
+    assert_eq!(map.ids_in(..).collect::<Vec<_>>(), vec![0, 1, 2, 3]);
+
+    map.insert_at(9, 99);
+    map.remove(2);
+    let remap = map.compact();
+    map.assert_invariant();
+    assert_eq!(remap.get(9), Some(3));
+    assert_eq!(
+        map.range(..).map(|(_, &val)| val).collect::<Vec<_>>(),
+        vec![2010, 1, 4, 99]
+    );
+    assert_eq!(map.next_id(), 4);
+    assert_eq!(map.drain().len(), 4);
+    assert!(map.is_empty());
+
+    let mut small = DenseIdMap::new();
+    small.set_max_id(10);
+    assert_eq!(small.try_insert_at(10, 'a'), Ok(None));
+    assert_eq!(
+        small.try_insert_at(11, 'b'),
+        Err(TryReserveError::IdTooLarge(11))
+    );
+    assert_eq!(small.try_insert('c'), Ok(0));
+    assert!(small.try_get_or_insert_with(11, || 'd').is_err());
+    assert!(small.try_reserve(100).is_ok());
+    small.set_max_id(0);
+    assert_eq!(small.try_next_id(), None);
+    small.set_max_id(10);
+    let sparse: IdMap<_> = small.into();
+    assert_eq!(sparse.max_id(), 10);
+    let dense: DenseIdMap<_> = sparse.into();
+    assert_eq!(dense.max_id(), 10);
+
+    let old: DenseIdMap<_> = (0..5).collect();
+    let mut new = old.clone();
+    new.remove(1);
+    new[2] = 20;
+    new.insert_at(7, 70);
+    let diff = old.diff(&new);
+    assert_eq!(diff.inserted, [(7, 70)]);
+    assert_eq!(diff.removed, [1]);
+    assert_eq!(diff.changed, [(2, 20)]);
+    let mut replica = old.clone();
+    replica.apply(diff.clone());
+    replica.assert_invariant();
+    assert_eq!(replica, new);
+    let mut small = old.clone();
+    small.set_max_id(5);
+    assert_eq!(small.try_apply(diff), Err(TryReserveError::IdTooLarge(7)));
+    assert_eq!(small, old);
+
+    let mut alloc = IdAllocator::new();
+    alloc.reserve_block(6);
+    alloc.free(2);
+    let mut map = DenseIdMap::from_allocator(alloc.clone(), |id| id * 10);
+    map.assert_invariant();
+    assert_eq!((map.len(), map[5]), (5, 50));
+    alloc.free(0);
+    alloc.allocate_at(8);
+    map.reset_allocator(alloc.clone(), |_| 0);
+    map.assert_invariant();
+    assert_eq!(map.as_set(), alloc.as_set());
+    assert_eq!((map[1], map[8]), (10, 0));
+    assert_eq!(map.next_id(), 0);
+    assert_eq!(map.into_allocator().len(), 5);
+
+    let mut free: DenseIdMap<_, Id, policy::FreeList> = DenseIdMap::with_policy(Default::default());
+    free.extend(0..3);
+    free.remove(0);
+    free.remove(2);
+    assert_eq!(free.insert(9), 2);
+}
+
+#[test]
+fn arena() {
+    let arena = IdArena::new();
+    let mut refs = Vec::new();
+    for i in 0..1000 {
+        let (id, val) = arena.insert(Box::new(i));
+        assert_eq!(id, i);
+        refs.push(val);
+    }
+    // Earlier references are still valid after the chunks have grown.
+    for (i, val) in refs.iter().enumerate() {
+        assert_eq!(***val, i);
+        assert!(std::ptr::eq(*val, &arena[i]));
+    }
+    assert_eq!(arena.len(), 1000);
+    assert_eq!(arena.next_id(), 1000);
+    assert!(arena.get(1000).is_none());
+    assert!(!arena.contains(1000));
+    assert_eq!(arena.iter().next_back().map(|(id, _)| id), Some(999));
+    assert_eq!(arena.iter().len(), 1000);
+    drop(refs);
+
+    let mut arena = arena;
+    *arena[31] = 0;
+    **arena.get_mut(32).unwrap() = 0;
+    assert!(arena.get(Id::MAX).is_none());
+    let map = arena.into_id_map();
+    map.assert_invariant();
+    assert_eq!(map.len(), 1000);
+    assert_eq!((*map[30], *map[31], *map[32], *map[999]), (30, 0, 0, 999));
+    assert_eq!(map.next_id(), 1000);
+}
+
+#[test]
+fn array_id_map() {
+    static EMPTY: ArrayIdMap<u32, 4> = ArrayIdMap::new();
+    assert!(EMPTY.is_empty());
+
+    let mut map = ArrayIdMap::<Box<u32>, 4>::new();
+    for i in 0..4 {
+        assert_eq!(map.insert(Box::new(i)), Ok(i as Id));
+        map.assert_invariant();
+    }
+    assert!(map.is_full());
+    assert_eq!(map.next_id(), None);
+    assert_eq!(*map.insert(Box::new(4)).unwrap_err().into_inner(), 4);
+    assert_eq!(*map.insert_at(4, Box::new(4)).unwrap_err().into_inner(), 4);
+
+    assert_eq!(*map.remove(2).unwrap(), 2);
+    assert_eq!(*map.remove(1).unwrap(), 1);
+    assert!(map.remove(1).is_none());
+    assert!(map.remove(4).is_none());
+    map.assert_invariant();
+    assert_eq!(map.next_id(), Some(1));
+    assert_eq!(map.ids().collect::<Vec<_>>(), [0, 3]);
+    assert_eq!(
+        map.iter().rev().map(|(_, v)| **v).collect::<Vec<_>>(),
+        [3, 0]
+    );
+
+    assert_eq!(map.insert_at(2, Box::new(20)), Ok(None));
+    assert_eq!(map.insert_at(2, Box::new(21)).map(|v| *v.unwrap()), Ok(20));
+    map.assert_invariant();
+    assert_eq!(map.len(), 3);
+    assert_eq!(map.next_id(), Some(1));
+
+    for (_, val) in &mut map {
+        **val += 1;
+    }
+    map.retain(|id, _| id != 0);
+    map.assert_invariant();
+    assert_eq!(map.next_id(), Some(0));
+
+    let id_map: IdMap<_> = map.clone().into();
+    id_map.assert_invariant();
+    assert_eq!(id_map.len(), 2);
+    assert_eq!((*id_map[2], *id_map[3]), (22, 4));
+
+    let mut iter = map.into_iter();
+    assert_eq!(iter.len(), 2);
+    assert_eq!(iter.next_back().map(|(id, v)| (id, *v)), Some((3, 4)));
+    assert_eq!(iter.next().map(|(id, v)| (id, *v)), Some((2, 22)));
+    assert!(iter.next().is_none());
+
+    // Ids beyond the first word of the bitset.
+    let mut big: ArrayIdMap<u32, 130, Id, { array::words(130) }> = ArrayIdMap::new();
+    for i in 0..130 {
+        assert_eq!(big.insert(i), Ok(i as Id));
+    }
+    big.assert_invariant();
+    assert!(big.insert(130).is_err());
+    big.retain(|id, _| id % 63 != 0);
+    big.assert_invariant();
+    assert_eq!(big.len(), 127);
+    assert_eq!(big.next_id(), Some(0));
+    big.remove(64);
+    big.insert_at(0, 0).unwrap();
+    assert_eq!(big.next_id(), Some(63));
+    assert_eq!(big.iter().nth(63).map(|(id, _)| id), Some(65));
+    assert_eq!(big.iter_mut().rev().nth(2).map(|(id, _)| id), Some(127));
+    let mut iter = big.iter_mut();
+    assert_eq!(iter.next_back().map(|(id, _)| id), Some(129));
+    assert_eq!(iter.next().map(|(id, _)| id), Some(0));
+    assert_eq!(iter.len(), 125);
+    big.clear();
+    big.assert_invariant();
+    assert_eq!(big.iter().next(), None);
+    assert_eq!(big.next_id(), Some(0));
+}
+
+#[test]
+fn try_insert() {
+    let mut map = IdMap::<Box<u32>>::new();
+    assert!(map.try_reserve(10).is_ok());
+    assert!(map.capacity() >= 10);
+    assert!(map.try_reserve(Id::MAX).is_err());
+
+    match map.try_insert_at(Id::MAX / 2, Box::new(0)) {
+        Err(TryReserveError::Alloc(_)) => {}
+        res => panic!("unexpected result {:?}", res),
+    }
+    assert!(map.is_empty());
+    map.assert_invariant();
+
+    map.set_max_id(2);
+    assert_eq!(map.max_id(), 2);
+    assert_eq!(map.try_insert(Box::new(0)), Ok(0));
+    assert_eq!(map.try_insert_at(2, Box::new(2)), Ok(None));
+    assert_eq!(**map.try_get_or_insert_with(1, || Box::new(1)).unwrap(), 1);
+    assert_eq!(**map.try_get_or_insert_with(1, || Box::new(10)).unwrap(), 1);
+    assert_eq!(
+        map.try_insert(Box::new(3)),
+        Err(TryReserveError::IdTooLarge(3))
+    );
+    assert_eq!(
+        map.try_insert_at(3, Box::new(3)),
+        Err(TryReserveError::IdTooLarge(3))
+    );
+    assert_eq!(
+        map.try_get_or_insert_with(3, || unreachable!()),
+        Err(TryReserveError::IdTooLarge(3))
+    );
+    assert_eq!(map.try_insert_at(2, Box::new(20)), Ok(Some(Box::new(2))));
+    map.assert_invariant();
+    assert_eq!(map.len(), 3);
+    assert_eq!(map.next_id(), 3);
+}
+
+#[test]
+#[should_panic(expected = "maximum id")]
+fn insert_past_max_id() {
+    let mut map = IdMap::new();
+    map.set_max_id(0);
+    map.insert(0);
+    map.insert(1);
+}
+
+#[test]
+fn key_width() {
+    new_key_type! {
+        struct SmallId(u16);
+        struct WideId;
+    }
+
+    assert_eq!(std::mem::size_of::<SmallId>(), 2);
+    assert_eq!(<SmallId as Key>::MAX_ID, u16::MAX as Id);
+    assert_eq!(<WideId as Key>::MAX_ID, Id::MAX);
+    assert_eq!(<u32 as Key>::MAX_ID, u32::MAX as Id);
+    assert_eq!(<u64 as Key>::from_id(7), 7);
+    assert_eq!(<u16 as Key>::try_from_id(70000), None);
+    assert_eq!(SmallId::try_from_id(7), Some(SmallId(7)));
+    assert_eq!(7u32.to_id(), 7);
+
+    let mut map = IdMap::<(), SmallId>::with_key();
+    assert_eq!(map.max_id(), SmallId(u16::MAX));
+    for _ in 0..=u16::MAX {
+        map.insert(());
+    }
+    assert_eq!(
+        map.try_insert(()),
+        Err(TryReserveError::IdTooLarge(u16::MAX as Id + 1))
+    );
+    assert_eq!(map.len(), u16::MAX as usize + 1);
+    assert_eq!(map.try_next_id(), None);
+    let res = std::panic::catch_unwind(|| map.next_id());
+    assert!(res.is_err());
+    map.remove(SmallId(3));
+    assert_eq!(map.try_next_id(), Some(SmallId(3)));
+    assert_eq!(map.insert(()), SmallId(3));
+
+    let mut map = IdMap::new();
+    map.set_max_id(0);
+    map.insert(());
+    assert_eq!(map.try_next_id(), None);
+    assert_eq!(map.next_id(), 1);
+
+    let mut alloc = IdAllocator::<u16, policy::Monotonic>::with_key();
+    alloc.allocate_at(u16::MAX - 1);
+    let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        alloc.reserve_block(2);
+    }));
+    assert!(res.is_err());
+    alloc.assert_invariant();
+    assert_eq!(alloc.len(), 1);
+
+    // A block whose end overflows the id type is rejected rather than wrapping around.
+    let mut alloc = IdAllocator::new();
+    alloc.allocate();
+    let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        alloc.reserve_block(usize::MAX);
+    }));
+    assert!(res.is_err());
+    alloc.assert_invariant();
+    assert_eq!(alloc.len(), 1);
+    assert_eq!(alloc.next_id(), 1);
+}
+
+#[test]
+fn persistent() {
+    let mut map = PersistentIdMap::new();
+    for i in 0..100 {
+        assert_eq!(map.insert(i), i);
+    }
+    map.assert_invariant();
+
+    let snapshot = map.clone();
+    assert!(map.ptr_eq(&snapshot));
+    assert_eq!(map.remove(40), Some(40));
+    assert!(map.remove(40).is_none());
+    *map.get_mut(41).unwrap() = 0;
+    for id in 64..96 {
+        map.remove(id);
+    }
+    map.assert_invariant();
+    assert!(!map.ptr_eq(&snapshot));
+    assert_eq!(map.len(), 67);
+    assert_eq!(map.next_id(), 40);
+    assert_eq!(map.ids().rev().take(2).collect::<Vec<_>>(), [99, 98]);
+    assert_eq!(map.iter().filter(|&(id, _)| id >= 60).count(), 8);
+
+    // The snapshot is unchanged.
+    snapshot.assert_invariant();
+    assert_eq!(snapshot.len(), 100);
+    assert!(snapshot.iter().all(|(id, &val)| id == val));
+
+    for id in 96..100 {
+        map.remove(id);
+    }
+    map.assert_invariant();
+    assert_eq!(map.iter().next_back(), Some((63, &63)));
+    assert_eq!(map.insert_at(200, 200), None);
+    assert_eq!(map.insert(40), 40);
+    map.assert_invariant();
+
+    let id_map = IdMap::from(map.clone());
+    id_map.assert_invariant();
+    assert_eq!(id_map.len(), map.len());
+    assert_eq!((id_map[40], id_map[41], id_map[200]), (40, 0, 200));
+    let round_trip = PersistentIdMap::from(id_map);
+    round_trip.assert_invariant();
+    assert_eq!(round_trip, map);
+    assert_eq!(round_trip.next_id(), 64);
+
+    // A write after a clone copies only the nodes on the path to the id.
+    let mut big: PersistentIdMap<_> = (0..10_000).collect();
+    big.assert_invariant();
+    let snapshot = big.clone();
+    big.insert_at(5000, 0);
+    assert_eq!(big.unshared_nodes(&snapshot), 3);
+    big.remove(7000);
+    assert_eq!(big.unshared_nodes(&snapshot), 5);
+    big.assert_invariant();
+    assert_eq!(snapshot[7000], 7000);
+
+    let mut sparse = PersistentIdMap::new();
+    sparse.insert_at(1 << 20, 1);
+    sparse.insert(0);
+    sparse.assert_invariant();
+    assert_eq!(sparse.ids().collect::<Vec<_>>(), [0, 1 << 20]);
+    assert_eq!(sparse.ids().rev().collect::<Vec<_>>(), [1 << 20, 0]);
+    assert_eq!(sparse.remove(1 << 20), Some(1));
+    sparse.assert_invariant();
+    assert_eq!(sparse.unshared_nodes(&PersistentIdMap::new()), 1);
+}
+
+#[cfg(all(feature = "concurrent", not(loom)))]
+#[test]
+fn concurrent() {
+    use std::sync::Barrier;
+    use std::thread;
+
+    let map = ConcurrentIdMap::with_shards(4);
+    let barrier = Barrier::new(4);
+    let ids: Vec<Vec<Id>> = thread::scope(|s| {
+        let handles: Vec<_> = (0..4)
+            .map(|t| {
+                let (map, barrier) = (&map, &barrier);
+                s.spawn(move || {
+                    barrier.wait();
+                    (0..100).map(|i| map.insert(t * 100 + i)).collect()
+                })
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+
+    assert_eq!(map.len(), 400);
+    for (t, ids) in ids.iter().enumerate() {
+        for (i, &id) in ids.iter().enumerate() {
+            assert_eq!(*map.get(id).unwrap(), t * 100 + i);
+        }
+    }
+    let mut all: Vec<Id> = ids.iter().flatten().cloned().collect();
+    all.sort();
+    all.dedup();
+    assert_eq!(all.len(), 400);
+
+    // Freed ids are reused.
+    let freed = ids[0][0];
+    let held = map.get(freed).unwrap();
+    assert_eq!(map.remove(freed).as_deref(), Some(&0));
+    assert!(map.get(freed).is_none());
+    assert_eq!(*held, 0);
+    // Guards from `get` hold no lock, so inserting into their shards does not wait for them.
+    let refs: Vec<_> = ids[0][1..5]
+        .iter()
+        .map(|&id| map.get(id).unwrap())
+        .collect();
+    let reused = (0..4).map(|i| map.insert(i)).collect::<Vec<_>>();
+    assert!(reused.contains(&freed));
+    {
+        let mut val = map.get_mut(freed).unwrap();
+        *val += 10;
+        // Readers see the old value until the guard is dropped.
+        assert!(*map.get(freed).unwrap() < 4);
+    }
+    assert!(*map.get(freed).unwrap() >= 10);
+    drop(refs);
+
+    let id_map = map.into_id_map();
+    id_map.assert_invariant();
+    assert_eq!(id_map.len(), 403);
+    assert!(*id_map[freed] >= 10);
+
+    // Without contention, inserts reuse the lowest free id, like an `IdMap`.
+    let map = ConcurrentIdMap::new();
+    assert!((0..40).map(|i| map.insert(i)).eq(0..40));
+    for id in [30, 3, 17] {
+        map.remove(id);
+    }
+    let reused: Vec<Id> = (0..4).map(|i| map.insert(i)).collect();
+    assert_eq!(reused, [3, 17, 30, 40]);
+}
+
+#[cfg(all(feature = "concurrent", loom))]
+#[test]
+fn concurrent_loom() {
+    use loom::model::Builder;
+    use loom::sync::Arc;
+    use loom::thread;
+
+    // crossbeam-epoch is modelled as well, which multiplies the interleavings to explore.
+    let mut model = Builder::new();
+    model.preemption_bound = Some(2);
+
+    model.check(|| {
+        let map = Arc::new(ConcurrentIdMap::with_shards(2));
+        let first = map.insert(0);
+
+        let handles: Vec<_> = (1..3)
+            .map(|i| {
+                let map = map.clone();
+                thread::spawn(move || {
+                    let id = map.insert(i);
+                    assert_eq!(*map.get(id).unwrap(), i);
+                    id
+                })
+            })
+            .collect();
+        assert_eq!(map.remove(first).as_deref(), Some(&0));
+        let ids: Vec<Id> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+
+        assert_ne!(ids[0], ids[1]);
+        assert_eq!(map.len(), 2);
+        // Every id is either the freed one or among the first four.
+        for &id in &ids {
+            assert!(id < 4);
+        }
+        // Without contention, the lowest free id is reused.
+        let lowest = (0..).find(|&id| !map.contains(id)).unwrap();
+        assert_eq!(map.insert(3), lowest);
+    });
+
+    // A reader racing a writer sees either snapshot, and the one it loaded is not freed under it.
+    model.check(|| {
+        let map = Arc::new(ConcurrentIdMap::with_shards(1));
+        let id = map.insert(1);
+
+        let writer = {
+            let map = map.clone();
+            thread::spawn(move || {
+                map.remove(id);
+                let id = map.insert(2);
+                crossbeam_epoch::pin().flush();
+                id
+            })
+        };
+        let seen = map.get(id).map(|val| *val);
+        assert!(matches!(seen, None | Some(1) | Some(2)));
+        assert_eq!(writer.join().unwrap(), id);
+        assert_eq!(*map.get(id).unwrap(), 2);
+    });
+}
+
+#[test]
+fn tracked() {
+    let mut map = TrackedIdMap::from((0..6).collect::<IdMap<_>>());
+    assert!(map.changes().is_empty());
+    assert_eq!(map.changed_since(0).count(), 6);
+    let start = map.drain_changes();
+    assert!(start.is_empty());
+    let tick = map.tick();
+    assert_eq!(tick, 1);
+
+    *map.get_mut(0).unwrap() += 10;
+    map[1] += 10;
+    map.remove(2);
+    let new = map.insert(20);
+    assert_eq!(new, 2);
+    map.remove(3);
+    map.insert_at(10, 10);
+    map.insert_at(11, 11);
+    map.remove(11);
+    for (_, val) in map.iter_mut().rev().take(1) {
+        *val += 100;
+    }
+    map.retain(|id, _| id != 4);
+
+    let changes = map.drain_changes();
+    assert_eq!(changes.added.iter().collect::<Vec<_>>(), [10]);
+    assert_eq!(changes.modified.iter().collect::<Vec<_>>(), [0, 1, 2]);
+    assert_eq!(changes.removed.iter().collect::<Vec<_>>(), [3, 4]);
+    assert_eq!(
+        map.changed_since(tick).collect::<Vec<_>>(),
+        [(0, &10), (1, &11), (2, &20), (10, &110)]
+    );
+
+    map[5] = 0;
+    map.clear();
+    let changes = map.drain_changes();
+    assert!(changes.added.is_empty() && changes.modified.is_empty());
+    assert_eq!(changes.removed.len(), 5);
+    assert_eq!(map.changed_since(0).count(), 0);
+    assert_eq!(map.tick(), 3);
+}
+
+#[test]
+fn diff() {
+    let old = IdMap::from_iter(vec![0, 1, 2, 3, 4]);
+    let mut new = old.clone();
+    new.remove(1);
+    new.remove(4);
+    new[2] = 20;
+    new[3] = 3;
+    new.insert_at(7, 7);
+
+    let diff = old.diff(&new);
+    assert_eq!(diff.inserted, [(7, 7)]);
+    assert_eq!(diff.removed, [1, 4]);
+    assert_eq!(diff.changed, [(2, 20)]);
+    assert!(old.diff(&old).is_empty());
+
+    let mut replica = old.clone();
+    replica.apply(diff);
+    replica.assert_invariant();
+    assert_eq!(replica, new);
+    assert_eq!(replica.as_set(), new.as_set());
+
+    // Changes within the tolerance of the closure are ignored.
+    let mut close = new.clone();
+    close[0] = 1;
+    close[2] = 30;
+    let diff = new.diff_by(&close, |a: &i32, b| (a - b).abs() <= 1);
+    assert_eq!(diff.changed, [(2, 30)]);
+
+    let reverse = new.diff(&old);
+    assert_eq!(reverse.inserted, [(1, 1), (4, 4)]);
+    assert_eq!(reverse.removed, [7]);
+    replica.apply(reverse);
+    assert_eq!(replica, old);
+}
+
+#[test]
+fn journal() {
+    let mut map = JournaledIdMap::new();
+    let ids: Vec<_> = (0..5).map(|i| map.insert(i * 10)).collect();
+    assert_eq!(ids, [0, 1, 2, 3, 4]);
+    let snapshot = map.as_map().clone();
+    map.clear_history();
+    assert!(!map.can_undo());
+
+    map.remove(1);
+    map[2] += 1;
+    *map.get_mut(2).unwrap() += 1;
+    map.transaction(|map| {
+        map.remove(3);
+        map.insert_at(8, 80);
+        map.transaction(|map| map.retain(|_, &v| v != 40));
+    });
+    let mut set = IdSet::new();
+    set.insert(0);
+    set.insert(6);
+    map.remove_set(&set);
+    assert_eq!(map.insert(-1), 0);
+    map.as_map().assert_invariant();
+    let edited = map.as_map().clone();
+    assert_eq!(
+        edited.iter().collect::<Vec<_>>(),
+        [(0, &-1), (2, &22), (8, &80)]
+    );
+    assert_eq!(edited.next_id(), 1);
+
+    // insert, remove_set, transaction, get_mut, IndexMut, remove.
+    for _ in 0..6 {
+        assert!(map.undo());
+        map.as_map().assert_invariant();
+    }
+    assert!(!map.undo());
+    assert_eq!(*map.as_map(), snapshot);
+    assert_eq!(map.as_map().as_set(), snapshot.as_set());
+    assert_eq!(map.next_id(), 5);
+
+    // Undoing part of the history restores the intermediate state exactly.
+    assert!(map.redo() && map.redo() && map.redo() && map.redo());
+    assert_eq!(map.next_id(), 1);
+    assert_eq!(map.get(1), None);
+    assert!(!map.contains(3) && !map.contains(4) && map[8] == 80);
+    assert!(map.redo() && map.redo());
+    assert!(!map.redo());
+    assert_eq!(*map.as_map(), edited);
+    assert_eq!(map.as_map().as_set(), edited.as_set());
+
+    // A new change discards the transactions available to redo.
+    map.undo();
+    map.clear();
+    assert!(map.is_empty() && !map.can_redo());
+    map.undo();
+    assert_eq!(map.len(), 2);
+    assert_eq!(map.next_id(), 0);
+
+    // A panicking transaction keeps and commits the changes made so far.
+    let mut map = JournaledIdMap::from(IdMap::from_iter(0..5));
+    let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        map.transaction(|map| {
+            map.remove(0);
+            map.transaction(|map| map.insert_at(8, 8));
+            panic!("transaction failed");
+        })
+    }));
+    assert!(res.is_err());
+    assert!(!map.contains(0) && map.contains(8));
+    let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        map.retain(|id, _| {
+            assert!(id != 8, "predicate failed");
+            id > 2
+        })
+    }));
+    assert!(res.is_err());
+    assert_eq!(map.ids().collect::<Vec<_>>(), [3, 4, 8]);
+    map.as_map().assert_invariant();
+
+    assert!(map.undo());
+    assert_eq!(map.ids().collect::<Vec<_>>(), [1, 2, 3, 4, 8]);
+    assert!(map.undo());
+    assert!(map.ids().eq(0..5));
+    assert!(!map.undo());
+    map.insert(5);
+    assert!(map.undo());
+}
+
+#[test]
+fn internal_id_set() {
+    let ids = [0, 3, 31, 32, 33, 64, 100, 1000];
+    let others = [3, 4, 32, 100, 2000];
+    let a: IdSet = ids.iter().cloned().collect();
+    let b: id_set::IdSet = ids.iter().cloned().collect();
+    let a2: IdSet = others.iter().cloned().collect();
+    let b2: id_set::IdSet = others.iter().cloned().collect();
+
+    assert_eq!(a.len(), b.len());
+    assert!(a.iter().eq(b.iter()));
+    assert!(a
+        .iter()
+        .rev()
+        .eq(b.iter().collect::<Vec<_>>().into_iter().rev()));
+    assert!((0..1100).all(|id| a.contains(id) == b.contains(id)));
+    assert!(a.intersection(&a2).into_iter().eq(b.intersection(&b2)));
+    assert!(a.difference(&a2).into_iter().eq(b.difference(&b2)));
+    assert!(a2.difference(&a).into_set().iter().eq(&b2 - &b));
+    assert_eq!(a.intersection(&a2).into_iter().len(), 3);
+
+    let mut a3 = a.clone();
+    a3.inplace_difference(&a2);
+    assert_eq!(a3.len(), 5);
+    assert!(a3.iter().eq(&b - &b2));
+    a3.retain(|id| id % 2 == 0);
+    assert!(a3.iter().eq(vec![0, 64, 1000]));
+    assert!(a3.remove(64) && !a3.remove(64) && !a3.remove(5000));
+    assert!(a3.insert(7) && !a3.insert(7));
+    assert_eq!(a3.clone().into_blocks(), a3.as_blocks());
+    assert!(a3.try_reserve_id(Id::MAX).is_err());
+    assert!(a3.try_reserve_id(5000).is_ok() && a3.capacity() > 5000);
+    assert!(a3.try_reserve(Id::MAX).is_err());
+
+    let mut a4 = IdSet::new_filled(40);
+    assert!(a4.iter().eq(id_set::IdSet::new_filled(40).iter()));
+    assert!(IdSet::new_filled(64).iter().eq(0..64));
+    a4.remove(39);
+    assert_eq!(a4, IdSet::new_filled(39));
+    a4.insert(1000);
+    a4.remove(1000);
+    assert_eq!(a4, IdSet::new_filled(39));
+    a4.shrink_to_fit();
+    assert_eq!(a4.as_blocks().len(), 2);
+    a4.clear();
+    assert!(a4.is_empty() && a4.iter().next().is_none());
+}
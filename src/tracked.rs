@@ -0,0 +1,432 @@
+//! A map which records the ids it changes, for sending incremental updates.
+
+use alloc::vec::Vec;
+use core::fmt;
+use core::iter::FusedIterator;
+use core::mem;
+use core::ops::{Index, IndexMut};
+
+use {Id, IdMap, IdSet, Iter, Key};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+/// The ids changed in a `TrackedIdMap` since changes were last drained.
+///
+/// Each id is in at most one of the sets, describing its net change: an id which was added and
+/// then removed again is in none of them, and an id which was removed and then added again is
+/// modified.
+pub struct ChangeSet {
+    /// Ids which were not in the map and now are.
+    pub added: IdSet,
+    /// Ids which were in the map and still are, but may have a different value.
+    pub modified: IdSet,
+    /// Ids which were in the map and now are not.
+    pub removed: IdSet,
+}
+
+impl ChangeSet {
+    #[inline]
+    /// Returns true if no ids have changed.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.modified.is_empty() && self.removed.is_empty()
+    }
+
+    fn record_added(&mut self, id: Id) {
+        if self.removed.remove(id) {
+            self.modified.insert(id);
+        } else {
+            self.added.insert(id);
+        }
+    }
+
+    fn record_modified(&mut self, id: Id) {
+        if !self.added.contains(id) {
+            self.modified.insert(id);
+        }
+    }
+
+    fn record_removed(&mut self, id: Id) {
+        if !self.added.remove(id) {
+            self.modified.remove(id);
+            self.removed.insert(id);
+        }
+    }
+}
+
+/// A wrapper around an `IdMap` which records which ids are added, modified and removed.
+///
+/// Any mutable access to a value counts as a modification, whether or not the value changes.
+/// Changes accumulate until they are taken by `drain_changes`, which also advances the tick.
+/// Every added or modified id is stamped with the tick it changed in, so the values changed since
+/// an earlier tick can be found with `changed_since`.
+///
+/// # Examples
+///
+/// ```
+/// # use id_map::TrackedIdMap;
+/// #
+/// let mut map = TrackedIdMap::new();
+/// let a = map.insert("a");
+/// let b = map.insert("b");
+/// map.drain_changes();
+/// let tick = map.tick();
+///
+/// map[a] = "A";
+/// let c = map.insert("c");
+/// map.remove(b);
+///
+/// let changes = map.drain_changes();
+/// assert!(changes.added.contains(c));
+/// assert!(changes.modified.contains(a));
+/// assert!(changes.removed.contains(b));
+///
+/// let changed: Vec<_> = map.changed_since(tick).collect();
+/// assert_eq!(changed, [(a, &"A"), (c, &"c")]);
+/// ```
+#[derive(Clone)]
+pub struct TrackedIdMap<T, K = Id> {
+    map: IdMap<T, K>,
+    changes: ChangeSet,
+    // The tick in which each id was last added or modified.
+    changed_at: Vec<u64>,
+    tick: u64,
+}
+
+impl<T> TrackedIdMap<T> {
+    #[inline]
+    /// Creates an empty `TrackedIdMap<T>`.
+    pub fn new() -> Self {
+        TrackedIdMap::with_key()
+    }
+}
+
+impl<T, K: Key> TrackedIdMap<T, K> {
+    #[inline]
+    /// Creates an empty `TrackedIdMap<T, K>` with a custom key type.
+    pub fn with_key() -> Self {
+        TrackedIdMap::from(IdMap::with_key())
+    }
+
+    #[inline]
+    /// Returns a reference to the underlying map.
+    pub fn as_map(&self) -> &IdMap<T, K> {
+        &self.map
+    }
+
+    #[inline]
+    /// Returns the underlying map, discarding the recorded changes.
+    pub fn into_map(self) -> IdMap<T, K> {
+        self.map
+    }
+
+    #[inline]
+    /// Returns the current tick, which changes are stamped with.
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    #[inline]
+    /// Returns the changes recorded since changes were last drained.
+    pub fn changes(&self) -> &ChangeSet {
+        &self.changes
+    }
+
+    #[inline]
+    /// Takes the changes recorded since changes were last drained, and advances the tick.
+    pub fn drain_changes(&mut self) -> ChangeSet {
+        self.tick += 1;
+        mem::take(&mut self.changes)
+    }
+
+    #[inline]
+    /// An iterator over the id-value pairs which were added or modified in `tick` or later, in
+    /// order of increasing id. Removed ids are only reported by `drain_changes`.
+    pub fn changed_since(&self, tick: u64) -> ChangedSince<'_, T, K> {
+        ChangedSince {
+            iter: self.map.iter(),
+            changed_at: &self.changed_at,
+            tick,
+        }
+    }
+
+    #[inline]
+    /// Returns the id that a subsequent call to insert() will produce.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the map is full, as `IdMap::next_id` does.
+    pub fn next_id(&self) -> K {
+        self.map.next_id()
+    }
+
+    #[inline]
+    /// Returns the id that a subsequent call to insert() will produce, or `None` if the map is
+    /// full.
+    pub fn try_next_id(&self) -> Option<K> {
+        self.map.try_next_id()
+    }
+
+    #[inline]
+    /// Returns the number of id-value pairs in the map.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    #[inline]
+    /// Returns true if the map contains no values.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    #[inline]
+    /// Returns true if the map contains a value for the specified id.
+    pub fn contains(&self, id: K) -> bool {
+        self.map.contains(id)
+    }
+
+    #[inline]
+    /// Returns a reference to the value at the specified id if it is in the map.
+    pub fn get(&self, id: K) -> Option<&T> {
+        self.map.get(id)
+    }
+
+    #[inline]
+    /// Returns a mutable reference to the value at the specified id if it is in the map, and
+    /// records the id as modified.
+    pub fn get_mut(&mut self, id: K) -> Option<&mut T> {
+        let val = self.map.get_mut(id)?;
+        self.changes.record_modified(id.to_id());
+        stamp(&mut self.changed_at, id.to_id(), self.tick);
+        Some(val)
+    }
+
+    #[inline]
+    /// An iterator over id-value pairs, in order of increasing id.
+    pub fn iter(&self) -> Iter<'_, T, K> {
+        self.map.iter()
+    }
+
+    #[inline]
+    /// A mutable iterator over id-value pairs, in order of increasing id. Each id is recorded as
+    /// modified when it is yielded.
+    pub fn iter_mut(&mut self) -> IterMut<'_, T, K> {
+        IterMut {
+            iter: self.map.iter_mut(),
+            changes: &mut self.changes,
+            changed_at: &mut self.changed_at,
+            tick: self.tick,
+        }
+    }
+
+    /// Inserts a value into an empty slot in the map and returns its id.
+    pub fn insert(&mut self, val: T) -> K {
+        let id = self.map.insert(val);
+        self.changes.record_added(id.to_id());
+        stamp(&mut self.changed_at, id.to_id(), self.tick);
+        id
+    }
+
+    /// Inserts a value at a specific id, returning the old value if it existed.
+    pub fn insert_at(&mut self, id: K, val: T) -> Option<T> {
+        let old = self.map.insert_at(id, val);
+        if old.is_some() {
+            self.changes.record_modified(id.to_id());
+        } else {
+            self.changes.record_added(id.to_id());
+        }
+        stamp(&mut self.changed_at, id.to_id(), self.tick);
+        old
+    }
+
+    /// Removes an id from the map, returning its value if it was previously in the map.
+    pub fn remove(&mut self, id: K) -> Option<T> {
+        let val = self.map.remove(id)?;
+        self.changes.record_removed(id.to_id());
+        Some(val)
+    }
+
+    /// Remove all values not satisfying the predicate.
+    pub fn retain<F: FnMut(K, &T) -> bool>(&mut self, mut pred: F) {
+        let changes = &mut self.changes;
+        self.map.retain(|id, val| {
+            let keep = pred(id, val);
+            if !keep {
+                changes.record_removed(id.to_id());
+            }
+            keep
+        });
+    }
+
+    /// Removes all values from the map.
+    pub fn clear(&mut self) {
+        for id in self.map.ids() {
+            self.changes.record_removed(id.to_id());
+        }
+        self.map.clear();
+    }
+}
+
+/// Records that the value at `id` changed in `tick`.
+#[inline]
+fn stamp(changed_at: &mut Vec<u64>, id: Id, tick: u64) {
+    if changed_at.len() <= id {
+        changed_at.resize(id + 1, 0);
+    }
+    changed_at[id] = tick;
+}
+
+impl<T: fmt::Debug, K: Key> fmt::Debug for TrackedIdMap<T, K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TrackedIdMap")
+            .field("map", &self.map)
+            .field("changes", &self.changes)
+            .field("tick", &self.tick)
+            .finish()
+    }
+}
+
+impl<T, K: Key> Default for TrackedIdMap<T, K> {
+    #[inline]
+    fn default() -> Self {
+        TrackedIdMap::with_key()
+    }
+}
+
+impl<T, K: Key> From<IdMap<T, K>> for TrackedIdMap<T, K> {
+    /// Wraps a map. Its existing values are not recorded as changes, and are stamped with tick
+    /// zero.
+    #[inline]
+    fn from(map: IdMap<T, K>) -> Self {
+        TrackedIdMap {
+            map,
+            changes: ChangeSet::default(),
+            changed_at: Vec::new(),
+            tick: 0,
+        }
+    }
+}
+
+impl<'a, T, K: Key> IntoIterator for &'a TrackedIdMap<T, K> {
+    type Item = (K, &'a T);
+    type IntoIter = Iter<'a, T, K>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, T, K: Key> IntoIterator for &'a mut TrackedIdMap<T, K> {
+    type Item = (K, &'a mut T);
+    type IntoIter = IterMut<'a, T, K>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+impl<T, K: Key> Index<K> for TrackedIdMap<T, K> {
+    type Output = T;
+
+    #[inline]
+    fn index(&self, id: K) -> &Self::Output {
+        &self.map[id]
+    }
+}
+
+impl<T, K: Key> IndexMut<K> for TrackedIdMap<T, K> {
+    #[inline]
+    fn index_mut(&mut self, id: K) -> &mut Self::Output {
+        match self.get_mut(id) {
+            Some(val) => val,
+            None => panic!("id {} out of bounds", id.to_id()),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+/// An iterator over the id-value pairs changed since a tick, in order of increasing id.
+pub struct ChangedSince<'a, T: 'a, K: 'a = Id> {
+    iter: Iter<'a, T, K>,
+    changed_at: &'a [u64],
+    tick: u64,
+}
+
+impl<'a, T: 'a, K: Key> Iterator for ChangedSince<'a, T, K> {
+    type Item = (K, &'a T);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        let (changed_at, tick) = (self.changed_at, self.tick);
+        self.iter
+            .find(|&(id, _)| changed_at.get(id.to_id()).map_or(0, |&t| t) >= tick)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.iter.size_hint().1)
+    }
+}
+
+impl<'a, T: 'a, K: Key> DoubleEndedIterator for ChangedSince<'a, T, K> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let (changed_at, tick) = (self.changed_at, self.tick);
+        self.iter
+            .rfind(|&(id, _)| changed_at.get(id.to_id()).map_or(0, |&t| t) >= tick)
+    }
+}
+
+impl<'a, T: 'a, K: Key> FusedIterator for ChangedSince<'a, T, K> {}
+
+#[derive(Debug)]
+/// A mutable iterator over id-value pairs, in order of increasing id, which records each id it
+/// yields as modified.
+pub struct IterMut<'a, T: 'a, K: 'a = Id> {
+    iter: ::IterMut<'a, T, K>,
+    changes: &'a mut ChangeSet,
+    changed_at: &'a mut Vec<u64>,
+    tick: u64,
+}
+
+impl<'a, T: 'a, K: Key> IterMut<'a, T, K> {
+    #[inline]
+    fn record(&mut self, id: K) {
+        self.changes.record_modified(id.to_id());
+        stamp(self.changed_at, id.to_id(), self.tick);
+    }
+}
+
+impl<'a, T: 'a, K: Key> Iterator for IterMut<'a, T, K> {
+    type Item = (K, &'a mut T);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        let (id, val) = self.iter.next()?;
+        self.record(id);
+        Some((id, val))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<'a, T: 'a, K: Key> DoubleEndedIterator for IterMut<'a, T, K> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let (id, val) = self.iter.next_back()?;
+        self.record(id);
+        Some((id, val))
+    }
+}
+
+impl<'a, T: 'a, K: Key> ExactSizeIterator for IterMut<'a, T, K> {
+    #[inline]
+    fn len(&self) -> usize {
+        self.iter.len()
+    }
+}
+
+impl<'a, T: 'a, K: Key> FusedIterator for IterMut<'a, T, K> {}